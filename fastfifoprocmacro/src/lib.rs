@@ -1,11 +1,12 @@
 #[cfg(test)]
 use std::fs;
 
-use itertools::izip;
+use itertools::{MultiUnzip, izip};
 use proc_macro::TokenStream;
-use quote::{format_ident, quote};
+use quote::{ToTokens, format_ident, quote, quote_spanned};
 use syn::{
-    Error, Expr, Generics, Ident, Token, Type, Visibility, braced,
+    Attribute, Error, Expr, Fields, GenericParam, Generics, Ident, ItemEnum, Meta, Path, Token,
+    Type, Visibility, braced,
     parse::{Parse, ParseStream},
     parse_macro_input, parse_quote,
     spanned::Spanned,
@@ -30,15 +31,98 @@ fn test() {
     .unwrap();
 }
 
+#[test]
+fn pipeline_matches_generate_union() {
+    let from_dsl = do_generate_union(parse_quote! {
+        /// Stages of the pipeline.
+        pub InOutUnion<Input, Output> {
+            on_uninit = hooks::uninit;
+            /// Produces inputs.
+            Producer: Input, atomic = false;
+            Transformer: Output, atomic = true, on_drop = hooks::drop_output;
+            Consumer: (), atomic = false;
+        }
+    });
+
+    let item: ItemEnum = parse_quote! {
+        /// Stages of the pipeline.
+        pub enum InOutUnion<Input, Output> {
+            /// Produces inputs.
+            Producer(Input),
+            #[atomic]
+            #[on_drop = hooks::drop_output]
+            Transformer(Output),
+            Consumer,
+        }
+    };
+    let args: PipelineArgs = parse_quote! { on_uninit = hooks::uninit };
+
+    let from_enum = do_generate_union(UnionTypeInput::from_enum(item, args).unwrap());
+
+    assert_eq!(from_dsl.to_string(), from_enum.to_string());
+}
+
+#[test]
+fn buffered_pipeline_matches_generate_union() {
+    let from_dsl = do_generate_union(parse_quote! {
+        pub Buffered<Input, Output> {
+            Producer: Input, atomic = false;
+            Transformer: Output, atomic = true, buffer;
+            Consumer: (), atomic = false;
+        }
+    });
+
+    let item: ItemEnum = parse_quote! {
+        pub enum Buffered<Input, Output> {
+            Producer(Input),
+            #[atomic]
+            #[buffer]
+            Transformer(Output),
+            Consumer,
+        }
+    };
+    let from_enum =
+        do_generate_union(UnionTypeInput::from_enum(item, PipelineArgs::default()).unwrap());
+
+    assert_eq!(from_dsl.to_string(), from_enum.to_string());
+}
+
+#[test]
+fn broadcast_pipeline_matches_generate_union() {
+    let from_dsl = do_generate_union(parse_quote! {
+        pub Observed<Input> {
+            Producer: Input, atomic = false;
+            Observer: Input, atomic = false, broadcast = true;
+            Consumer: (), atomic = false;
+        }
+    });
+
+    let item: ItemEnum = parse_quote! {
+        pub enum Observed<Input> {
+            Producer(Input),
+            #[broadcast]
+            Observer(Input),
+            Consumer,
+        }
+    };
+    let from_enum =
+        do_generate_union(UnionTypeInput::from_enum(item, PipelineArgs::default()).unwrap());
+
+    assert_eq!(from_dsl.to_string(), from_enum.to_string());
+}
+
 struct UnionTypeInput {
+    attrs: Vec<Attribute>,
     vis: Visibility,
     name: Ident,
     generics: Generics,
+    on_uninit: Option<Path>,
     variants: Vec<UnionVariant>,
 }
 
 impl Parse for UnionTypeInput {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = doc_attrs(input.call(Attribute::parse_outer)?)?;
         let vis: Visibility = input.parse()?;
         let name: Ident = input.parse()?;
         let generics: Generics = input.parse()?;
@@ -46,67 +130,515 @@ impl Parse for UnionTypeInput {
         let content;
         braced!(content in input);
 
-        let variants = content.parse_terminated(UnionVariant::parse, Token![;])?;
+        let mut on_uninit = None;
+        let mut variants = Vec::new();
+
+        while !content.is_empty() {
+            if content.peek(Ident) && content.peek2(Token![=]) {
+                let key: Ident = content.parse()?;
+
+                if key != "on_uninit" {
+                    return Err(Error::new(
+                        key.span(),
+                        format!("unknown option `{key}`, expected `on_uninit = <path>`"),
+                    ));
+                }
+                if on_uninit.is_some() {
+                    return Err(Error::new(key.span(), "duplicate `on_uninit` option"));
+                }
+
+                content.parse::<Token![=]>()?;
+                on_uninit = Some(content.parse()?);
+            } else {
+                variants.push(content.parse()?);
+            }
 
-        let variants = variants.into_iter().collect::<Vec<_>>();
+            if content.is_empty() {
+                break;
+            }
+            content.parse::<Token![;]>()?;
+        }
 
-        Ok(Self {
+        let this = Self {
+            attrs,
             vis,
             name,
             generics,
+            on_uninit,
             variants,
-        })
+        };
+
+        this.validate()?;
+
+        Ok(this)
+    }
+}
+
+/// Lowers a `#[pipeline]` enum into the same input `generate_union!` parses.
+///
+/// Unit variants become `()` stages, single field tuple variants carry their field's
+/// type and `#[atomic]` (or `#[atomic = <bool>]`) marks a stage as shared.
+/// `#[on_drop = <path>]`, `#[buffer]` and `#[broadcast]` (or `#[broadcast = <bool>]`) on a variant
+/// are the same as the stage's options.
+impl TryFrom<ItemEnum> for UnionTypeInput {
+    type Error = Error;
+
+    fn try_from(item: ItemEnum) -> syn::Result<Self> {
+        Self::from_enum(item, PipelineArgs::default())
+    }
+}
+
+/// Arguments of the `#[pipeline(..)]` attribute itself.
+#[derive(Default)]
+struct PipelineArgs {
+    on_uninit: Option<Path>,
+}
+
+impl Parse for PipelineArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = Self::default();
+
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+
+            if key != "on_uninit" {
+                return Err(Error::new(
+                    key.span(),
+                    format!("unknown argument `{key}`, expected `on_uninit = <path>`"),
+                ));
+            }
+            if args.on_uninit.is_some() {
+                return Err(Error::new(key.span(), "duplicate `on_uninit` argument"));
+            }
+
+            input.parse::<Token![=]>()?;
+            args.on_uninit = Some(input.parse()?);
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+
+        Ok(args)
+    }
+}
+
+impl UnionTypeInput {
+    fn from_enum(item: ItemEnum, PipelineArgs { on_uninit }: PipelineArgs) -> syn::Result<Self> {
+        let variants = item
+            .variants
+            .into_iter()
+            .map(|variant| {
+                if let Some((_, discriminant)) = &variant.discriminant {
+                    return Err(Error::new(
+                        discriminant.span(),
+                        "pipeline stages cannot have explicit discriminants",
+                    ));
+                }
+
+                let ty = match variant.fields {
+                    Fields::Unit => parse_quote! { () },
+                    Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                        fields.unnamed.into_iter().next().unwrap().ty
+                    }
+                    fields => {
+                        return Err(Error::new(
+                            fields.span(),
+                            "expected a unit variant or a tuple variant with exactly one field",
+                        ));
+                    }
+                };
+
+                let mut atomicity = None;
+                let mut on_drop = None;
+                let mut buffer = false;
+                let mut broadcast = None;
+                let mut attrs = Vec::with_capacity(variant.attrs.len());
+
+                for attr in variant.attrs {
+                    if attr.path().is_ident("on_drop") {
+                        if on_drop.is_some() {
+                            return Err(Error::new(
+                                attr.meta.span(),
+                                "duplicate `#[on_drop]` attribute",
+                            ));
+                        }
+
+                        on_drop = Some(match &attr.meta {
+                            Meta::NameValue(syn::MetaNameValue {
+                                value: Expr::Path(path),
+                                ..
+                            }) if path.qself.is_none() => path.path.clone(),
+                            _ => {
+                                return Err(Error::new(
+                                    attr.meta.span(),
+                                    "expected `#[on_drop = <path>]`",
+                                ));
+                            }
+                        });
+                        continue;
+                    }
+
+                    if attr.path().is_ident("buffer") {
+                        if buffer {
+                            return Err(Error::new(
+                                attr.meta.span(),
+                                "duplicate `#[buffer]` attribute",
+                            ));
+                        }
+                        if !matches!(attr.meta, Meta::Path(_)) {
+                            return Err(Error::new(attr.meta.span(), "expected `#[buffer]`"));
+                        }
+
+                        buffer = true;
+                        continue;
+                    }
+
+                    if attr.path().is_ident("broadcast") {
+                        if broadcast.is_some() {
+                            return Err(Error::new(
+                                attr.meta.span(),
+                                "duplicate `#[broadcast]` attribute",
+                            ));
+                        }
+
+                        broadcast = Some(match &attr.meta {
+                            Meta::Path(_) => true,
+                            Meta::NameValue(meta) => eval_bool(&meta.value)?,
+                            Meta::List(_) => {
+                                return Err(Error::new(
+                                    attr.meta.span(),
+                                    "expected `#[broadcast]` or `#[broadcast = <bool>]`",
+                                ));
+                            }
+                        });
+                        continue;
+                    }
+
+                    if !attr.path().is_ident("atomic") {
+                        attrs.push(attr);
+                        continue;
+                    }
+
+                    if atomicity.is_some() {
+                        return Err(Error::new(
+                            attr.meta.span(),
+                            "duplicate `#[atomic]` attribute",
+                        ));
+                    }
+
+                    atomicity = Some(match &attr.meta {
+                        Meta::Path(_) => true,
+                        Meta::NameValue(meta) => eval_bool(&meta.value)?,
+                        Meta::List(_) => {
+                            return Err(Error::new(
+                                attr.meta.span(),
+                                "expected `#[atomic]` or `#[atomic = <bool>]`",
+                            ));
+                        }
+                    });
+                }
+
+                Ok(UnionVariant {
+                    attrs: doc_attrs(attrs)?,
+                    name: variant.ident,
+                    atomicity: atomicity.unwrap_or(false),
+                    ty,
+                    on_drop,
+                    buffer,
+                    broadcast: broadcast.unwrap_or(false),
+                })
+            })
+            .collect::<syn::Result<Vec<_>>>()?;
+
+        let this = Self {
+            attrs: doc_attrs(item.attrs)?,
+            vis: item.vis,
+            name: item.ident,
+            generics: item.generics,
+            on_uninit,
+            variants,
+        };
+
+        this.validate()?;
+
+        Ok(this)
+    }
+}
+
+/// Doc comments are forwarded onto the generated items, anything else is rejected
+/// rather than being dropped or landing on an item it was not written for.
+fn doc_attrs(attrs: Vec<Attribute>) -> syn::Result<Vec<Attribute>> {
+    match attrs.iter().find(|attr| !attr.path().is_ident("doc")) {
+        Some(attr) => Err(Error::new(
+            attr.span(),
+            "only doc comments are supported on pipelines and their stages",
+        )),
+        None => Ok(attrs),
     }
 }
 
+/// Keywords (strict, reserved and edition-dependent) that a stage name must not
+/// turn into once it has been converted to `PascalCase` or `snake_case`.
+const RESERVED: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+impl UnionTypeInput {
+    /// Rejects inputs that would otherwise expand into confusing errors (or
+    /// silently conflicting items) inside the generated code.
+    fn validate(&self) -> syn::Result<()> {
+        let mut errors: Option<Error> = None;
+        let mut push = |err: Error| match &mut errors {
+            Some(errors) => errors.combine(err),
+            None => errors = Some(err),
+        };
+
+        for param in &self.generics.params {
+            match param {
+                syn::GenericParam::Type(_) => {}
+                syn::GenericParam::Lifetime(_) => push(Error::new(
+                    param.span(),
+                    "lifetime parameters are not supported by `generate_union!`, \
+                     stage types must be `'static`",
+                )),
+                syn::GenericParam::Const(_) => push(Error::new(
+                    param.span(),
+                    "const parameters are not supported by `generate_union!`, \
+                     only type parameters are allowed",
+                )),
+            }
+        }
+
+        if self.variants.len() < 2 {
+            push(Error::new(
+                self.name.span(),
+                format!(
+                    "`{}` must have at least two stages (a producer and a consumer), found {}",
+                    self.name,
+                    self.variants.len()
+                ),
+            ));
+        }
+
+        let mut seen: Vec<(&Ident, String, String)> = Vec::with_capacity(self.variants.len());
+
+        for UnionVariant { name, .. } in &self.variants {
+            let raw = name.to_string();
+
+            if raw.starts_with("r#") {
+                push(Error::new(
+                    name.span(),
+                    format!("raw identifier `{raw}` cannot be used as a stage name"),
+                ));
+                continue;
+            }
+
+            let pascal = stringcase::pascal_case(&raw);
+            let snake = stringcase::snake_case(&raw);
+
+            if let Some(reserved) = [&pascal, &snake]
+                .into_iter()
+                .find(|ident| RESERVED.contains(&ident.as_str()))
+            {
+                push(Error::new(
+                    name.span(),
+                    format!(
+                        "stage name `{raw}` is reserved: it becomes the keyword `{reserved}` \
+                         in the generated code"
+                    ),
+                ));
+                continue;
+            }
+
+            match seen.iter().find(|(_, p, s)| *p == pascal || *s == snake) {
+                Some((first, ..)) if **first == raw => push(Error::new(
+                    name.span(),
+                    format!("stage `{raw}` is defined more than once"),
+                )),
+                Some((first, ..)) => push(Error::new(
+                    name.span(),
+                    format!(
+                        "stage `{raw}` collides with stage `{first}`: both become \
+                         `{pascal}` / `{snake}` in the generated code"
+                    ),
+                )),
+                None => seen.push((name, pascal, snake)),
+            }
+        }
+
+        if let Some(first) = self.variants.first().filter(|variant| variant.broadcast) {
+            push(Error::new(
+                first.name.span(),
+                "the producer cannot be a `broadcast` stage, it does not chase another stage",
+            ));
+        }
+
+        if let Some(first) = self.variants.first().filter(|variant| variant.buffer) {
+            push(Error::new(
+                first.name.span(),
+                "the producer cannot be a `buffer` stage, it already starts the first buffer",
+            ));
+        }
+
+        if self.variants.len() > 1 {
+            let last = self.variants.last().unwrap();
+
+            if last.buffer {
+                push(Error::new(
+                    last.name.span(),
+                    "the last stage cannot be a `buffer` stage, every buffer needs at least two stages",
+                ));
+            }
+
+            if self.variants.iter().any(|variant| variant.buffer) {
+                if !is_unit(&last.ty) {
+                    push(Error::new(
+                        last.ty.span(),
+                        "the last stage of a pipeline with `buffer` stages must be `()`, \
+                         the producer cannot receive what it leaves behind",
+                    ));
+                }
+
+                if let Some(on_uninit) = &self.on_uninit {
+                    push(Error::new(
+                        on_uninit.span(),
+                        "`on_uninit` is not supported together with `buffer` stages",
+                    ));
+                }
+
+                for variant in self.variants.iter().filter(|variant| variant.broadcast) {
+                    push(Error::new(
+                        variant.name.span(),
+                        "`broadcast` stages are not supported together with `buffer` stages",
+                    ));
+                }
+            }
+        }
+
+        errors.map_or(Ok(()), Err)
+    }
+}
+
+#[derive(Clone)]
 struct UnionVariant {
+    attrs: Vec<Attribute>,
     name: Ident,
-    atomicity: Expr,
+    atomicity: bool,
     ty: Type,
+    on_drop: Option<Path>,
+    /// The stage starts a buffer of its own, see `do_generate_buffered`.
+    buffer: bool,
+    /// Every handle of the stage sees every item, see `generate_union`.
+    broadcast: bool,
 }
 
 impl Parse for UnionVariant {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = doc_attrs(input.call(Attribute::parse_outer)?)?;
         let name: Ident = input.parse()?;
 
+        if !input.peek(Token![:]) {
+            return Err(input.error(format!(
+                "expected `:` after stage name, e.g. `{name}: Type, atomic = true;`"
+            )));
+        }
         input.parse::<Token![:]>()?;
 
         let ty: Type = input.parse()?;
 
+        if !input.peek(Token![,]) {
+            return Err(input.error("expected `, atomic = <bool>` after the stage type"));
+        }
         input.parse::<Token![,]>()?;
 
         let atomicity = parse_atomic_expr(input)?;
 
+        let mut on_drop = None;
+        let mut buffer = false;
+        let mut broadcast = None;
+
+        while input.peek(Token![,]) {
+            input.parse::<Token![,]>()?;
+
+            let key: Ident = input.parse()?;
+            if key == "on_drop" {
+                if on_drop.is_some() {
+                    return Err(Error::new(key.span(), "duplicate `on_drop` option"));
+                }
+
+                input.parse::<Token![=]>()?;
+                on_drop = Some(input.parse()?);
+            } else if key == "buffer" {
+                if buffer {
+                    return Err(Error::new(key.span(), "duplicate `buffer` option"));
+                }
+
+                buffer = true;
+            } else if key == "broadcast" {
+                if broadcast.is_some() {
+                    return Err(Error::new(key.span(), "duplicate `broadcast` option"));
+                }
+
+                input.parse::<Token![=]>()?;
+                broadcast = Some(eval_bool(&input.parse()?)?);
+            } else {
+                return Err(Error::new(
+                    key.span(),
+                    format!(
+                        "unknown option `{key}`, expected `on_drop = <path>`, `buffer` \
+                         or `broadcast = <bool>`"
+                    ),
+                ));
+            }
+        }
+
         Ok(UnionVariant {
+            attrs,
             name,
             ty,
             atomicity,
+            on_drop,
+            buffer,
+            broadcast: broadcast.unwrap_or(false),
         })
     }
 }
 
-fn parse_atomic_expr(input: ParseStream) -> syn::Result<Expr> {
+fn parse_atomic_expr(input: ParseStream) -> syn::Result<bool> {
     let name: Ident = input.parse()?;
 
     if name != "atomic" {
-        return Err(Error::new(name.span(), "expected `atomic`"));
+        return Err(Error::new(
+            name.span(),
+            format!("expected `atomic = <bool>`, found `{name}`"),
+        ));
     }
 
+    if !input.peek(Token![=]) {
+        return Err(input.error("expected `=` followed by a boolean after `atomic`"));
+    }
     input.parse::<Token![=]>()?;
 
     let expr: Expr = input.parse()?;
 
-    expect_bool(&expr)?;
-
-    Ok(expr)
+    eval_bool(&expr)
 }
 
 #[derive(Clone)]
 struct FullUnionVariant {
+    attrs: Vec<Attribute>,
     variant_name: Ident,
     field_name: Ident,
-    atomicity: Expr,
+    atomicity: bool,
     ty: Type,
+    on_drop: Option<Path>,
+    broadcast: bool,
     chases: usize,
 }
 
@@ -119,11 +651,16 @@ fn uv_to_fuv(uv: Vec<UnionVariant>) -> Vec<FullUnionVariant> {
             |(
                 i,
                 UnionVariant {
+                    attrs,
                     name,
                     atomicity,
                     ty,
+                    on_drop,
+                    buffer: _,
+                    broadcast,
                 },
             )| FullUnionVariant {
+                attrs,
                 variant_name: Ident::new(
                     stringcase::pascal_case(name.to_string().as_str()).as_str(),
                     name.span(),
@@ -134,36 +671,40 @@ fn uv_to_fuv(uv: Vec<UnionVariant>) -> Vec<FullUnionVariant> {
                 ),
                 atomicity,
                 ty,
+                on_drop,
+                broadcast,
                 chases: (i + n - 1) % n,
             },
         )
         .collect()
 }
 
-fn expect_bool(expr: &Expr) -> syn::Result<()> {
+/// Atomicity decides which impls get generated, so it has to be known to the macro.
+fn eval_bool(expr: &Expr) -> syn::Result<bool> {
     match expr {
         Expr::Lit(syn::ExprLit {
-            lit: syn::Lit::Bool(_),
+            lit: syn::Lit::Bool(lit),
             ..
-        }) => Ok(()),
-        Expr::Binary(bin)
-            if matches!(
-                bin.op,
-                syn::BinOp::Eq(_)
-                    | syn::BinOp::Ne(_)
-                    | syn::BinOp::Lt(_)
-                    | syn::BinOp::Le(_)
-                    | syn::BinOp::Gt(_)
-                    | syn::BinOp::Ge(_)
-                    | syn::BinOp::And(_)
-                    | syn::BinOp::Or(_)
-            ) =>
-        {
-            expect_bool(&*bin.left)?;
-            expect_bool(&*bin.right)?;
-            Ok(())
+        }) => Ok(lit.value),
+        Expr::Binary(bin) => {
+            let (left, right) = (eval_bool(&bin.left)?, eval_bool(&bin.right)?);
+
+            match bin.op {
+                syn::BinOp::Eq(_) => Ok(left == right),
+                syn::BinOp::Ne(_) => Ok(left != right),
+                syn::BinOp::Lt(_) => Ok(!left & right),
+                syn::BinOp::Le(_) => Ok(left <= right),
+                syn::BinOp::Gt(_) => Ok(left & !right),
+                syn::BinOp::Ge(_) => Ok(left >= right),
+                syn::BinOp::And(_) => Ok(left && right),
+                syn::BinOp::Or(_) => Ok(left || right),
+                _ => Err(syn::Error::new(
+                    expr.span(),
+                    "expected a boolean expression",
+                )),
+            }
         }
-        Expr::Unary(u) if matches!(u.op, syn::UnOp::Not(_)) => expect_bool(&*u.expr),
+        Expr::Unary(u) if matches!(u.op, syn::UnOp::Not(_)) => Ok(!eval_bool(&u.expr)?),
         _ => Err(syn::Error::new(
             expr.span(),
             "expected a boolean expression",
@@ -171,34 +712,53 @@ fn expect_bool(expr: &Expr) -> syn::Result<()> {
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn unroll_variants(
     variants: Vec<FullUnionVariant>,
-) -> (Vec<Ident>, Vec<Ident>, Vec<Expr>, Vec<Type>, Vec<usize>) {
+) -> (
+    Vec<Vec<Attribute>>,
+    Vec<Ident>,
+    Vec<Ident>,
+    Vec<bool>,
+    Vec<Type>,
+    Vec<Option<Path>>,
+    Vec<bool>,
+    Vec<usize>,
+) {
+    let mut vec0 = Vec::with_capacity(variants.len());
     let mut vec1 = Vec::with_capacity(variants.len());
     let mut vec2 = Vec::with_capacity(variants.len());
     let mut vec3 = Vec::with_capacity(variants.len());
     let mut vec4 = Vec::with_capacity(variants.len());
     let mut vec5 = Vec::with_capacity(variants.len());
+    let mut vec6 = Vec::with_capacity(variants.len());
+    let mut vec7 = Vec::with_capacity(variants.len());
 
     for FullUnionVariant {
+        attrs,
         variant_name,
         field_name,
         atomicity,
         ty,
+        on_drop,
+        broadcast,
         chases,
     } in variants
     {
+        vec0.push(attrs);
         vec1.push(variant_name);
         vec2.push(field_name);
         vec3.push(atomicity);
         vec4.push(ty);
-        vec5.push(chases);
+        vec5.push(on_drop);
+        vec6.push(broadcast);
+        vec7.push(chases);
     }
 
-    (vec1, vec2, vec3, vec4, vec5)
+    (vec0, vec1, vec2, vec3, vec4, vec5, vec6, vec7)
 }
 
-fn get_chases<T: Clone>(chases: &Vec<usize>, original: &Vec<T>) -> Vec<T> {
+fn get_chases<T: Clone>(chases: &[usize], original: &[T]) -> Vec<T> {
     chases
         .iter()
         .map(|&i| original[i].clone())
@@ -212,19 +772,21 @@ fn is_unit(ty: &Type) -> bool {
     }
 }
 
-pub(crate) fn do_generate_union(
-    UnionTypeInput {
+pub(crate) fn do_generate_union(input: UnionTypeInput) -> proc_macro2::TokenStream {
+    if input.variants.iter().any(|variant| variant.buffer) {
+        return do_generate_buffered(input);
+    }
+
+    let UnionTypeInput {
+        attrs,
         vis,
         name,
         generics,
+        on_uninit,
         variants,
-    }: UnionTypeInput,
-) -> proc_macro2::TokenStream {
-    let num_variants = variants.len();
+    } = input;
 
-    if num_variants < 2 {
-        return quote! { compile_error!("Must have at least two layers!") };
-    }
+    let num_variants = variants.len();
 
     let lib_path = quote! { ::fastfifo };
     let fifo_path = quote! { #lib_path ::fifo };
@@ -242,15 +804,28 @@ pub(crate) fn do_generate_union(
         .iter()
         .enumerate()
         .map(|(i, variant)| {
-            let variant_name = variant.variant_name.clone();
-            quote! { #variant_name = #i }
+            let FullUnionVariant {
+                attrs,
+                variant_name,
+                ..
+            } = variant;
+            quote! { #( #attrs )* #variant_name = #i }
         })
         .collect::<Vec<_>>();
 
     let default_ty = &variants.last().unwrap().ty.clone();
     let default_field = &variants.last().unwrap().field_name.clone();
 
-    let (variant_names, field_names, atomicities, types, chases) = unroll_variants(variants);
+    let (
+        variant_attrs,
+        variant_names,
+        field_names,
+        atomicities,
+        types,
+        on_drops,
+        broadcasts,
+        chases,
+    ) = unroll_variants(variants);
 
     let producer_variant = variant_names.first().unwrap();
 
@@ -261,6 +836,8 @@ pub(crate) fn do_generate_union(
     let tag_name = format_ident!("{}Tag", name);
     let fifo_name = format_ident!("{}Fifo", name);
     let try_from_error_name = format_ident!("{}TryFromError", tag_name);
+    let atom_pairs_name = format_ident!("{}AtomPairs", name);
+    let heads_name = format_ident!("{}Heads", name);
 
     let (variant_fifos, variant_entries) = variant_names
         .iter()
@@ -295,9 +872,11 @@ pub(crate) fn do_generate_union(
 
     let (lifetime_impl_generic, lifetime_ty_generic, _) = lifetime_generics.split_for_impl();
 
-    let transform_f_trait = izip!(&chases_types, &types)
-        .map(|(chases_type, ty)| {
-            if is_unit(ty) && is_unit(chases_type) {
+    let transform_f_trait = izip!(&chases_types, &types, &broadcasts)
+        .map(|(chases_type, ty, &broadcast)| {
+            if broadcast {
+                quote! {::std::ops::FnOnce(&#ty)}
+            } else if is_unit(ty) && is_unit(chases_type) {
                 quote! {::std::ops::FnOnce()}
             } else if is_unit(ty) {
                 quote! {::std::ops::FnOnce(#chases_type)}
@@ -322,10 +901,22 @@ pub(crate) fn do_generate_union(
         &types,
         &field_names,
         &chases_field_names,
-        &transform_f_trait
+        &transform_f_trait,
+        &broadcasts
     )
-    .map(|(variant_entry, chases_type, ty, field_name, chases_field_name, transform_trait)| {
-        if is_unit(ty) && is_unit(chases_type) {
+    .map(|(variant_entry, chases_type, ty, field_name, chases_field_name, transform_trait, &broadcast)| {
+        if broadcast {
+            // Handles share the item, it stays where it is for the stage chasing this one. Every field starts at
+            // the same offset and `broadcast_type_checks` makes sure this stage's type is the chased one.
+            quote! {
+                impl #lifetime_impl_generic #variant_entry #lifetime_ty_generic #where_clause {
+                    #[allow(dead_code)]
+                    pub fn transform<F: #transform_trait>(&mut self, transformer: F) {
+                        self.0.modify_t_in_place(|ptr| transformer(unsafe { &*(*ptr).#field_name }))
+                    }
+                }
+            }
+        } else if is_unit(ty) && is_unit(chases_type) {
             quote! {
                 impl #lifetime_impl_generic #variant_entry #lifetime_ty_generic #where_clause {
                     #[allow(dead_code)]
@@ -376,9 +967,193 @@ pub(crate) fn do_generate_union(
         }
     }).collect::<Vec<_>>();
 
+    // Only stages marked `atomic = true` may be shared, a single threaded stage is neither `Clone` nor `Sync`.
+    let variant_sync_markers = atomicities
+        .iter()
+        .map(|&atomic| {
+            if atomic {
+                quote! { ::core::marker::PhantomData<()> }
+            } else {
+                quote! { ::core::marker::PhantomData<::core::cell::Cell<()>> }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // A `broadcast` stage fifo also carries the handle it takes entries for.
+    let variant_handle_fields = broadcasts
+        .iter()
+        .map(|&broadcast| broadcast.then(|| quote! { , usize }))
+        .collect::<Vec<_>>();
+
+    let variant_clone_impls = izip!(&variant_fifos, &atomicities, &broadcasts)
+        .map(|(variant_fifo, &atomic, &broadcast)| {
+            let handle = broadcast.then(|| quote! { , self.2 });
+
+            if atomic {
+                quote! {
+                    impl #alloc_impl_generic #fifo_config_path ::TaggedClone<#tag_name> for #variant_fifo #alloc_ty_generic #where_clause {
+                        fn unchecked_clone(&self) -> Self {
+                            Self(self.0.unchecked_clone(), ::core::marker::PhantomData #handle)
+                        }
+                    }
+
+                    impl #alloc_impl_generic Clone for #variant_fifo #alloc_ty_generic #where_clause {
+                        fn clone(&self) -> Self {
+                            <Self as #fifo_config_path ::TaggedClone<#tag_name>>::unchecked_clone(self)
+                        }
+                    }
+                }
+            } else {
+                quote! {}
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Stranded items are handed to the stage's `on_drop` hook instead of being dropped in place.
+    let tagged_drops = izip!(&field_names, &types, &on_drops)
+        .map(|(field_name, ty, on_drop)| match on_drop {
+            Some(on_drop) => quote_spanned! {on_drop.span()=>
+                {
+                    let on_drop: fn(#ty) = #on_drop;
+                    on_drop(unsafe { #manually_drop ::take(&mut self.#field_name) })
+                }
+            },
+            None => quote! {
+                unsafe { #manually_drop ::drop(&mut self.#field_name) }
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let variant_get_entries = izip!(&variant_names, &variant_entries, &broadcasts)
+        .map(|(variant_name, variant_entry, &broadcast)| {
+            if broadcast {
+                quote! {
+                    self.0.get_handle_entry(#tag_name :: #variant_name, self.2).map(#variant_entry ::from)
+                }
+            } else {
+                quote! {
+                    self.0.get_entry(#tag_name :: #variant_name).map(#variant_entry ::from)
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let variant_handle_fns = broadcasts
+        .iter()
+        .map(|&broadcast| {
+            broadcast.then(|| {
+                quote! {
+                    /// Which of the stage's handles this is.
+                    #[allow(dead_code)]
+                    pub fn handle(&self) -> usize {
+                        self.2
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let (split_types, split_exprs) = izip!(&variant_fifos, &broadcasts)
+        .map(|(variant_fifo, &broadcast)| {
+            let clone = quote! {
+                <Self as #fifo_config_path ::TaggedClone<#tag_name>>::unchecked_clone(&self)
+            };
+
+            if broadcast {
+                (
+                    quote! { ::std::vec::Vec<#variant_fifo #alloc_ty_generic> },
+                    quote! {
+                        (0..self.0.handles())
+                            .map(|handle| #variant_fifo (#clone, ::core::marker::PhantomData, handle))
+                            .collect()
+                    },
+                )
+            } else {
+                (
+                    quote! { #variant_fifo #alloc_ty_generic },
+                    quote! { #variant_fifo (#clone, ::core::marker::PhantomData) },
+                )
+            }
+        })
+        .collect::<(Vec<_>, Vec<_>)>();
+
+    // Handles read the item the chased stage left behind through their own field, so both have to be the same type.
+    let broadcast_type_checks = izip!(&field_names, &types, &chases_types, &broadcasts)
+        .filter(|(.., broadcast)| **broadcast)
+        .map(|(field_name, ty, chases_type, _)| {
+            let check = format_ident!("__{}_is_broadcast", field_name);
+
+            quote_spanned! {ty.span()=>
+                impl #impl_generic #name #ty_generic #where_clause {
+                    #[allow(dead_code)]
+                    fn #check(item: #chases_type) -> #ty {
+                        item
+                    }
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // A `broadcast` stage keeps a pair and a head per handle, no matter its atomicity.
+    let select_atomicity = atomicities
+        .iter()
+        .map(|atomic| {
+            quote! { <#fifo_config_path ::Atomicity<#atomic> as #fifo_config_path ::SelectAtomicity> }
+        })
+        .collect::<Vec<_>>();
+    let (pair_types, new_pairs, handle_pairs): (Vec<_>, Vec<_>, Vec<_>) =
+        izip!(&select_atomicity, &field_names, &broadcasts)
+            .map(|(select, field_name, &broadcast)| {
+                if broadcast {
+                    (
+                        quote! { #fifo_config_path ::BroadcastPairs },
+                        quote! { #fifo_config_path ::BroadcastPairs::new(block_size, handles) },
+                        quote! { self.#field_name.handle(handle) },
+                    )
+                } else {
+                    (
+                        quote! { #select ::Pair },
+                        quote! { #select ::new_pair(block_size) },
+                        quote! { (&self.#field_name).into() },
+                    )
+                }
+            })
+            .multiunzip();
+    let (head_types, new_heads) = izip!(&select_atomicity, &broadcasts)
+        .map(|(select, &broadcast)| {
+            if broadcast {
+                (
+                    quote! { #fifo_config_path ::BroadcastHeads },
+                    quote! { #fifo_config_path ::BroadcastHeads::new(num_blocks, handles) },
+                )
+            } else {
+                (
+                    quote! { #select ::Head },
+                    quote! { #select ::new_head(num_blocks) },
+                )
+            }
+        })
+        .collect::<(Vec<_>, Vec<_>)>();
+
+    let indexed_drop = on_uninit.map(|on_uninit| {
+        let on_uninit_fn = quote_spanned! {on_uninit.span()=>
+            { let on_uninit: fn(*mut Self) = #on_uninit; on_uninit }
+        };
+
+        quote! {
+            unsafe fn indexed_drop(&mut self, index: usize) {
+                match <#tag_name as ::core::convert::TryFrom<usize>>::try_from(index) {
+                    Ok(tag) => unsafe { <Self as #fifo_config_path ::IndexedDrop<#tag_name>>::tagged_drop(self, tag) },
+                    Err(_) => (#on_uninit_fn)(self as *mut Self),
+                }
+            }
+        }
+    });
+
     quote! {
+        #( #attrs )*
         #vis union #name #impl_generic #where_clause {
-            #( #field_names : #manually_drop <#types> ,)*
+            #( #( #variant_attrs )* #field_names : #manually_drop <#types> ,)*
         }
 
         impl #impl_generic ::std::default::Default for #name #ty_generic #where_clause {
@@ -447,16 +1222,64 @@ pub(crate) fn do_generate_union(
             }
         }
 
+        #vis struct #atom_pairs_name {
+            #( #field_names : #pair_types ,)*
+        }
+
+        impl #fifo_config_path ::TaggedAtomPairs<#tag_name> for #atom_pairs_name {
+            #[allow(unused_variables)]
+            fn new(block_size: usize, handles: usize) -> Self {
+                Self {
+                    #( #field_names : #new_pairs ,)*
+                }
+            }
+
+            fn get(&self, tag: #tag_name) -> #fifo_config_path ::AtomPairRef<'_> {
+                match tag {
+                    #( #tag_name :: #variant_names => (&self.#field_names).into() ,)*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn get_handle(&self, tag: #tag_name, handle: usize) -> #fifo_config_path ::AtomPairRef<'_> {
+                match tag {
+                    #( #tag_name :: #variant_names => #handle_pairs ,)*
+                }
+            }
+        }
+
+        #vis struct #heads_name {
+            #( #field_names : #head_types ,)*
+        }
+
+        impl #fifo_config_path ::TaggedHeads<#tag_name> for #heads_name {
+            #[allow(unused_variables)]
+            fn new(num_blocks: usize, handles: usize) -> Self {
+                Self {
+                    #( #field_names : #new_heads ,)*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn get(&self, tag: #tag_name, handle: usize) -> #fifo_config_path ::HeadRef<'_> {
+                match tag {
+                    #( #tag_name :: #variant_names => #handle_pairs ,)*
+                }
+            }
+        }
+
         impl #impl_generic #fifo_config_path ::IndexedDrop<#tag_name> for #name #ty_generic #where_clause {
             unsafe fn tagged_drop(&mut self, tag: #tag_name) {
                 match tag {
-                    #( #tag_name :: #variant_names => unsafe { #manually_drop ::drop(&mut self.#field_names) } ,)*
+                    #( #tag_name :: #variant_names => #tagged_drops ,)*
                 }
             }
+
+            #indexed_drop
         }
 
         #vis struct #fifo_name #default_alloc_generics (
-            #fifo_path ::FastFifo<#tag_name, #name #ty_generic>//, A>,
+            #fifo_path ::FastFifo<#tag_name, #name #ty_generic, #atom_pairs_name, #heads_name>//, A>,
         ) #where_clause;
 
         impl #alloc_impl_generic #fifo_config_path ::TaggedClone<#tag_name> for #fifo_name #alloc_ty_generic #where_clause
@@ -471,8 +1294,18 @@ pub(crate) fn do_generate_union(
             pub fn new(num_blocks: usize, block_size: usize) -> Self {
                 Self(#fifo_path ::FastFifo::new(num_blocks, block_size))
             }
+
+            /// Every `broadcast` stage is split into `handles` stage fifos, each of them sees every item.
+            ///
+            /// Panics if `handles` is 0.
+            #[allow(dead_code)]
+            pub fn with_handles(num_blocks: usize, block_size: usize, handles: usize) -> Self {
+                Self(#fifo_path ::FastFifo::with_handles(num_blocks, block_size, handles))
+            }
         }
 
+        #( #broadcast_type_checks )*
+
         impl #alloc_impl_generic #fifo_name #alloc_ty_generic #where_clause {
             // #[allow(dead_code)]
             // pub fn new_in(num_blocks: usize, block_size: usize, alloc: A) -> Self {
@@ -480,76 +1313,530 @@ pub(crate) fn do_generate_union(
             // }
 
             #[allow(dead_code)]
-            pub fn get_entry(&self, tag: #tag_name) -> #result <#entry_descriptor <'_, #tag_name, #name #ty_generic>>{//, A>> {
+            pub fn get_entry(&self, tag: #tag_name) -> #result <#entry_descriptor <'_, #tag_name, #name #ty_generic, #atom_pairs_name>>{//, A>> {
                 self.0.get_entry(tag)
             }
 
+            /// Panics if `handle` is not below `handles()`.
+            #[allow(dead_code)]
+            pub fn get_handle_entry(&self, tag: #tag_name, handle: usize) -> #result <#entry_descriptor <'_, #tag_name, #name #ty_generic, #atom_pairs_name>> {
+                self.0.get_handle_entry(tag, handle)
+            }
+
+            #[allow(dead_code)]
+            pub fn handles(&self) -> usize {
+                self.0.handles()
+            }
+
+            /// Lets `shutdown` race the producer stage, see the fifo's `concurrent_shutdown`.
+            #[allow(dead_code)]
+            pub fn concurrent_shutdown(self) -> Self {
+                Self(self.0.concurrent_shutdown())
+            }
+
+            /// Stops the producer stage, the other stages drain what is left and then get `Error::Closed`.
+            ///
+            /// Must come after the producer stage's last `get_entry` unless built with `concurrent_shutdown`.
+            #[allow(dead_code)]
+            pub fn shutdown(&self) {
+                self.0.shutdown()
+            }
+
+            #[allow(dead_code)]
+            pub fn is_closed(&self) -> bool {
+                self.0.is_closed()
+            }
+
+            #[allow(dead_code)]
+            pub fn stage_stats(&self) -> ::std::vec::Vec<#fifo_path ::StageStats<#tag_name>> {
+                self.0.stage_stats()
+            }
+
+            #lib_path ::__if_metrics! {
+                /// Publishes `stage_stats` into the `metrics` facade under `name`.
+                #[allow(dead_code)]
+                pub fn export_metrics(&self, name: &str) {
+                    self.0.export_metrics(name)
+                }
+            }
+
+            #lib_path ::__if_not_loom! {
+                /// Every entry holding the output of a stage with that stage's tag, `None` while the fifo is shared.
+                #[allow(dead_code)]
+                pub fn slots(&mut self) -> ::std::option::Option<::std::vec::Vec<#fifo_path ::Slot<'_, #tag_name, #name #ty_generic>>> {
+                    self.0.slots()
+                }
+            }
+
+            /// One stage fifo per stage, a `broadcast` stage is split into one per handle.
             #[allow(dead_code)]
             pub fn split(self) -> (
-                #( #variant_fifos #alloc_ty_generic ,)*
+                #( #split_types ,)*
             ) {
                 (
-                    #( #variant_fifos ( <Self as #fifo_config_path ::TaggedClone<#tag_name>>::unchecked_clone(&self)) ,)*
+                    #( #split_exprs ,)*
                 )
             }
         }
 
         #(
             #vis struct #variant_entries #lifetime_impl_generic (
-                #entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic>//, A>
+                #entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic, #atom_pairs_name>//, A>
             ) #where_clause;
 
-            impl #lifetime_impl_generic From<#entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic>>//, A>>
+            impl #lifetime_impl_generic From<#entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic, #atom_pairs_name>>//, A>>
                 for #variant_entries #lifetime_ty_generic #where_clause
             {
-                fn from(value: #entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic>) -> Self {//, A>) -> Self {
+                fn from(value: #entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic, #atom_pairs_name>) -> Self {//, A>) -> Self {
                     Self(value)
                 }
             }
 
-            impl #lifetime_impl_generic Into<#entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic>>//, A>>
+            impl #lifetime_impl_generic Into<#entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic, #atom_pairs_name>>//, A>>
                 for #variant_entries #lifetime_ty_generic #where_clause
             {
-                fn into(self) -> #entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic>{//, A> {
+                fn into(self) -> #entry_descriptor <'entry_descriptor_lifetime, #tag_name, #name #ty_generic, #atom_pairs_name>{//, A> {
                     self.0
                 }
             }
 
             #variant_impls
 
+            #( #variant_attrs )*
             #vis struct #variant_fifos #alloc_impl_generic (
-                #fifo_name #alloc_ty_generic
+                #fifo_name #alloc_ty_generic,
+                #variant_sync_markers
+                #variant_handle_fields
             ) #where_clause;
 
-            impl #alloc_impl_generic #fifo_config_path ::TaggedClone<#tag_name> for #variant_fifos #alloc_ty_generic #where_clause {
-                fn unchecked_clone(&self) -> Self {
-                    Self(self.0.unchecked_clone())
-                }
-            }
-
-            impl #alloc_impl_generic Clone for #variant_fifos #alloc_ty_generic #where_clause {
-                fn clone(&self) -> Self {
-                    <Self as #fifo_config_path ::TaggedClone<#tag_name>>::tagged_clone(&self, #tag_name :: #variant_names)
-                        .expect("this variant was marked with `atomic = false` and cannot be cloned")
-                }
-            }
+            #variant_clone_impls
 
             impl #alloc_impl_generic #variant_fifos #alloc_ty_generic #where_clause {
                 #[allow(dead_code)]
                 pub fn get_entry<'entry_descriptor_lifetime>(&'entry_descriptor_lifetime self) -> #result <#variant_entries #lifetime_ty_generic> {
-                    self.0.get_entry(#tag_name :: #variant_names).map(#variant_entries ::from)
+                    #variant_get_entries
                 }
 
+                #variant_handle_fns
+
                 #[allow(dead_code)]
                 pub fn transform<F: #transform_f_trait>(&self, transformer: F) -> #result <()> {
                     self.get_entry().map(|mut entry| entry.transform(transformer))
                 }
+
+                /// Shuts down the whole pipeline, see the fifo's `shutdown`.
+                #[allow(dead_code)]
+                pub fn shutdown(&self) {
+                    self.0.shutdown()
+                }
+
+                #[allow(dead_code)]
+                pub fn is_closed(&self) -> bool {
+                    self.0.is_closed()
+                }
+
+                #[allow(dead_code)]
+                pub fn stage_stats(&self) -> ::std::vec::Vec<#fifo_path ::StageStats<#tag_name>> {
+                    self.0.stage_stats()
+                }
+
+                #lib_path ::__if_metrics! {
+                    /// Publishes the whole pipeline's `stage_stats` into the `metrics` facade under `name`.
+                    #[allow(dead_code)]
+                    pub fn export_metrics(&self, name: &str) {
+                        self.0.export_metrics(name)
+                    }
+                }
+
+                #lib_path ::__if_not_loom! {
+                    /// The whole pipeline's `slots`, `None` until every other stage fifo is dropped.
+                    #[allow(dead_code)]
+                    pub fn slots(&mut self) -> ::std::option::Option<::std::vec::Vec<#fifo_path ::Slot<'_, #tag_name, #name #ty_generic>>> {
+                        self.0.slots()
+                    }
+                }
             }
         )*
     }
 }
 
+/// Whether `ident` shows up anywhere in `tokens`.
+fn mentions(tokens: proc_macro2::TokenStream, ident: &Ident) -> bool {
+    tokens.into_iter().any(|tree| match tree {
+        proc_macro2::TokenTree::Ident(other) => other == *ident,
+        proc_macro2::TokenTree::Group(group) => mentions(group.stream(), ident),
+        _ => false,
+    })
+}
+
+/// The type parameters of `generics` that `types` use, along with the where clauses on them.
+/// Unions and structs must use all of their parameters, so every buffer only gets its own.
+fn generics_used_by<'a>(
+    generics: &Generics,
+    types: impl IntoIterator<Item = &'a Type>,
+) -> Generics {
+    let types = types
+        .into_iter()
+        .map(|ty| ty.to_token_stream())
+        .collect::<Vec<_>>();
+
+    let unused = generics
+        .type_params()
+        .map(|param| &param.ident)
+        .filter(|ident| !types.iter().any(|ty| mentions(ty.clone(), ident)))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut used = generics.clone();
+    used.params = used
+        .params
+        .into_iter()
+        .filter(
+            |param| !matches!(param, GenericParam::Type(param) if unused.contains(&param.ident)),
+        )
+        .collect();
+
+    if let Some(where_clause) = &mut used.where_clause {
+        where_clause.predicates = where_clause
+            .predicates
+            .clone()
+            .into_iter()
+            .filter(|predicate| {
+                !unused
+                    .iter()
+                    .any(|ident| mentions(predicate.to_token_stream(), ident))
+            })
+            .collect();
+    }
+
+    used
+}
+
+/// Lowers a pipeline with `buffer` stages into one pipeline per buffer.
+///
+/// Buffer `k` is generated as the pipeline `{Name}Buffer{k}`, holding its own stages followed by the stage
+/// starting the next buffer as a `()` consumer. That stage is also the producer of its own buffer, and its
+/// `{Name}{Stage}Fifo` moves items across using a shared `fastfifo::boundary::Boundary`. Every other stage is
+/// handed out as its buffer's stage fifo.
+fn do_generate_buffered(
+    UnionTypeInput {
+        attrs,
+        vis,
+        name,
+        generics,
+        on_uninit: _,
+        variants,
+    }: UnionTypeInput,
+) -> proc_macro2::TokenStream {
+    let lib_path = quote! { ::fastfifo };
+    let result = quote! { #lib_path ::Result };
+
+    let fifo_name = format_ident!("{}Fifo", name);
+    let pascal = |ident: &Ident| {
+        Ident::new(
+            stringcase::pascal_case(ident.to_string().as_str()).as_str(),
+            ident.span(),
+        )
+    };
+
+    let starts = variants
+        .iter()
+        .enumerate()
+        .filter(|(i, variant)| *i == 0 || variant.buffer)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let num_buffers = starts.len();
+    let ends = starts
+        .iter()
+        .skip(1)
+        .copied()
+        .chain([variants.len()])
+        .collect::<Vec<_>>();
+
+    let buffers = izip!(&starts, &ends)
+        .enumerate()
+        .map(|(k, (&start, &end))| {
+            let mut stages = variants[start..end]
+                .iter()
+                .cloned()
+                .map(|variant| UnionVariant {
+                    buffer: false,
+                    ..variant
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(next) = variants.get(end) {
+                stages.push(UnionVariant {
+                    attrs: Vec::new(),
+                    name: next.name.clone(),
+                    atomicity: next.atomicity,
+                    ty: parse_quote! { () },
+                    on_drop: None,
+                    buffer: false,
+                    broadcast: false,
+                });
+            }
+
+            UnionTypeInput {
+                attrs: Vec::new(),
+                vis: vis.clone(),
+                name: format_ident!("{}Buffer{}", name, k),
+                generics: generics_used_by(&generics, stages.iter().map(|stage| &stage.ty)),
+                on_uninit: None,
+                variants: stages,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let buffer_fifos = buffers
+        .iter()
+        .map(|buffer| format_ident!("{}Fifo", buffer.name))
+        .collect::<Vec<_>>();
+    let buffer_ty_generics = buffers
+        .iter()
+        .map(|buffer| {
+            let (_, ty_generic, _) = buffer.generics.split_for_impl();
+            quote! { #ty_generic }
+        })
+        .collect::<Vec<_>>();
+    let buffer_indices = (0..num_buffers).map(syn::Index::from).collect::<Vec<_>>();
+
+    // The stage fifos every buffer splits into, the boundary's `()` consumer included.
+    let buffer_stage_vars = buffers
+        .iter()
+        .enumerate()
+        .map(|(k, buffer)| {
+            (0..buffer.variants.len())
+                .map(|i| format_ident!("buffer{}_{}", k, i))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut stage_types = Vec::with_capacity(variants.len());
+    let mut stage_exprs = Vec::with_capacity(variants.len());
+    let mut boundaries = Vec::new();
+
+    for (k, (buffer, &start)) in izip!(&buffers, &starts).enumerate() {
+        let buffer_ty_generic = &buffer_ty_generics[k];
+
+        for (i, stage) in buffer.variants.iter().enumerate().take(ends[k] - start) {
+            let stage_fifo = format_ident!("{}{}Fifo", buffer.name, pascal(&stage.name));
+            let var = &buffer_stage_vars[k][i];
+
+            if k == 0 || i != 0 {
+                stage_types.push(quote! { #stage_fifo #buffer_ty_generic });
+                stage_exprs.push(quote! { #var });
+                continue;
+            }
+
+            // Starts buffer `k`, takes from the `()` consumer closing buffer `k - 1`.
+            let upstream = &buffers[k - 1];
+            let upstream_fifo = format_ident!("{}{}Fifo", upstream.name, pascal(&stage.name));
+            let upstream_ty_generic = &buffer_ty_generics[k - 1];
+            let upstream_var = buffer_stage_vars[k - 1].last().unwrap();
+
+            let adapter = format_ident!("{}{}Fifo", name, pascal(&stage.name));
+            let adapter_generics = generics_used_by(
+                &generics,
+                upstream
+                    .variants
+                    .iter()
+                    .chain(&buffer.variants)
+                    .map(|stage| &stage.ty),
+            );
+            let (adapter_impl_generic, adapter_ty_generic, adapter_where_clause) =
+                adapter_generics.split_for_impl();
+
+            stage_types.push(quote! { #adapter #adapter_ty_generic });
+            stage_exprs.push(quote! {
+                #adapter(#upstream_var, #var, ::std::sync::Arc::default())
+            });
+
+            let attrs = &stage.attrs;
+            let ty = &stage.ty;
+            let input_ty = &variants[start - 1].ty;
+
+            let (transform_trait, upstream_transform) = match (is_unit(input_ty), is_unit(ty)) {
+                (true, true) => (
+                    quote! { ::std::ops::FnOnce() },
+                    quote! { || place(transformer()) },
+                ),
+                (true, false) => (
+                    quote! { ::std::ops::FnOnce() -> #ty },
+                    quote! { || place(transformer()) },
+                ),
+                (false, true) => (
+                    quote! { ::std::ops::FnOnce(#input_ty) },
+                    quote! { |input| place(transformer(input)) },
+                ),
+                (false, false) => (
+                    quote! { ::std::ops::FnOnce(#input_ty) -> #ty },
+                    quote! { |input| place(transformer(input)) },
+                ),
+            };
+
+            let clone_impl = stage.atomicity.then(|| {
+                quote! {
+                    impl #adapter_impl_generic Clone for #adapter #adapter_ty_generic #adapter_where_clause {
+                        fn clone(&self) -> Self {
+                            Self(self.0.clone(), self.1.clone(), self.2.clone())
+                        }
+                    }
+                }
+            });
+
+            boundaries.push(quote! {
+                #( #attrs )*
+                #vis struct #adapter #adapter_impl_generic (
+                    #upstream_fifo #upstream_ty_generic,
+                    #stage_fifo #buffer_ty_generic,
+                    ::std::sync::Arc<#lib_path ::boundary::Boundary<#ty>>,
+                ) #adapter_where_clause;
+
+                #clone_impl
+
+                impl #adapter_impl_generic #adapter #adapter_ty_generic #adapter_where_clause {
+                    /// Takes an item from the buffer before this stage and puts the result into this stage's
+                    /// buffer. Once the buffer before is drained this shuts down this stage's buffer.
+                    #[allow(dead_code)]
+                    pub fn transform<F: #transform_trait>(&self, transformer: F) -> #result <()> {
+                        self.2.transform(
+                            |place| self.0.get_entry().map(|mut entry| entry.transform(#upstream_transform)),
+                            |item| match self.1.get_entry() {
+                                Ok(mut entry) => {
+                                    entry.transform(|| item);
+                                    Ok(())
+                                }
+                                Err(error) => Err((item, error)),
+                            },
+                            || self.1.shutdown(),
+                        )
+                    }
+
+                    /// Shuts down the buffer before this stage.
+                    #[allow(dead_code)]
+                    pub fn shutdown(&self) {
+                        self.0.shutdown()
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn is_closed(&self) -> bool {
+                        self.0.is_closed()
+                    }
+
+                    /// Items already transformed that wait for room in this stage's buffer.
+                    #[allow(dead_code)]
+                    pub fn waiting(&self) -> usize {
+                        self.2.waiting()
+                    }
+                }
+            });
+        }
+    }
+
+    let (impl_generic, ty_generic, where_clause) = generics.split_for_impl();
+    let buffers = buffers
+        .into_iter()
+        .map(do_generate_union)
+        .collect::<Vec<_>>();
+
+    quote! {
+        #( #buffers )*
+
+        #( #boundaries )*
+
+        #( #attrs )*
+        #vis struct #fifo_name #impl_generic (
+            #( #buffer_fifos #buffer_ty_generics ,)*
+        ) #where_clause;
+
+        impl #impl_generic #fifo_name #ty_generic #where_clause {
+            /// Every buffer gets the same geometry.
+            #[allow(dead_code)]
+            pub fn new(num_blocks: usize, block_size: usize) -> Self {
+                Self::with_buffers([(num_blocks, block_size); #num_buffers])
+            }
+
+            /// One `(num_blocks, block_size)` per buffer, the first one belongs to the producer and every
+            /// `buffer` stage starts the next one.
+            #[allow(dead_code)]
+            pub fn with_buffers(geometry: [(usize, usize); #num_buffers]) -> Self {
+                Self( #( #buffer_fifos ::new(geometry[#buffer_indices].0, geometry[#buffer_indices].1) ,)* )
+            }
+
+            /// Stops the producer, every `buffer` stage shuts down its own buffer once the one before it is drained.
+            #[allow(dead_code)]
+            pub fn shutdown(&self) {
+                self.0.shutdown()
+            }
+
+            #[allow(dead_code)]
+            pub fn is_closed(&self) -> bool {
+                self.0.is_closed()
+            }
+
+            #[allow(dead_code)]
+            pub fn split(self) -> (
+                #( #stage_types ,)*
+            ) {
+                #( let ( #( #buffer_stage_vars ,)* ) = self.#buffer_indices.split(); )*
+
+                ( #( #stage_exprs ,)* )
+            }
+        }
+    }
+}
+
+/// Generates the union, tag and fifos of a pipeline, stages are listed in order.
+///
+/// ```ignore
+/// generate_union! {
+///     pub Stages<I, O> {
+///         on_uninit = forget_slot;
+///         Producer: I, atomic = false;
+///         Transformer: O, atomic = true, on_drop = recycle;
+///         Consumer: (), atomic = false;
+///     }
+/// }
+/// ```
+///
+/// `on_drop = path` is called with every item of that stage still in the fifo when it
+/// is dropped, `on_uninit = path` with a pointer to every entry holding no live item.
+///
+/// A stage marked `buffer` starts a `fifo::FastFifo` of its own, which `{Name}Fifo::with_buffers`
+/// sizes separately. Such a stage is handed out as a fifo moving items from the buffer before it
+/// into its own, and shuts its buffer down once the one before it is drained. The last stage of
+/// such a pipeline has to be `()`.
+///
+/// A stage marked `broadcast = true` is split into `handles` stage fifos by `{Name}Fifo::with_handles`
+/// (one by `new`), and every one of them sees every item. Handles only get a `&` to the item, so the
+/// stage has the type of the stage it chases, and the stage chasing it waits until every handle gave
+/// the item. `split` returns a `Vec` of them. The producer cannot be `broadcast`.
 #[proc_macro]
 pub fn generate_union(input: TokenStream) -> TokenStream {
     do_generate_union(parse_macro_input!(input as UnionTypeInput)).into()
 }
+
+/// Attribute form of [`generate_union!`], the annotated enum lists the stages in order.
+///
+/// ```ignore
+/// #[fastfifo::pipeline]
+/// pub enum Stages<I, O> {
+///     Producer(I),
+///     #[atomic]
+///     Transformer(O),
+///     Consumer,
+/// }
+/// ```
+///
+/// `#[pipeline(on_uninit = path)]`, `#[on_drop = path]`, `#[buffer]` and `#[broadcast]` on a variant
+/// mirror the `on_uninit`, `on_drop`, `buffer` and `broadcast` options of `generate_union!`.
+#[proc_macro_attribute]
+pub fn pipeline(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as PipelineArgs);
+
+    match UnionTypeInput::from_enum(parse_macro_input!(item as ItemEnum), args) {
+        Ok(input) => do_generate_union(input).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
@@ -7,18 +7,18 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 #[cfg(loom)]
 use loom::sync::atomic::{AtomicUsize, Ordering};
 
-// pub trait AtomPair {
-//     fn load_take(&self) -> Field;
-//     fn fetch_max_take(&self, val: Field) -> Field;
-//     fn load_give(&self) -> Field;
-//     fn incr_give(&self);
-//     fn fetch_max_give(&self, val: Field) -> Field;
-
-//     /// Returns old (give, take)
-//     fn fetch_max_both(&self, val: Field) -> (Field, Field) {
-//         (self.fetch_max_give(val), self.fetch_max_take(val))
-//     }
-// }
+pub trait AtomPair {
+    fn load_take(&self) -> Field;
+    fn fetch_max_take(&self, val: Field) -> Field;
+    fn load_give(&self) -> Field;
+    fn incr_give(&self);
+    fn fetch_max_give(&self, val: Field) -> Field;
+
+    /// Returns old (give, take)
+    fn fetch_max_both(&self, val: Field) -> (Field, Field) {
+        (self.fetch_max_give(val), self.fetch_max_take(val))
+    }
+}
 
 #[repr(align(128))]
 pub struct Line128<T>(T);
@@ -57,14 +57,14 @@ impl AtomicPair {
             give: AtomicUsize::new(inner).into(),
         }
     }
-// }
+}
 
-// impl AtomPair for AtomicPair {
-    pub fn load_take(&self) -> Field {
+impl AtomPair for AtomicPair {
+    fn load_take(&self) -> Field {
         Field::from_raw_parts(self.index_max, self.take.load(Ordering::Relaxed))
     }
 
-    pub fn fetch_max_take(&self, val: Field) -> Field {
+    fn fetch_max_take(&self, val: Field) -> Field {
         Field::from_raw_parts(
             self.index_max,
             self.take.fetch_max(val.get_raw_inner(), Ordering::Relaxed),
@@ -72,76 +72,234 @@ impl AtomicPair {
     }
 
     /// Must be aquire so previous give stores are seen before this one is loaded
-    pub fn load_give(&self) -> Field {
+    fn load_give(&self) -> Field {
         Field::from_raw_parts(self.index_max, self.give.load(Ordering::Acquire))
     }
 
     /// Must be release so subsequent give loads are seen after this one is stored
-    pub fn incr_give(&self) {
+    fn incr_give(&self) {
         self.give.fetch_add(1, Ordering::Release);
     }
 
     /// This is for resetting give idx, it does not need to be ordered
-    pub fn fetch_max_give(&self, val: Field) -> Field {
+    fn fetch_max_give(&self, val: Field) -> Field {
         Field::from_raw_parts(
             self.index_max,
             self.give.fetch_max(val.get_raw_inner(), Ordering::Relaxed),
         )
     }
+}
 
-    pub fn fetch_max_both(&self, val: Field) -> (Field, Field) {
-        (self.fetch_max_give(val), self.fetch_max_take(val))
+/// Optimized for singular access -- since there is only one thread writing to this pair
+/// every update can be a plain load followed by a store instead of an RMW.
+///
+/// Other layers still read `take` and `give` concurrently, so both stay atomic. `take` is kept
+/// (rather than aliasing it to `give`) because the owning thread may hold several entries at once.
+/// Both indices are only ever written by the owner, so they share a single cache line.
+pub struct NonAtomicPair {
+    index_max: usize,
+    take_give: Line128<(AtomicUsize, AtomicUsize)>,
+}
+
+impl From<Field> for NonAtomicPair {
+    fn from(value: Field) -> Self {
+        Self::from_raw_parts(value.get_index_max(), value.get_raw_inner())
+    }
+}
+
+impl NonAtomicPair {
+    pub fn from_raw_parts(index_max: usize, inner: usize) -> Self {
+        Self {
+            index_max,
+            take_give: (AtomicUsize::new(inner), AtomicUsize::new(inner)).into(),
+        }
+    }
+
+    fn take(&self) -> &AtomicUsize {
+        &self.take_give.0.0
+    }
+
+    fn give(&self) -> &AtomicUsize {
+        &self.take_give.0.1
+    }
+
+    /// Single writer `fetch_max`, nobody else can store to `atomic` between the load and the store.
+    fn max(&self, atomic: &AtomicUsize, val: Field, order: Ordering) -> Field {
+        let old = atomic.load(Ordering::Relaxed);
+
+        if val.get_raw_inner() > old {
+            atomic.store(val.get_raw_inner(), order);
+        }
+
+        Field::from_raw_parts(self.index_max, old)
+    }
+}
+
+impl AtomPair for NonAtomicPair {
+    fn load_take(&self) -> Field {
+        Field::from_raw_parts(self.index_max, self.take().load(Ordering::Relaxed))
+    }
+
+    fn fetch_max_take(&self, val: Field) -> Field {
+        self.max(self.take(), val, Ordering::Relaxed)
+    }
+
+    /// Must be aquire so previous give stores are seen before this one is loaded
+    fn load_give(&self) -> Field {
+        Field::from_raw_parts(self.index_max, self.give().load(Ordering::Acquire))
+    }
+
+    /// Must be release so subsequent give loads are seen after this one is stored
+    fn incr_give(&self) {
+        self.give()
+            .store(self.give().load(Ordering::Relaxed) + 1, Ordering::Release);
+    }
+
+    /// Unlike the RMW in `AtomicPair::fetch_max_give`, a plain store does not continue the release
+    /// sequence of the previous `incr_give`, so it has to be release itself.
+    fn fetch_max_give(&self, val: Field) -> Field {
+        self.max(self.give(), val, Ordering::Release)
+    }
+}
+
+/// The pairs of a `broadcast` layer, one per handle.
+///
+/// Every handle takes and gives through its own pair. To the layer chasing it the pairs look like a single one
+/// that only gave what every handle gave, and has entries in flight while any handle has.
+pub struct BroadcastPairs(Box<[AtomicPair]>);
+
+impl BroadcastPairs {
+    pub fn new(block_size: usize, handles: usize) -> Self {
+        Self(
+            (0..handles)
+                .map(|_| Field::from_parts(block_size, 0, 0).into())
+                .collect(),
+        )
+    }
+
+    pub fn handle(&self, handle: usize) -> AtomPairRef<'_> {
+        (&self.0[handle]).into()
+    }
+
+    fn min_give(&self) -> Field {
+        self.0
+            .iter()
+            .map(|pair| pair.load_give())
+            .min_by_key(|give| give.get_raw_inner())
+            .unwrap()
+    }
+}
+
+impl AtomPair for BroadcastPairs {
+    /// The highest `take` if a handle has entries in flight, the lowest `give` otherwise.
+    fn load_take(&self) -> Field {
+        self.0
+            .iter()
+            .filter_map(|pair| {
+                // `give` first, like everywhere else a layer checks whether the one it chases is writing.
+                let give = pair.load_give();
+                let take = pair.load_take();
+
+                (take.get_index() > give.get_index()).then_some(take)
+            })
+            .max_by_key(|take| take.get_raw_inner())
+            .unwrap_or_else(|| self.min_give())
+    }
+
+    fn fetch_max_take(&self, val: Field) -> Field {
+        self.0
+            .iter()
+            .map(|pair| pair.fetch_max_take(val))
+            .min_by_key(|take| take.get_raw_inner())
+            .unwrap()
+    }
+
+    fn load_give(&self) -> Field {
+        self.min_give()
+    }
+
+    fn incr_give(&self) {
+        unreachable!("a broadcast layer gives through the pair of one of its handles")
+    }
+
+    fn fetch_max_give(&self, val: Field) -> Field {
+        self.0
+            .iter()
+            .map(|pair| pair.fetch_max_give(val))
+            .min_by_key(|give| give.get_raw_inner())
+            .unwrap()
+    }
+}
+
+/// Statically dispatched reference to the pair of a single layer, see `TaggedAtomPairs::get`.
+#[derive(Clone, Copy)]
+pub enum AtomPairRef<'a> {
+    Atomic(&'a AtomicPair),
+    NonAtomic(&'a NonAtomicPair),
+    Broadcast(&'a BroadcastPairs),
+}
+
+impl<'a> From<&'a AtomicPair> for AtomPairRef<'a> {
+    fn from(value: &'a AtomicPair) -> Self {
+        Self::Atomic(value)
+    }
+}
+
+impl<'a> From<&'a NonAtomicPair> for AtomPairRef<'a> {
+    fn from(value: &'a NonAtomicPair) -> Self {
+        Self::NonAtomic(value)
     }
 }
 
-// /// Optimized for singular access -- since there is only one thread accessing
-// /// we can guarantee that every idx less than give has completed,
-// pub struct NonAtomicPair {
-//     index_max: usize,
-//     give: Line128<AtomicUsize>,
-// }
-
-// impl From<Field> for NonAtomicPair {
-//     fn from(value: Field) -> Self {
-//         Self::from_raw_parts(value.get_index_max(), value.get_raw_inner())
-//     }
-// }
-
-// impl NonAtomicPair {
-//     fn from_raw_parts(index_max: usize, inner: usize) -> Self {
-//         Self {
-//             index_max,
-//             give: AtomicUsize::new(inner).into(),
-//         }
-//     }
-// }
-
-// impl AtomPair for NonAtomicPair {
-//     /// Take is always the same as give -- there is no reserving when there is only one thread.
-//     fn load_take(&self) -> Field {
-//         Field::from_raw_parts(self.index_max, self.give.load(Ordering::Relaxed))
-//     }
-
-//     /// Just like `load_take`, this just loads give since there is no take.
-//     fn fetch_max_take(&self, _val: Field) -> Field {
-//         self.load_take()
-//     }
-
-//     /// Must be aquire so previous give stores are seen before this one is loaded
-//     fn load_give(&self) -> Field {
-//         Field::from_raw_parts(self.index_max, self.give.load(Ordering::Acquire))
-//     }
-
-//     /// Must be release so subsequent give loads are seen after this one is stored
-//     fn incr_give(&self) {
-//         self.give.fetch_add(1, Ordering::Release);
-//     }
-
-//     /// This is for resetting give idx, it does not need to be ordered
-//     fn fetch_max_give(&self, val: Field) -> Field {
-//         Field::from_raw_parts(
-//             self.index_max,
-//             self.give.fetch_max(val.get_raw_inner(), Ordering::Relaxed),
-//         )
-//     }
-// }
+impl<'a> From<&'a BroadcastPairs> for AtomPairRef<'a> {
+    fn from(value: &'a BroadcastPairs) -> Self {
+        Self::Broadcast(value)
+    }
+}
+
+impl AtomPair for AtomPairRef<'_> {
+    #[inline]
+    fn load_take(&self) -> Field {
+        match self {
+            Self::Atomic(pair) => pair.load_take(),
+            Self::NonAtomic(pair) => pair.load_take(),
+            Self::Broadcast(pairs) => pairs.load_take(),
+        }
+    }
+
+    #[inline]
+    fn fetch_max_take(&self, val: Field) -> Field {
+        match self {
+            Self::Atomic(pair) => pair.fetch_max_take(val),
+            Self::NonAtomic(pair) => pair.fetch_max_take(val),
+            Self::Broadcast(pairs) => pairs.fetch_max_take(val),
+        }
+    }
+
+    #[inline]
+    fn load_give(&self) -> Field {
+        match self {
+            Self::Atomic(pair) => pair.load_give(),
+            Self::NonAtomic(pair) => pair.load_give(),
+            Self::Broadcast(pairs) => pairs.load_give(),
+        }
+    }
+
+    #[inline]
+    fn incr_give(&self) {
+        match self {
+            Self::Atomic(pair) => pair.incr_give(),
+            Self::NonAtomic(pair) => pair.incr_give(),
+            Self::Broadcast(pairs) => pairs.incr_give(),
+        }
+    }
+
+    #[inline]
+    fn fetch_max_give(&self, val: Field) -> Field {
+        match self {
+            Self::Atomic(pair) => pair.fetch_max_give(val),
+            Self::NonAtomic(pair) => pair.fetch_max_give(val),
+            Self::Broadcast(pairs) => pairs.fetch_max_give(val),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use clap::Parser;
+use fastfifo::mpmc::stealing::{Stealer, Worker};
+use std::{
+    fs::File,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Instant,
+};
+use tracing::info;
+use tracing_appender::non_blocking::NonBlockingBuilder;
+use tracing_subscriber::{
+    EnvFilter, Registry, fmt::layer, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[arg(short = 'b', long)]
+    block_size: usize,
+
+    #[arg(short = 'n', long)]
+    num_blocks: usize,
+
+    #[arg(short = 'o', long)]
+    nops: usize,
+
+    #[arg(short = 't', long)]
+    num_thieves: usize,
+
+    #[arg(short = 'l', long)]
+    log_file: Option<String>,
+}
+
+// The worker only pushes, every item goes to the thieves, first one at a time then in batches.
+// RUST_LOG=stealing_perf=info cargo run --release --bin stealing_perf -F cli -- -n 64 -b 1024 -o 20000000 -t 3
+
+/// Pushes `nops` items from the worker while `num_thieves` threads run `steal` until every item was taken.
+fn run(
+    name: &str,
+    num_blocks: usize,
+    block_size: usize,
+    nops: usize,
+    num_thieves: usize,
+    steal: impl Fn(&Stealer<usize>) -> usize + Sync,
+) {
+    let worker = Worker::new(num_blocks, block_size);
+    let remaining = AtomicUsize::new(nops);
+    let epoch = Instant::now();
+
+    thread::scope(|s| {
+        for _ in 0..num_thieves {
+            let stealer = worker.stealer();
+            let (remaining, steal) = (&remaining, &steal);
+            s.spawn(move || {
+                while remaining.load(Ordering::Relaxed) != 0 {
+                    match steal(&stealer) {
+                        0 => std::hint::spin_loop(),
+                        stolen => {
+                            remaining.fetch_sub(stolen, Ordering::Relaxed);
+                        }
+                    }
+                }
+            });
+        }
+
+        for i in 0..nops {
+            let mut val = i;
+            while let Err(back) = worker.push(val) {
+                val = back;
+                std::hint::spin_loop();
+            }
+        }
+    });
+
+    info!(
+        "{name}: estimated rate ({:.2e} items/s)",
+        nops as f64 / epoch.elapsed().as_secs_f64()
+    );
+}
+
+fn main() {
+    let Cli {
+        block_size,
+        num_blocks,
+        nops,
+        num_thieves,
+        log_file,
+    } = Cli::parse();
+
+    let log_path = PathBuf::new().join("logs").join(format!(
+        "{}.log",
+        log_file.unwrap_or("stealing_perf".to_string())
+    ));
+
+    let log_file = File::create(log_path).unwrap();
+
+    let (non_blocking_writer, _guard) = NonBlockingBuilder::default()
+        .buffered_lines_limit(100_000)
+        .lossy(false)
+        .finish(log_file);
+
+    let file_layer = layer()
+        .with_writer(non_blocking_writer)
+        .with_ansi(false)
+        .without_time()
+        .with_thread_names(true);
+
+    Registry::default()
+        .with(file_layer)
+        .with(EnvFilter::from_default_env())
+        .init();
+
+    info!("Buffer: ({num_blocks}, {block_size}), {num_thieves} thieves");
+
+    run(
+        "per item",
+        num_blocks,
+        block_size,
+        nops,
+        num_thieves,
+        |stealer| stealer.steal().map_or(0, |_| 1),
+    );
+    run(
+        "batched",
+        num_blocks,
+        block_size,
+        nops,
+        num_thieves,
+        |stealer| stealer.steal_batch().map_or(0, |batch| batch.count()),
+    );
+}
@@ -0,0 +1,167 @@
+use clap::Parser;
+use fastfifo::generate_union;
+use std::{fs::File, path::PathBuf, thread, time::Instant};
+use tracing::info;
+use tracing_appender::non_blocking::NonBlockingBuilder;
+use tracing_subscriber::{
+    EnvFilter, Registry, fmt::layer, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[arg(short = 'b', long)]
+    block_size: usize,
+
+    #[arg(short = 'n', long)]
+    num_blocks: usize,
+
+    #[arg(short = 'o', long)]
+    nops: usize,
+
+    #[arg(short = 'l', long)]
+    log_file: Option<String>,
+
+    /// Give the transformer and consumer a buffer of their own
+    #[arg(short = 'B', long)]
+    buffered: bool,
+
+    /// Block size of the second buffer, defaults to `block_size`
+    #[arg(long, requires = "buffered")]
+    second_block_size: Option<usize>,
+
+    /// Number of blocks of the second buffer, defaults to `num_blocks`
+    #[arg(long, requires = "buffered")]
+    second_num_blocks: Option<usize>,
+}
+
+generate_union! {
+    pub SingleUnion<Input, Output> {
+        Producer: Input, atomic = false;
+        Transformer: Output, atomic = false;
+        Consumer: (), atomic = false;
+    }
+}
+
+generate_union! {
+    pub BufferedUnion<Input, Output> {
+        Producer: Input, atomic = false;
+        Transformer: Output, atomic = false, buffer;
+        Consumer: (), atomic = false;
+    }
+}
+
+// RUST_LOG=variadic_buffered_perf=info cargo run --release --bin variadic_buffered_perf -F cli -- -n 64 -b 1024 -o 20000000
+// RUST_LOG=variadic_buffered_perf=info cargo run --release --bin variadic_buffered_perf -F cli -- -n 64 -b 1024 -o 20000000 -B --second-num-blocks 4 --second-block-size 64
+
+/// Runs every stage on its own thread, each closure retries until its stage accepted item `i`.
+fn run(
+    nops: usize,
+    mut producer: impl FnMut(usize) -> bool + Send,
+    mut transformer: impl FnMut() -> bool + Send,
+    mut consumer: impl FnMut(usize) -> bool + Send,
+) {
+    let epoch = Instant::now();
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..nops {
+                while !producer(i) {
+                    std::hint::spin_loop();
+                }
+            }
+        });
+        s.spawn(|| {
+            for _ in 0..nops {
+                while !transformer() {
+                    std::hint::spin_loop();
+                }
+            }
+        });
+        s.spawn(|| {
+            for i in 0..nops {
+                while !consumer(i) {
+                    std::hint::spin_loop();
+                }
+            }
+        });
+    });
+
+    info!(
+        "Estimated rate ({:.2e} ops/s)",
+        (3 * nops) as f64 / epoch.elapsed().as_secs_f64()
+    );
+}
+
+fn main() {
+    let Cli {
+        block_size,
+        num_blocks,
+        nops,
+        log_file,
+        buffered,
+        second_block_size,
+        second_num_blocks,
+    } = Cli::parse();
+
+    let log_path = PathBuf::new().join("logs").join(format!(
+        "{}.log",
+        log_file.unwrap_or("variadic_buffered_perf".to_string())
+    ));
+
+    let log_file = File::create(log_path).unwrap();
+
+    let (non_blocking_writer, _guard) = NonBlockingBuilder::default()
+        .buffered_lines_limit(100_000)
+        .lossy(false)
+        .finish(log_file);
+
+    let file_layer = layer()
+        .with_writer(non_blocking_writer)
+        .with_ansi(false)
+        .without_time()
+        .with_thread_names(true);
+
+    Registry::default()
+        .with(file_layer)
+        .with(EnvFilter::from_default_env())
+        .init();
+
+    if buffered {
+        let second = (
+            second_num_blocks.unwrap_or(num_blocks),
+            second_block_size.unwrap_or(block_size),
+        );
+        info!("Buffers: ({num_blocks}, {block_size}), {second:?}");
+
+        let (producer, transformer, consumer) =
+            BufferedUnionFifo::<usize, usize>::with_buffers([(num_blocks, block_size), second])
+                .split();
+
+        run(
+            nops,
+            move |i| producer.transform(|| i).is_ok(),
+            move || transformer.transform(|input| input + 1).is_ok(),
+            move |i| {
+                consumer
+                    .transform(|output| assert_eq!(output, i + 1))
+                    .is_ok()
+            },
+        );
+    } else {
+        info!("Buffer: ({num_blocks}, {block_size})");
+
+        let (producer, transformer, consumer) =
+            SingleUnionFifo::<usize, usize>::new(num_blocks, block_size).split();
+
+        run(
+            nops,
+            move |i| producer.transform(|| i).is_ok(),
+            move || transformer.transform(|input| input + 1).is_ok(),
+            move |i| {
+                consumer
+                    .transform(|output| assert_eq!(output, i + 1))
+                    .is_ok()
+            },
+        );
+    }
+}
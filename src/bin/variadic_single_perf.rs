@@ -5,7 +5,7 @@
 use tracing::error;
 
 use clap::Parser;
-use fastfifo::generate_union;
+use fastfifo::{fifo::FastFifo, generate_union};
 use std::{
     fs::File,
     path::PathBuf,
@@ -31,6 +31,10 @@ struct Cli {
 
     #[arg(short = 'l', long)]
     log_file: Option<String>,
+
+    /// Drive every stage from the main thread, one block at a time
+    #[arg(short = 's', long)]
+    sequential: bool,
 }
 
 generate_union! {
@@ -41,17 +45,34 @@ generate_union! {
     }
 }
 
+/// The heads `-s` runs with, `-F dyn_heads` swaps in the `Box<dyn Atomic>` ones `generate_union!` replaced.
+#[cfg(not(feature = "dyn_heads"))]
+type Heads = InOutUnionHeads;
+#[cfg(feature = "dyn_heads")]
+type Heads = fastfifo::config::DynHeads;
+
+// RUST_LOG=variadic_single_perf=info cargo run --release --bin variadic_single_perf -F cli -- -n 64 -b 1024 -o 50000000 -s
+// RUST_LOG=variadic_single_perf=info cargo run --release --bin variadic_single_perf -F cli,dyn_heads -- -n 64 -b 1024 -o 50000000 -s
+// Median of 5 runs each, on a single core:
+// heads: InOutUnionHeads
+// 4.37e7 ops/s
+// heads: DynHeads
+// 2.98e7 ops/s
+
+// RUST_LOG=variadic_single_perf=info cargo run --release --bin variadic_single_perf -F cli -- -n 64 -b 1024 -o 20000000
+
 fn main() {
     let Cli {
         nops,
         log_file,
         block_size,
         num_blocks,
+        sequential,
     } = Cli::parse();
 
     let log_path = PathBuf::new().join("logs").join(format!(
         "{}.log",
-        log_file.unwrap_or("variadic_perf".to_string())
+        log_file.unwrap_or("variadic_single_perf".to_string())
     ));
 
     let log_file = File::create(log_path).unwrap();
@@ -72,6 +93,59 @@ fn main() {
         .with(EnvFilter::from_default_env())
         .init();
 
+    if sequential {
+        // Measures the cost of `get_entry` itself, without any cross-core traffic.
+        let fifo =
+            FastFifo::<InOutUnionTag, InOutUnion<usize, usize>, InOutUnionAtomPairs, Heads>::new(
+                num_blocks, block_size,
+            );
+        let epoch = Instant::now();
+
+        for start in (0..nops).step_by(block_size) {
+            let batch = start..nops.min(start + block_size);
+
+            for i in batch.clone() {
+                while fifo
+                    .get_entry(InOutUnionTag::Producer)
+                    .map(|entry| InOutUnionProducerEntry::from(entry).transform(|| i))
+                    .is_err()
+                {
+                    std::hint::spin_loop();
+                }
+            }
+            for _ in batch.clone() {
+                while fifo
+                    .get_entry(InOutUnionTag::Transformer)
+                    .map(|entry| {
+                        InOutUnionTransformerEntry::from(entry).transform(|input| input + 1)
+                    })
+                    .is_err()
+                {
+                    std::hint::spin_loop();
+                }
+            }
+            for i in batch {
+                while fifo
+                    .get_entry(InOutUnionTag::Consumer)
+                    .map(|entry| {
+                        InOutUnionConsumerEntry::from(entry)
+                            .transform(|output| assert_eq!(output, i + 1))
+                    })
+                    .is_err()
+                {
+                    std::hint::spin_loop();
+                }
+            }
+        }
+
+        info!(
+            "Estimated rate ({:.2e} ops/s)",
+            (3 * nops) as f64 / epoch.elapsed().as_secs_f64()
+        );
+
+        return;
+    }
+
     let epoch = Instant::now();
     let deadline = epoch + Duration::from_millis(100);
 
@@ -81,14 +155,14 @@ fn main() {
 
     let prod_thread = {
         thread::Builder::new()
-            .name(format!("producer"))
+            .name("producer".to_string())
             .spawn(move || {
                 sleep_until(deadline);
 
                 info!("Woken");
 
                 for i in 0..nops {
-                    while fifo
+                    while producer
                         .transform(|| {
                             #[cfg(feature = "debug")]
                             info!("Op {i}: Uninit -> {i}");
@@ -96,7 +170,6 @@ fn main() {
                         })
                         .is_err()
                     {
-                        // sleep(Duration::from_millis(100));
                         std::hint::spin_loop();
                     }
                 }
@@ -108,104 +181,82 @@ fn main() {
 
     info!("Created producer thread");
 
-    let mut trans_threads = Vec::with_capacity(num_trans_threads);
-    for t in 0..num_trans_threads {
-        let fifo = transformer.clone();
-        let deadline = deadline.clone();
-
-        trans_threads.push(
-            thread::Builder::new()
-                .name(format!("transformer-{t}"))
-                .spawn(move || {
-                    // let span = span!(Level::INFO, "transformer");
-                    // let _guard = span.enter();
-
-                    sleep_until(deadline); // + Duration::from_millis(1000));
-
-                    info!("Woken");
-
-                    for i in 0..trans_nops {
-                        while fifo
-                            .transform(|input| {
-                                #[cfg(feature = "debug")]
-                                info!("Op {i}: {input} -> {}", input + 1);
-                                input + 1
-                            })
-                            .is_err()
-                        {
-                            // sleep(Duration::from_millis(100));
-                            std::hint::spin_loop();
-                        }
-                        let _ = i;
+    let trans_thread = {
+        thread::Builder::new()
+            .name("transformer".to_string())
+            .spawn(move || {
+                sleep_until(deadline);
+
+                info!("Woken");
+
+                for i in 0..nops {
+                    while transformer
+                        .transform(|input| {
+                            #[cfg(feature = "debug")]
+                            info!("Op {i}: {input} -> {}", input + 1);
+                            input + 1
+                        })
+                        .is_err()
+                    {
+                        std::hint::spin_loop();
                     }
+                    let _ = i;
+                }
 
-                    info!("Done");
-                })
-                .unwrap(),
-        )
-    }
+                info!("Done");
+            })
+            .unwrap()
+    };
+
+    info!("Created transformer thread");
+
+    let cons_thread = {
+        thread::Builder::new()
+            .name("consumer".to_string())
+            .spawn(move || {
+                sleep_until(deadline);
 
-    info!("Created {num_trans_threads} transformer thread(s)");
-
-    let mut cons_threads = Vec::with_capacity(num_cons_threads);
-    for c in 0..num_cons_threads {
-        let fifo = consumer.clone();
-        let deadline = deadline.clone();
-
-        cons_threads.push(
-            thread::Builder::new()
-                .name(format!("consumer-{c}"))
-                .spawn(move || {
-                    // let span = span!(Level::INFO, "consumer");
-                    // let _guard = span.enter();
-
-                    sleep_until(deadline); // + Duration::from_millis(2000));
-
-                    info!("Woken");
-
-                    for i in 0..cons_nops {
-                        while fifo
-                            .transform(|output| {
-                                #[cfg(feature = "debug")]
-                                info!("Op {i}: {output} -> Uninit");
-                                #[cfg(feature = "debug")]
-                                if output != i + 1 {
-                                    error!(
-                                        "FAILED ASSERTION `output ({output}) == i + 1 ({})`",
-                                        i + 1
-                                    );
-                                } else {
-                                    info!("SUCCEEDED ASSERTION `output == i + 1` ({output})")
-                                }
-                                // assert_eq!(output, i + 1)
-                            })
-                            .is_err()
-                        {
-                            // sleep(Duration::from_millis(100));
-                            std::hint::spin_loop();
-                        }
+                info!("Woken");
+
+                for i in 0..nops {
+                    while consumer
+                        .transform(|output| {
+                            #[cfg(feature = "debug")]
+                            info!("Op {i}: {output} -> Uninit");
+                            #[cfg(feature = "debug")]
+                            if output != i + 1 {
+                                error!("FAILED ASSERTION `output ({output}) == i + 1 ({})`", i + 1);
+                            } else {
+                                info!("SUCCEEDED ASSERTION `output == i + 1` ({output})")
+                            }
+                            let _ = output;
+                        })
+                        .is_err()
+                    {
+                        std::hint::spin_loop();
                     }
+                    let _ = i;
+                }
 
-                    info!("Done");
-                })
-                .unwrap(),
-        )
-    }
+                info!("Done");
+            })
+            .unwrap()
+    };
 
-    info!("Created {num_cons_threads} consumer thread(s)");
+    info!("Created consumer thread");
 
     sleep_until(deadline);
 
     info!("Woken from sleep");
 
-    prod_threads.into_iter().for_each(|t| t.join().unwrap());
-    trans_threads.into_iter().for_each(|t| t.join().unwrap());
-    cons_threads.into_iter().for_each(|t| t.join().unwrap());
+    prod_thread.join().unwrap();
+    trans_thread.join().unwrap();
+    cons_thread.join().unwrap();
 
     info!("Threads joined");
 
     info!(
         "Estimated rate ({:.2e} ops/s)",
-        true_nops as f64 / deadline.elapsed().as_secs_f64()
+        (3 * nops) as f64 / deadline.elapsed().as_secs_f64()
     );
 }
@@ -1,9 +1,6 @@
-#[cfg(feature = "debug")]
-use tracing::{info, instrument, warn};
-
 use crate::{
-    atom_pair::AtomicPair,
-    config::{FifoTag, IndexedDrop},
+    atom_pair::{AtomPair, AtomPairRef},
+    config::{FifoTag, IndexedDrop, TaggedAtomPairs},
     entry_descriptor::EntryDescriptor,
     field::Field,
 };
@@ -16,49 +13,29 @@ use loom::cell::{MutPtr, UnsafeCell};
 use std::cell::UnsafeCell;
 
 #[repr(C)]
-pub struct Block<Tag: FifoTag, Inner: IndexedDrop<Tag>> {
+pub struct Block<Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag>> {
     _phantom: PhantomData<(Tag,)>,
-    atomics: Box<[AtomicPair]>,
+    atomics: Pairs,
     entries: Box<[UnsafeCell<Inner>]>,
     block_size: usize,
 }
 
-pub enum ReserveState<'a, Tag: FifoTag, Inner: IndexedDrop<Tag>> {
-    Success(EntryDescriptor<'a, Tag, Inner>),
+pub enum ReserveState<'a, Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag>> {
+    Success(EntryDescriptor<'a, Tag, Inner, Pairs>),
     NotAvailable,
     BlockDone,
     Busy,
+    Closed,
 }
 
-impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> Block<Tag, Inner> {
-    #[cfg_attr(feature = "debug", instrument(skip(block_size)))]
-    pub fn new_in(block_size: usize) -> Self
+impl<Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag>> Block<Tag, Inner, Pairs> {
+    pub fn new_in(block_size: usize, handles: usize) -> Self
     where
         Inner: Default,
     {
         Self {
             _phantom: PhantomData,
-            atomics: {
-                let mut vec = Vec::new(); // (alloc);
-                vec.reserve(Tag::num_transformations());
-
-                vec.extend((0..Tag::num_transformations()).map(|i| {
-                    let field = Field::from_parts(block_size, 0, 0);
-
-                    #[cfg(feature = "debug")]
-                    info!("Atomics[{i}] = {field:?}");
-
-                    AtomicPair::from(field)
-
-                    // if Tag::try_from(i).unwrap().is_atomic() {
-                    //     Box::new(AtomicPair::from(field)) as Box<dyn AtomPair>
-                    // } else {
-                    //     Box::new(NonAtomicPair::from(field))
-                    // }
-                }));
-
-                vec.into_boxed_slice()
-            },
+            atomics: Pairs::new(block_size, handles),
             entries: {
                 let mut vec = Vec::new(); // (alloc);
                 vec.resize_with(block_size, || UnsafeCell::new(Inner::default()));
@@ -69,69 +46,74 @@ impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> Block<Tag, Inner> {
         }
     }
 
-    pub fn get_atomics(&self, tag: Tag) -> &AtomicPair {
-        &self.atomics.as_ref()[tag.into()]
+    pub fn get_atomics(&self, tag: Tag) -> AtomPairRef<'_> {
+        self.atomics.get(tag)
     }
 
-    pub fn get_current_chasing(&self, tag: Tag) -> (&AtomicPair, &AtomicPair) {
-        (self.get_atomics(tag), self.get_atomics(tag.chases()))
+    pub fn get_handle_atomics(&self, tag: Tag, handle: usize) -> AtomPairRef<'_> {
+        self.atomics.get_handle(tag, handle)
     }
 
-    #[cfg_attr(feature = "debug", instrument(skip(self, tag)))]
-    pub fn reserve_in_layer(&self, tag: Tag) -> ReserveState<'_, Tag, Inner> {
-        let (current, chasing) = self.get_current_chasing(tag);
+    pub fn get_current_chasing(
+        &self,
+        tag: Tag,
+        handle: usize,
+    ) -> (AtomPairRef<'_>, AtomPairRef<'_>) {
+        (
+            self.get_handle_atomics(tag, handle),
+            self.get_atomics(tag.chases()),
+        )
+    }
+
+    /// `enter` is asked right before an entry is claimed and refuses the claim by returning `false`,
+    /// `exit` is called once the claim was attempted.
+    pub fn reserve_in_layer(
+        &self,
+        tag: Tag,
+        handle: usize,
+        enter: impl Fn() -> bool,
+        exit: impl Fn(),
+    ) -> ReserveState<'_, Tag, Inner, Pairs> {
+        let (current, chasing) = self.get_current_chasing(tag, handle);
         let producer_offset = if tag == Tag::producer() { 1 } else { 0 };
 
         loop {
             let current_take = current.load_take();
 
-            #[cfg(feature = "debug")]
-            info!(?current_take);
-
             if current_take.get_index() >= self.block_size {
-                #[cfg(feature = "debug")]
-                info!("BlockDone");
                 break ReserveState::BlockDone;
             } else {
                 let chasing_give = chasing.load_give();
 
-                #[cfg(feature = "debug")]
-                info!(?chasing_give);
-
                 if current_take.get_version() >= chasing_give.get_version() + producer_offset {
                     if current_take.get_index() == chasing_give.get_index()
                         || current_take.get_version() > chasing_give.get_version() + producer_offset
                     {
-                        #[cfg(feature = "debug")]
-                        warn!("NotAvailable");
                         break ReserveState::NotAvailable;
                     } else {
                         let chasing_take = chasing.load_take();
 
-                        #[cfg(feature = "debug")]
-                        info!(?chasing_take);
-
                         if chasing_take.get_index() > chasing_give.get_index() {
-                            #[cfg(feature = "debug")]
-                            warn!("Busy");
                             break ReserveState::Busy;
                         }
                     }
                 }
 
                 let current_take_overflowing_add = current_take.overflowing_add(1);
-                #[cfg(feature = "debug")]
-                info!(?current_take_overflowing_add);
+
+                if !enter() {
+                    break ReserveState::Closed;
+                }
 
                 let fetch_max_result = current.fetch_max_take(current_take_overflowing_add);
-                #[cfg(feature = "debug")]
-                info!(?fetch_max_result);
+                exit();
 
                 if fetch_max_result == current_take {
                     break ReserveState::Success(EntryDescriptor {
-                        block: &self,
+                        block: self,
                         index: current_take.get_index(),
                         tag,
+                        handle,
                     });
                 }
             }
@@ -148,20 +130,119 @@ impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> Block<Tag, Inner> {
         self.entries.as_ref()[index].get_mut()
     }
 
-    pub fn drop_in(&mut self) {
-        let x = (0..Tag::num_transformations())
-            .map(|i| {
-                let atomic_pair = &self.atomics.as_ref()[i];
-                let (give, take) = (atomic_pair.load_give(), atomic_pair.load_take());
+    fn gives(&self) -> Vec<Field> {
+        (0..Tag::num_transformations())
+            .map(|i| self.get_atomics(Tag::try_from(i).unwrap()).load_give())
+            .collect()
+    }
+
+    /// The `give` index of every stage on the newest lap of this block. A stage still on an older lap
+    /// (or one that already moved on to the next lap of an empty block) has not given anything on it.
+    fn lap_indices(gives: &[Field]) -> Vec<usize> {
+        let version = gives.iter().map(|give| give.get_version()).max().unwrap();
 
-                if give.get_index() < take.get_index() {
-                    panic!("attempted to drop block while there exist incomplete transformations")
+        Self::on_lap(gives, version)
+    }
+
+    fn on_lap(fields: &[Field], version: usize) -> Vec<usize> {
+        fields
+            .iter()
+            .map(|field| {
+                if field.get_version() == version {
+                    field.get_index()
                 } else {
-                    give.get_index()
+                    0
                 }
             })
+            .collect()
+    }
+
+    /// How many entries of the newest lap of this block are queued for (handed over by the stage it chases
+    /// but not taken yet) and in flight in (taken but not given yet) every stage.
+    ///
+    /// The pairs are not loaded atomically together, so this is only an estimate while stages are running.
+    pub fn occupancy(&self) -> (Vec<usize>, Vec<usize>) {
+        let gives = self.gives();
+        let takes = (0..Tag::num_transformations())
+            .map(|i| self.get_atomics(Tag::try_from(i).unwrap()).load_take())
             .collect::<Vec<_>>();
 
+        let version = gives
+            .iter()
+            .chain(&takes)
+            .map(|field| field.get_version())
+            .max()
+            .unwrap();
+        let (gives, takes) = (Self::on_lap(&gives, version), Self::on_lap(&takes, version));
+
+        let queued = (0..gives.len())
+            .map(|i| {
+                if i == Tag::producer().into() {
+                    0
+                } else {
+                    gives[Tag::try_from(i).unwrap().chases().into()].saturating_sub(takes[i])
+                }
+            })
+            .collect();
+        let in_flight = takes
+            .iter()
+            .zip(&gives)
+            .map(|(take, give)| take.saturating_sub(*give))
+            .collect();
+
+        (queued, in_flight)
+    }
+
+    /// Whether `tag` and every stage before it have nothing in flight and `tag` has given everything
+    /// the producer gave in this block.
+    pub fn drained(&self, tag: Tag) -> bool {
+        // Each `give` is loaded before its `take`, a `take` equal to an earlier `give` means nothing was
+        // in flight in between.
+        let gives = self.gives();
+        let x = Self::lap_indices(&gives);
+
+        (0..=tag.into()).all(|i| {
+            self.get_atomics(Tag::try_from(i).unwrap())
+                .load_take()
+                .get_index()
+                == gives[i].get_index()
+        }) && x[tag.into()] == x[Tag::producer().into()]
+    }
+
+    /// Every entry of the newest lap of this block holding the output of a stage, along with that stage's tag,
+    /// in entry order. The last stage's output is free for the producer again and not listed.
+    ///
+    /// Only meaningful while nobody else uses the block.
+    #[cfg(not(loom))]
+    pub fn slots(&self) -> Vec<(usize, Tag, &Inner)> {
+        let x = Self::lap_indices(&self.gives());
+        let mut slots: Vec<_> = (1..x.len())
+            .flat_map(|i| (x[i]..x[i - 1]).map(move |k| (k, Tag::try_from(i - 1).unwrap())))
+            .collect();
+        slots.sort_by_key(|&(k, _)| k);
+
+        slots
+            .into_iter()
+            .map(|(k, tag)| (k, tag, unsafe { &*self.get_ptr(k) }))
+            .collect()
+    }
+
+    pub fn drop_in(&mut self) {
+        let gives = self.gives();
+
+        for (i, give) in gives.iter().enumerate() {
+            if give.get_index()
+                < self
+                    .get_atomics(Tag::try_from(i).unwrap())
+                    .load_take()
+                    .get_index()
+            {
+                panic!("attempted to drop block while there exist incomplete transformations")
+            }
+        }
+
+        let x = Self::lap_indices(&gives);
+
         //         v [2].give (1)
         //         |         v [2].take (2)
         //         |         |           v [1].give (3)
@@ -170,45 +251,46 @@ impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> Block<Tag, Inner> {
         //         |         |           |            |          |          v [0].take (6)
         // [Uninit, Reserved, Post_Trans, Trans_Alloc, Pre_Trans, Allocated, Uninit] ->
 
-        // Drop every set of entries between every current-chasing pair
-        for i in 0..x.len() {
-            let j = (i + x.len() - 1) % x.len();
-
-            let current = x[i];
-            let chasing = x[j];
+        // Every entry between a stage's give and the give of the stage it chases holds the chased stage's output
+        for i in 1..x.len() {
+            let j = i - 1;
 
-            if current < chasing {
-                for k in current..chasing {
-                    #[cfg(not(loom))]
-                    unsafe {
-                        self.entries.as_mut()[k].get_mut().indexed_drop(i)
-                    }
-                    #[cfg(loom)]
-                    unsafe {
-                        self.entries.as_mut()[k].get_mut().deref().indexed_drop(i)
-                    }
+            for k in x[i]..x[j] {
+                #[cfg(not(loom))]
+                unsafe {
+                    self.entries.as_mut()[k].get_mut().indexed_drop(j)
+                }
+                #[cfg(loom)]
+                unsafe {
+                    self.entries.as_mut()[k].get_mut().deref().indexed_drop(j)
                 }
             }
         }
 
-        // Drop the set of entries outside of [x.first.index..x.last.index] with an index set intentionally to x.len()
+        // Drop the set of entries outside of [x.last.index..x.first.index] with an index set intentionally to x.len()
         // which is usually out of range, inducing a forget rather than a drop for what is usually uninitialized data.
         //
-        // Simply implementing a valid TryFrom<usize> for your UnionTag will change this behaviour to whatever you want!
-        for k in (0..x[0]).chain(x[x.len() - 1]..self.entries.len()) {
+        // Simply implementing a valid TryFrom<usize> for your UnionTag (or overriding `indexed_drop`, which is what
+        // `on_uninit` does) will change this behaviour to whatever you want!
+        for k in (0..x[x.len() - 1]).chain(x[0]..self.entries.len()) {
             #[cfg(not(loom))]
             unsafe {
-                self.entries.as_mut()[k].get_mut().indexed_drop(k)
+                self.entries.as_mut()[k].get_mut().indexed_drop(x.len())
             }
             #[cfg(loom)]
             unsafe {
-                self.entries.as_mut()[k].get_mut().deref().indexed_drop(k)
+                self.entries.as_mut()[k]
+                    .get_mut()
+                    .deref()
+                    .indexed_drop(x.len())
             }
         }
     }
 }
 
-impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> Drop for Block<Tag, Inner> {
+impl<Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag>> Drop
+    for Block<Tag, Inner, Pairs>
+{
     fn drop(&mut self) {
         self.drop_in()
     }
@@ -0,0 +1,83 @@
+//! Glue between the buffers of a pipeline split with `buffer` stages.
+//!
+//! A stage marked `buffer` in `generate_union!` starts a new `fifo::FastFifo` with its own geometry. It is the
+//! last stage of the buffer before it and the producer of its own buffer, so its generated fifo takes an entry
+//! upstream and one downstream for every item. The downstream buffer may be full once the item is already
+//! transformed, and entries cannot be handed back, so such items wait in a [`Boundary`] until there is room.
+
+use crate::{Result, error::Error};
+use std::{
+    collections::VecDeque,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// Items transformed by a boundary stage that did not fit into the downstream buffer yet.
+///
+/// Shared by every clone of the stage's fifo. Waiting items are placed before any new item is taken upstream,
+/// so a single threaded boundary stage keeps the pipeline's order.
+pub struct Boundary<T> {
+    waiting: Mutex<VecDeque<T>>,
+    /// Mirrors `waiting.len()`, lets the common case skip the lock.
+    len: AtomicUsize,
+}
+
+impl<T> Default for Boundary<T> {
+    fn default() -> Self {
+        Self {
+            waiting: Mutex::new(VecDeque::new()),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T> Boundary<T> {
+    /// Moves one item across the boundary.
+    ///
+    /// `upstream` takes an upstream entry and calls `place` with the transformed item while it still holds the
+    /// entry. `downstream` hands the item back if the downstream buffer has no room. Once `upstream` reports
+    /// `Error::Closed` and nothing is waiting anymore, `shutdown` closes the downstream buffer.
+    pub fn transform(
+        &self,
+        upstream: impl FnOnce(&mut dyn FnMut(T)) -> Result<()>,
+        downstream: impl Fn(T) -> std::result::Result<(), (T, Error)>,
+        shutdown: impl FnOnce(),
+    ) -> Result<()> {
+        if self.len.load(Ordering::Acquire) != 0 {
+            let mut waiting = self.waiting.lock().unwrap();
+
+            while let Some(item) = waiting.pop_front() {
+                if let Err((item, error)) = downstream(item) {
+                    waiting.push_front(item);
+                    return Err(error);
+                }
+                self.len.fetch_sub(1, Ordering::Release);
+            }
+        }
+
+        // Parking happens before the upstream entry is given, so whoever sees the upstream buffer drained also
+        // sees the parked item and does not shut down the downstream buffer under it.
+        let result = upstream(&mut |item| {
+            if let Err((item, _)) = downstream(item) {
+                self.waiting.lock().unwrap().push_back(item);
+                self.len.fetch_add(1, Ordering::Release);
+            }
+        });
+
+        match result {
+            Err(Error::Closed) if self.len.load(Ordering::Acquire) != 0 => Err(Error::Busy),
+            Err(Error::Closed) => {
+                shutdown();
+                Err(Error::Closed)
+            }
+            result => result,
+        }
+    }
+
+    /// How many items are waiting for room downstream.
+    pub fn waiting(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+}
@@ -1,5 +1,13 @@
+use crate::atom_pair::{AtomicPair, NonAtomicPair};
+use crate::field::Field;
+use crate::head::{AtomicHead, NonAtomicHead};
 use std::fmt::Debug;
 
+pub use crate::atom_pair::{AtomPairRef, BroadcastPairs};
+#[cfg(feature = "dyn_heads")]
+pub use crate::head::DynHeads;
+pub use crate::head::{BroadcastHeads, HeadRef};
+
 // pub trait FifoConfig {
 //     type Tag: FifoTag;
 
@@ -10,6 +18,8 @@ use std::fmt::Debug;
 //     const BLOCK_SIZE: usize;
 // }
 
+/// The per-stage fifos generated by `generate_union!` only implement this (and `Clone`) for stages
+/// marked `atomic = true`, so cloning a single threaded stage is a compile error rather than a `None`.
 pub trait TaggedClone<Tag: FifoTag>: Sized {
     fn tagged_clone(&self, tag: Tag) -> Option<Self> {
         if tag.is_atomic() {
@@ -32,6 +42,10 @@ pub trait IndexedDrop<Tag: FifoTag> {
     /// Theoretically there could be several stages of uninitialized memory?
     ///
     /// The default implementation is a simple forwarding of `drop`, specifically `std::ptr::drop_in_place`.
+    /// `generate_union!` overrides it to call the `on_uninit` hook instead, when one is given.
+    ///
+    /// # Safety
+    /// Same as `tagged_drop`.
     unsafe fn indexed_drop(&mut self, index: usize) {
         if let Ok(tag) = Tag::try_from(index) {
             unsafe { self.tagged_drop(tag) }
@@ -49,3 +63,63 @@ pub trait FifoTag: TryFrom<usize, Error: Debug> + Into<usize> + Copy + Debug + P
     /// Make sure to implement a Self::try_from(Tag::num_transformations()) for custom drop behaviour.
     fn num_transformations() -> usize;
 }
+
+/// One `AtomPair` per transformation, stored in every `Block`.
+///
+/// `generate_union!` implements this with a struct holding a concrete pair per layer, chosen
+/// from the layer's `atomic =` flag through `SelectAtomicity`, so no `dyn` is involved.
+/// A `broadcast` layer holds `handles` pairs in a `BroadcastPairs` instead.
+pub trait TaggedAtomPairs<Tag: FifoTag> {
+    fn new(block_size: usize, handles: usize) -> Self;
+    /// The pair the layer chasing `tag` sees.
+    fn get(&self, tag: Tag) -> AtomPairRef<'_>;
+    /// The pair `handle` of `tag` takes and gives through, any `handle` is the same pair for other layers.
+    fn get_handle(&self, tag: Tag, handle: usize) -> AtomPairRef<'_>;
+}
+
+/// One head per transformation, owned by the fifo.
+///
+/// Implemented by `generate_union!` in the same way as `TaggedAtomPairs`, typing each head as
+/// an `AtomicHead` or a `NonAtomicHead` according to the layer's `atomic =` flag. A `broadcast`
+/// layer has a head per handle.
+pub trait TaggedHeads<Tag: FifoTag> {
+    fn new(num_blocks: usize, handles: usize) -> Self;
+    fn get(&self, tag: Tag, handle: usize) -> HeadRef<'_>;
+}
+
+/// Type level version of `FifoTag::is_atomic`.
+pub struct Atomicity<const ATOMIC: bool>;
+
+pub trait SelectAtomicity {
+    type Pair;
+    type Head;
+
+    fn new_pair(block_size: usize) -> Self::Pair;
+    fn new_head(num_blocks: usize) -> Self::Head;
+}
+
+impl SelectAtomicity for Atomicity<true> {
+    type Pair = AtomicPair;
+    type Head = AtomicHead;
+
+    fn new_pair(block_size: usize) -> Self::Pair {
+        Field::from_parts(block_size, 0, 0).into()
+    }
+
+    fn new_head(num_blocks: usize) -> Self::Head {
+        Field::from_parts(num_blocks, 0, 0).into()
+    }
+}
+
+impl SelectAtomicity for Atomicity<false> {
+    type Pair = NonAtomicPair;
+    type Head = NonAtomicHead;
+
+    fn new_pair(block_size: usize) -> Self::Pair {
+        Field::from_parts(block_size, 0, 0).into()
+    }
+
+    fn new_head(num_blocks: usize) -> Self::Head {
+        Field::from_parts(num_blocks, 0, 0).into()
+    }
+}
@@ -1,17 +1,25 @@
 use crate::{
+    atom_pair::AtomPair,
     block::Block,
-    config::{FifoTag, IndexedDrop},
+    config::{FifoTag, IndexedDrop, TaggedAtomPairs},
 };
 // use std::alloc::{Allocator, Global};
 
-pub struct EntryDescriptor<'a, Tag: FifoTag, Inner: IndexedDrop<Tag> /*A: Allocator = Global*/> {
-    pub(crate) block: &'a Block<Tag, Inner /*A*/>,
+pub struct EntryDescriptor<
+    'a,
+    Tag: FifoTag,
+    Inner: IndexedDrop<Tag>,
+    Pairs: TaggedAtomPairs<Tag>, /*A: Allocator = Global*/
+> {
+    pub(crate) block: &'a Block<Tag, Inner, Pairs /*A*/>,
     pub(crate) index: usize,
     pub(crate) tag: Tag,
+    /// Which of the tag's handles took the entry, always 0 unless the stage is `broadcast`.
+    pub(crate) handle: usize,
 }
 
-impl<'a, Tag: FifoTag, Inner: IndexedDrop<Tag> /*, A: Allocator*/>
-    EntryDescriptor<'a, Tag, Inner /*A*/>
+impl<'a, Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag> /*, A: Allocator*/>
+    EntryDescriptor<'a, Tag, Inner, Pairs /*A*/>
 {
     pub fn modify_t_in_place<F: FnOnce(*mut Inner)>(&mut self, modifier: F) {
         #[cfg(not(loom))]
@@ -21,10 +29,12 @@ impl<'a, Tag: FifoTag, Inner: IndexedDrop<Tag> /*, A: Allocator*/>
     }
 }
 
-impl<'a, Tag: FifoTag, Inner: IndexedDrop<Tag> /*, A: Allocator*/> Drop
-    for EntryDescriptor<'a, Tag, Inner /*A*/>
+impl<'a, Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag> /*, A: Allocator*/> Drop
+    for EntryDescriptor<'a, Tag, Inner, Pairs /*A*/>
 {
     fn drop(&mut self) {
-        self.block.get_atomics(self.tag).incr_give();
+        self.block
+            .get_handle_atomics(self.tag, self.handle)
+            .incr_give();
     }
 }
@@ -1,5 +1,7 @@
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum Error {
     NotAvailable,
     Busy,
+    /// The fifo was shut down and this stage has nothing left to transform.
+    Closed,
 }
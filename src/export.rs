@@ -0,0 +1,78 @@
+//! Publishing fifo statistics into the [`metrics`] facade.
+//!
+//! Nothing is published from the hot path. Call `export_metrics` on a fifo from whatever thread scrapes it, the
+//! snapshot only reads atomics, and every metric below is set from it. All of them carry a `fifo` label with the name passed in.
+//! Pipeline metrics also carry a `stage` label with the stage's `Debug` name.
+
+use crate::{fifo::StageStats, mpmc::Stats};
+use metrics::{counter, gauge};
+use std::fmt::Debug;
+
+/// Gauge, entries waiting for a stage. For the producer these are the free entries.
+pub const QUEUED: &str = "fastfifo_queued";
+/// Gauge, entries taken by a stage and not given yet.
+pub const IN_FLIGHT: &str = "fastfifo_in_flight";
+/// Counter, entries handed out to a stage.
+pub const TAKEN: &str = "fastfifo_taken_total";
+/// Counter, `get_entry` calls of a stage that returned `Error::Busy`.
+pub const BUSY: &str = "fastfifo_busy_total";
+/// Counter, `get_entry` calls of a stage that returned `Error::NotAvailable`.
+pub const NOT_AVAILABLE: &str = "fastfifo_not_available_total";
+
+/// Gauge, entries pushed into an `mpmc::FastFifo` and not popped yet.
+pub const DEPTH: &str = "fastfifo_mpmc_depth";
+/// Counter, see [`Stats::pushes`].
+pub const PUSHES: &str = "fastfifo_mpmc_pushes_total";
+/// Counter, see [`Stats::pops`].
+pub const POPS: &str = "fastfifo_mpmc_pops_total";
+/// Counter, see [`Stats::full`].
+pub const FULL: &str = "fastfifo_mpmc_full_total";
+/// Counter, see [`Stats::busy`].
+pub const MPMC_BUSY: &str = "fastfifo_mpmc_busy_total";
+/// Counter, see [`Stats::empty`].
+pub const EMPTY: &str = "fastfifo_mpmc_empty_total";
+/// Counter, see [`Stats::phead_advances`].
+pub const PHEAD_ADVANCES: &str = "fastfifo_mpmc_phead_advances_total";
+/// Counter, see [`Stats::phead_advance_failures`].
+pub const PHEAD_ADVANCE_FAILURES: &str = "fastfifo_mpmc_phead_advance_failures_total";
+/// Counter, see [`Stats::chead_advances`].
+pub const CHEAD_ADVANCES: &str = "fastfifo_mpmc_chead_advances_total";
+/// Counter, see [`Stats::chead_advance_failures`].
+pub const CHEAD_ADVANCE_FAILURES: &str = "fastfifo_mpmc_chead_advance_failures_total";
+/// Counter, see [`Stats::wraparounds`].
+pub const WRAPAROUNDS: &str = "fastfifo_mpmc_wraparounds_total";
+
+pub(crate) fn export_stages<Tag: Debug>(name: &str, stats: &[StageStats<Tag>]) {
+    for stage in stats {
+        let labels = [
+            ("fifo", name.to_owned()),
+            ("stage", format!("{:?}", stage.tag)),
+        ];
+
+        gauge!(QUEUED, &labels).set(stage.queued as f64);
+        gauge!(IN_FLIGHT, &labels).set(stage.in_flight as f64);
+        counter!(TAKEN, &labels).absolute(stage.taken as u64);
+        counter!(BUSY, &labels).absolute(stage.busy as u64);
+        counter!(NOT_AVAILABLE, &labels).absolute(stage.not_available as u64);
+    }
+}
+
+pub(crate) fn export_mpmc(name: &str, stats: &Stats) {
+    let labels = [("fifo", name.to_owned())];
+
+    gauge!(DEPTH, &labels).set(stats.pushes.saturating_sub(stats.pops) as f64);
+    for (metric, value) in [
+        (PUSHES, stats.pushes),
+        (POPS, stats.pops),
+        (FULL, stats.full),
+        (MPMC_BUSY, stats.busy),
+        (EMPTY, stats.empty),
+        (PHEAD_ADVANCES, stats.phead_advances),
+        (PHEAD_ADVANCE_FAILURES, stats.phead_advance_failures),
+        (CHEAD_ADVANCES, stats.chead_advances),
+        (CHEAD_ADVANCE_FAILURES, stats.chead_advance_failures),
+        (WRAPAROUNDS, stats.wraparounds),
+    ] {
+        counter!(metric, &labels).absolute(value);
+    }
+}
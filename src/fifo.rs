@@ -1,6 +1,6 @@
 use crate::{
     Result,
-    config::{FifoTag, IndexedDrop, TaggedClone},
+    config::{FifoTag, IndexedDrop, TaggedAtomPairs, TaggedClone, TaggedHeads},
     entry_descriptor::EntryDescriptor,
     fifo_inner::FastFifoInner,
 };
@@ -9,23 +9,79 @@ use std::{
     sync::Arc,
 };
 
-pub struct FastFifo<Tag: FifoTag, Inner: IndexedDrop<Tag> + Default /*A: Allocator = Global*/>(
-    Arc<FastFifoInner<Tag, Inner /*A*/>>,
-);
+/// A snapshot of a single stage, see [`FastFifo::stage_stats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StageStats<Tag> {
+    pub tag: Tag,
+    /// The block this stage's head points at, `None` for an `atomic = false` stage whose head only the thread
+    /// running it may read.
+    pub block: Option<usize>,
+    /// Entries handed over by the stage this one chases and not taken yet.
+    /// For the producer these are the free entries.
+    pub queued: usize,
+    /// Entries taken by this stage and not given yet.
+    pub in_flight: usize,
+    /// How many entries `get_entry` handed out to this stage.
+    #[cfg(feature = "metrics")]
+    pub taken: usize,
+    /// How often `get_entry` returned `Error::Busy` for this stage.
+    #[cfg(feature = "metrics")]
+    pub busy: usize,
+    /// How often `get_entry` returned `Error::NotAvailable` for this stage.
+    #[cfg(feature = "metrics")]
+    pub not_available: usize,
+}
+
+/// An entry holding the output of a stage, see [`FastFifo::slots`].
+#[derive(Debug)]
+pub struct Slot<'a, Tag, Inner> {
+    pub block: usize,
+    pub index: usize,
+    /// The stage whose output the entry holds, the union field to read.
+    pub tag: Tag,
+    pub value: &'a Inner,
+}
 
-impl<Tag: FifoTag, Inner: IndexedDrop<Tag> + Default /*, A: Allocator*/> TaggedClone<Tag>
-    for FastFifo<Tag, Inner /*A*/>
+pub struct FastFifo<
+    Tag: FifoTag,
+    Inner: IndexedDrop<Tag> + Default,
+    Pairs: TaggedAtomPairs<Tag>,
+    Heads: TaggedHeads<Tag>, /*A: Allocator = Global*/
+>(Arc<FastFifoInner<Tag, Inner, Pairs, Heads /*A*/>>);
+
+impl<
+    Tag: FifoTag,
+    Inner: IndexedDrop<Tag> + Default,
+    Pairs: TaggedAtomPairs<Tag>,
+    Heads: TaggedHeads<Tag>, /*, A: Allocator*/
+> TaggedClone<Tag> for FastFifo<Tag, Inner, Pairs, Heads /*A*/>
 {
     fn unchecked_clone(&self) -> Self {
         Self(self.0.clone())
     }
 }
 
-impl<Tag: FifoTag + 'static, Inner: IndexedDrop<Tag> + Default> FastFifo<Tag, Inner> {
+impl<
+    Tag: FifoTag + 'static,
+    Inner: IndexedDrop<Tag> + Default,
+    Pairs: TaggedAtomPairs<Tag>,
+    Heads: TaggedHeads<Tag>,
+> FastFifo<Tag, Inner, Pairs, Heads>
+{
     pub fn new(num_blocks: usize, block_size: usize) -> Self {
-        Self(Arc::new(FastFifoInner::new_in(num_blocks, block_size)))
+        Self::with_handles(num_blocks, block_size, 1)
         // Self::new_in(num_blocks, block_size, Global)
     }
+
+    /// Every `broadcast` stage gets `handles` handles, each of them sees every item.
+    ///
+    /// Panics if `handles` is 0.
+    pub fn with_handles(num_blocks: usize, block_size: usize, handles: usize) -> Self {
+        assert!(handles > 0, "a broadcast stage needs at least one handle");
+        Self(Arc::new(FastFifoInner::new_in(
+            num_blocks, block_size, handles,
+        )))
+    }
 }
 
 // impl<Tag: FifoTag + 'static, Inner: IndexedDrop<Tag> + Default, /*A: Allocator*/>
@@ -38,10 +94,81 @@ impl<Tag: FifoTag + 'static, Inner: IndexedDrop<Tag> + Default> FastFifo<Tag, In
 //     }
 // }
 
-impl<Tag: FifoTag, Inner: IndexedDrop<Tag> + Default /*A: Allocator*/>
-    FastFifo<Tag, Inner /*A*/>
+impl<
+    Tag: FifoTag,
+    Inner: IndexedDrop<Tag> + Default,
+    Pairs: TaggedAtomPairs<Tag>,
+    Heads: TaggedHeads<Tag>, /*A: Allocator*/
+> FastFifo<Tag, Inner, Pairs, Heads /*A*/>
 {
-    pub fn get_entry(&self, tag: Tag) -> Result<EntryDescriptor<'_, Tag, Inner /*A*/>> {
-        self.0.get_entry(tag)
+    pub fn get_entry(&self, tag: Tag) -> Result<EntryDescriptor<'_, Tag, Inner, Pairs /*A*/>> {
+        self.0.get_entry(tag, 0)
+    }
+
+    /// Takes an entry for one of the handles of a `broadcast` stage, see [`Self::with_handles`].
+    ///
+    /// Panics if `handle` is not below [`Self::handles`].
+    pub fn get_handle_entry(
+        &self,
+        tag: Tag,
+        handle: usize,
+    ) -> Result<EntryDescriptor<'_, Tag, Inner, Pairs /*A*/>> {
+        assert!(
+            handle < self.handles(),
+            "handle {handle} out of {} handles",
+            self.handles()
+        );
+        self.0.get_entry(tag, handle)
+    }
+
+    /// Handles of every `broadcast` stage.
+    pub fn handles(&self) -> usize {
+        self.0.handles()
+    }
+
+    /// Lets [`Self::shutdown`] run while producers may still be in `get_entry`, at the cost of two atomic
+    /// operations on a shared counter in every producer `get_entry`.
+    ///
+    /// Panics if the fifo is shared already.
+    pub fn concurrent_shutdown(mut self) -> Self {
+        Arc::get_mut(&mut self.0)
+            .expect("concurrent_shutdown must be set before the fifo is shared")
+            .set_concurrent_shutdown();
+        self
+    }
+
+    /// Stops the producer stage. Every other stage keeps transforming what is left and gets
+    /// `Error::Closed` once the stage it chases is exhausted.
+    ///
+    /// Unless the fifo was built with [`Self::concurrent_shutdown`], this must happen after the last producer
+    /// `get_entry`, for instance called by the producer itself once it is done. Otherwise an entry claimed while
+    /// the fifo shuts down can be missed by the stages draining it.
+    pub fn shutdown(&self) {
+        self.0.shutdown()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+
+    /// Per stage occupancy, in `Tag` order. The stages are not stopped while this is collected,
+    /// so the numbers are only exact for a quiescent fifo. Only atomics are read, any thread may call this.
+    pub fn stage_stats(&self) -> Vec<StageStats<Tag>> {
+        self.0.stage_stats()
+    }
+
+    /// Publishes [`Self::stage_stats`] into the `metrics` facade, see [`crate::export`].
+    #[cfg(feature = "metrics")]
+    pub fn export_metrics(&self, name: &str) {
+        crate::export::export_stages(name, &self.stage_stats());
     }
-}
\ No newline at end of file
+
+    /// Every entry holding the output of a stage along with that stage's tag, block by block, to dump what is
+    /// left in the fifo.
+    ///
+    /// Returns `None` while another handle, like one of the stage fifos from `split`, shares the fifo.
+    #[cfg(not(loom))]
+    pub fn slots(&mut self) -> Option<Vec<Slot<'_, Tag, Inner>>> {
+        Arc::get_mut(&mut self.0).map(|inner| inner.slots())
+    }
+}
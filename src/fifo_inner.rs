@@ -1,29 +1,83 @@
-#[cfg(feature = "debug")]
-use tracing::{info, instrument, warn};
-
 use crate::{
     Result,
+    atom_pair::AtomPair,
     block::{Block, ReserveState},
-    config::{FifoTag, IndexedDrop},
+    config::{FifoTag, IndexedDrop, TaggedAtomPairs, TaggedHeads},
     entry_descriptor::EntryDescriptor,
     error::Error,
     field::Field,
     field::FieldConfig,
-    head::{Atomic, AtomicHead, NonAtomicHead},
+    fifo::StageStats,
+    head::{Atomic, HeadRef},
+    trace::fifo_event,
 };
 
-pub(crate) struct FastFifoInner<Tag: FifoTag, Inner: IndexedDrop<Tag>> {
+#[cfg(not(loom))]
+use crate::fifo::Slot;
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Set in `FastFifoInner::state` once the fifo is shut down.
+const CLOSED: usize = 1;
+/// Added to `FastFifoInner::state` for every producer between checking `CLOSED` and claiming its entry, only
+/// with `concurrent_shutdown`.
+const PRODUCER: usize = 2;
+
+pub(crate) struct FastFifoInner<
+    Tag: FifoTag,
+    Inner: IndexedDrop<Tag>,
+    Pairs: TaggedAtomPairs<Tag>,
+    Heads: TaggedHeads<Tag>,
+> {
     // num_heads == Tag::num_transformations()
-    heads: Box<[Box<dyn Atomic>]>,
-    blocks: Box<[Block<Tag, Inner>]>,
+    heads: Heads,
+    blocks: Box<[Block<Tag, Inner, Pairs>]>,
     num_blocks: usize,
     block_size: usize,
+    /// Handles of every `broadcast` stage.
+    handles: usize,
+    /// Mirrors `CLOSED` so that stages waiting on each other never have to touch `state` before a shutdown.
+    closed: AtomicBool,
+    state: AtomicUsize,
+    /// Whether producers announce themselves in `state`, so `shutdown` may race them.
+    concurrent_shutdown: bool,
+    #[cfg(feature = "metrics")]
+    counters: Box<[StageCounters]>,
+    #[cfg(feature = "debug")]
+    span: tracing::Span,
+}
+
+/// Cumulative `get_entry` results of a single stage.
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+struct StageCounters {
+    taken: AtomicUsize,
+    busy: AtomicUsize,
+    not_available: AtomicUsize,
+}
+
+#[cfg(feature = "metrics")]
+impl StageCounters {
+    fn record<T>(&self, result: &Result<T>) {
+        let Err(error) = result else {
+            self.taken.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        match error {
+            Error::Busy => self.busy.fetch_add(1, Ordering::Relaxed),
+            Error::NotAvailable => self.not_available.fetch_add(1, Ordering::Relaxed),
+            Error::Closed => return,
+        };
+    }
 }
 
 #[rustfmt::skip]
-unsafe impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> Send for FastFifoInner<Tag, Inner> {}
+unsafe impl<Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag>, Heads: TaggedHeads<Tag>> Send for FastFifoInner<Tag, Inner, Pairs, Heads> {}
 #[rustfmt::skip]
-unsafe impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> Sync for FastFifoInner<Tag, Inner> {}
+unsafe impl<Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag>, Heads: TaggedHeads<Tag>> Sync for FastFifoInner<Tag, Inner, Pairs, Heads> {}
 
 #[derive(Debug)]
 enum AdvanceHeadStatus {
@@ -31,71 +85,208 @@ enum AdvanceHeadStatus {
     Success,
 }
 
-impl<Tag: FifoTag + 'static, Inner: IndexedDrop<Tag>> FastFifoInner<Tag, Inner> {
-    #[cfg_attr(feature = "debug", instrument)]
-    pub fn new_in(num_blocks: usize, block_size: usize) -> Self
+impl<
+    Tag: FifoTag + 'static,
+    Inner: IndexedDrop<Tag>,
+    Pairs: TaggedAtomPairs<Tag>,
+    Heads: TaggedHeads<Tag>,
+> FastFifoInner<Tag, Inner, Pairs, Heads>
+{
+    pub fn new_in(num_blocks: usize, block_size: usize, handles: usize) -> Self
     where
         Inner: Default,
     {
-        Self {
-            heads: {
-                let mut vec = Vec::new();
-                vec.reserve(Tag::num_transformations());
+        let fifo = Self {
+            heads: Heads::new(num_blocks, handles),
+            blocks: {
+                let mut vec = Vec::with_capacity(num_blocks);
+                vec.extend((0..num_blocks).map(|_| Block::new_in(block_size, handles)));
 
-                vec.extend((0..Tag::num_transformations()).map(|i| {
-                    let tag = Tag::try_from(i).unwrap();
+                vec.into_boxed_slice()
+            },
+            num_blocks,
+            block_size,
+            handles,
+            closed: AtomicBool::new(false),
+            state: AtomicUsize::new(0),
+            concurrent_shutdown: false,
+            #[cfg(feature = "metrics")]
+            counters: (0..Tag::num_transformations())
+                .map(|_| StageCounters::default())
+                .collect(),
+            #[cfg(feature = "debug")]
+            span: crate::trace::fifo_span("pipeline", num_blocks, block_size),
+        };
 
-                    let field = Field::from_parts(num_blocks, 0, 0);
+        fifo_event!(fifo, DEBUG, stages = Tag::num_transformations(), "created");
+        fifo
+    }
+}
 
-                    #[cfg(feature = "debug")]
-                    info!("Head[{i}] = {field:?}");
+impl<Tag: FifoTag, Inner: IndexedDrop<Tag>, Pairs: TaggedAtomPairs<Tag>, Heads: TaggedHeads<Tag>>
+    FastFifoInner<Tag, Inner, Pairs, Heads>
+{
+    fn get_head(&self, tag: Tag, handle: usize) -> HeadRef<'_> {
+        self.heads.get(tag, handle)
+    }
 
-                    if tag.is_atomic() {
-                        Box::new(AtomicHead::from(field)) as Box<dyn Atomic>
-                    } else {
-                        Box::new(NonAtomicHead::from(field))
-                    }
-                }));
+    fn get_block(&self, tag: Tag, handle: usize) -> (Field, &Block<Tag, Inner, Pairs>) {
+        let head = self.get_head(tag, handle).load();
 
-                vec.into_boxed_slice()
-            },
-            blocks: {
-                let mut vec = Vec::new();
-                vec.reserve(num_blocks);
+        (head, &self.blocks.as_ref()[head.get_index()])
+    }
 
-                vec.extend((0..num_blocks).map(|i| {
-                    #[cfg(feature = "debug")]
-                    info!("Init block {i}");
-                    let _ = i;
+    /// Every entry holding the output of a stage, block by block. Only meaningful while nobody else uses the fifo.
+    #[cfg(not(loom))]
+    pub fn slots(&mut self) -> Vec<Slot<'_, Tag, Inner>> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(block, b)| {
+                b.slots().into_iter().map(move |(index, tag, value)| Slot {
+                    block,
+                    index,
+                    tag,
+                    value,
+                })
+            })
+            .collect()
+    }
 
-                    Block::new_in(block_size)
-                }));
+    /// Makes producers announce themselves before claiming an entry, for `shutdown` to race them.
+    pub fn set_concurrent_shutdown(&mut self) {
+        self.concurrent_shutdown = true;
+    }
 
-                vec.into_boxed_slice()
-            },
-            num_blocks,
-            block_size,
+    /// Stops the producer, every other stage gets `Error::Closed` once it has drained whatever is left.
+    pub fn shutdown(&self) {
+        self.state.fetch_or(CLOSED, Ordering::Release);
+        self.closed.store(true, Ordering::Release);
+
+        fifo_event!(self, INFO, "shutdown");
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub fn handles(&self) -> usize {
+        self.handles
+    }
+
+    /// `handle` picks one of the handles of a `broadcast` stage and is 0 for any other stage.
+    pub fn get_entry(
+        &self,
+        tag: Tag,
+        handle: usize,
+    ) -> Result<EntryDescriptor<'_, Tag, Inner, Pairs>> {
+        let result = if tag == Tag::producer() {
+            if self.is_closed() {
+                Err(Error::Closed)
+            } else if self.concurrent_shutdown {
+                self.reserve(
+                    tag,
+                    handle,
+                    || self.enter_producer(),
+                    || self.exit_producer(),
+                )
+            } else {
+                // `shutdown` comes after every claim, `state` never has a producer to wait for.
+                self.reserve(tag, handle, || true, || {})
+            }
+        } else {
+            match self.reserve(tag, handle, || true, || {}) {
+                Err(Error::NotAvailable) if self.drained(tag) => {
+                    fifo_event!(self, DEBUG, stage = ?tag, "drained");
+                    Err(Error::Closed)
+                }
+                result => result,
+            }
+        };
+
+        #[cfg(feature = "metrics")]
+        self.counters[tag.into()].record(&result);
+
+        result
+    }
+
+    pub fn stage_stats(&self) -> Vec<StageStats<Tag>> {
+        let num_stages = Tag::num_transformations();
+        let producer = Tag::producer().into();
+
+        let (mut queued, mut in_flight) = (vec![0; num_stages], vec![0; num_stages]);
+        for block in self.blocks.iter() {
+            let (block_queued, block_in_flight) = block.occupancy();
+
+            for i in 0..num_stages {
+                queued[i] += block_queued[i];
+                in_flight[i] += block_in_flight[i];
+            }
         }
+
+        // Whatever no other stage holds is free for the producer.
+        let held = (0..num_stages)
+            .filter(|&i| i != producer)
+            .map(|i| queued[i] + in_flight[i])
+            .sum::<usize>()
+            + in_flight[producer];
+        queued[producer] = (self.num_blocks * self.block_size).saturating_sub(held);
+
+        (0..num_stages)
+            .map(|i| {
+                let tag = Tag::try_from(i).unwrap();
+
+                StageStats {
+                    tag,
+                    block: match self.get_head(tag, 0) {
+                        // Only the thread running the stage may read a non-atomic head.
+                        HeadRef::NonAtomic(_) => None,
+                        #[cfg(feature = "dyn_heads")]
+                        HeadRef::Dyn(_) if !tag.is_atomic() => None,
+                        head => Some(head.load().get_index()),
+                    },
+                    queued: queued[i],
+                    in_flight: in_flight[i],
+                    #[cfg(feature = "metrics")]
+                    taken: self.counters[i].taken.load(Ordering::Relaxed),
+                    #[cfg(feature = "metrics")]
+                    busy: self.counters[i].busy.load(Ordering::Relaxed),
+                    #[cfg(feature = "metrics")]
+                    not_available: self.counters[i].not_available.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
     }
-}
 
-impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> FastFifoInner<Tag, Inner> {
-    fn get_head(&self, tag: Tag) -> &dyn Atomic {
-        // Safety: this pointer can be turned into a reference because I said so.
-        self.heads.as_ref().get(tag.into()).unwrap().as_ref()
+    /// Announcing ourselves before checking `CLOSED` means nobody can see a closed and quiescent
+    /// producer while we are about to claim an entry.
+    fn enter_producer(&self) -> bool {
+        if self.state.fetch_add(PRODUCER, Ordering::Acquire) & CLOSED != 0 {
+            self.exit_producer();
+            false
+        } else {
+            true
+        }
     }
 
-    #[cfg_attr(feature = "debug", instrument(skip(self, tag)))]
-    fn get_block(&self, tag: Tag) -> (Field, &Block<Tag, Inner>) {
-        let head = self.get_head(tag).load();
-        #[cfg(feature = "debug")]
-        info!(?head);
+    fn exit_producer(&self) {
+        self.state.fetch_sub(PRODUCER, Ordering::Release);
+    }
 
-        (head.clone(), &self.blocks.as_ref()[head.get_index()])
+    /// Whether the producer is shut down for good and `tag` has transformed everything it produced.
+    fn drained(&self, tag: Tag) -> bool {
+        self.is_closed()
+            && self.state.load(Ordering::Acquire) == CLOSED
+            && self.blocks.iter().all(|block| block.drained(tag))
     }
 
-    #[cfg_attr(feature = "debug", instrument(skip(self, tag)))]
-    pub fn get_entry(&self, tag: Tag) -> Result<EntryDescriptor<'_, Tag, Inner>> {
+    fn reserve(
+        &self,
+        tag: Tag,
+        handle: usize,
+        enter: impl Fn() -> bool,
+        exit: impl Fn(),
+    ) -> Result<EntryDescriptor<'_, Tag, Inner, Pairs>> {
         //         v [2].give (1)
         //         |         v [2].take (2)
         //         |         |           v [1].give (3)
@@ -105,25 +296,39 @@ impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> FastFifoInner<Tag, Inner> {
         // [Uninit, Reserved, Post_Trans, Mid_Trans, Pre_Trans, Allocated, Uninit] ->
 
         loop {
-            let (head, block) = self.get_block(tag);
+            let (head, block) = self.get_block(tag, handle);
 
-            match block.reserve_in_layer(tag) {
+            match block.reserve_in_layer(tag, handle, &enter, &exit) {
                 ReserveState::Success(entry_descriptor) => {
-                    #[cfg(feature = "debug")]
-                    info!(
-                        "Success, block[{}][{}]",
-                        head.get_index(),
-                        entry_descriptor.index,
+                    fifo_event!(
+                        self,
+                        TRACE,
+                        stage = ?tag,
+                        block = head.get_index(),
+                        index = entry_descriptor.index,
+                        "entry taken"
                     );
                     break Ok(entry_descriptor);
                 }
                 ReserveState::NotAvailable => {
+                    fifo_event!(self, TRACE, stage = ?tag, block = head.get_index(), "not available");
                     break Err(Error::NotAvailable);
                 }
                 ReserveState::Busy => {
+                    fifo_event!(
+                        self,
+                        DEBUG,
+                        stage = ?tag,
+                        block = head.get_index(),
+                        "busy, the chased stage is still writing"
+                    );
                     break Err(Error::Busy);
                 }
-                ReserveState::BlockDone => match self.advance_head(head, tag) {
+                ReserveState::Closed => {
+                    fifo_event!(self, TRACE, stage = ?tag, "closed");
+                    break Err(Error::Closed);
+                }
+                ReserveState::BlockDone => match self.advance_head(head, tag, handle) {
                     AdvanceHeadStatus::Busy => {
                         break Err(Error::Busy);
                     }
@@ -135,20 +340,14 @@ impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> FastFifoInner<Tag, Inner> {
         }
     }
 
-    #[cfg_attr(feature = "debug", instrument(skip(self, tag)))]
-    fn advance_head(&self, head: Field, tag: Tag) -> AdvanceHeadStatus {
+    fn advance_head(&self, head: Field, tag: Tag, handle: usize) -> AdvanceHeadStatus {
+        let next = (head.get_index() + 1) % self.num_blocks;
         let (next_current, next_chasing) =
-            self.blocks.as_ref()[(head.get_index() + 1) % self.num_blocks].get_current_chasing(tag);
+            self.blocks.as_ref()[next].get_current_chasing(tag, handle);
 
         let chasing_give = next_chasing.load_give();
 
-        #[cfg(feature = "debug")]
-        info!(?chasing_give);
-
-        if let AdvanceHeadStatus::Success = if chasing_give.get_index() >= self.num_blocks {
-            #[cfg(feature = "debug")]
-            info!("Success (early)");
-
+        if let AdvanceHeadStatus::Success = if chasing_give.get_index() >= self.block_size {
             // Guaranteed to be able to advance to next block, early escape
             AdvanceHeadStatus::Success
         } else {
@@ -157,61 +356,60 @@ impl<Tag: FifoTag, Inner: IndexedDrop<Tag>> FastFifoInner<Tag, Inner> {
             // is at least `give.index`, that is, chasing_give.index <= chasing_take.index is always true.
             let chasing_take = next_chasing.load_take();
 
-            #[cfg(feature = "debug")]
-            info!(?chasing_take);
-
             if chasing_take.get_index() > chasing_give.get_index() {
-                #[cfg(feature = "debug")]
-                warn!("Busy");
-
                 // The pair we are chasing is currently writing
                 // We do not know in which slot they are writing
                 // We must assume that every entry is garbage
                 AdvanceHeadStatus::Busy
             } else {
-                #[cfg(feature = "debug")]
-                info!("Success");
-
                 // MUST be chasing_take == chasing_give, the valid state to advance this head
                 AdvanceHeadStatus::Success
             }
         } {
             // Success, update atomics in nblk and cached head
 
-            let next_current_give = next_current.load_give();
-            #[cfg(feature = "debug")]
-            info!(?next_current_give);
+            let head_vsn_inc_add = head.version_inc_add(1);
 
+            // The next block is on whichever lap the head is on once it points at that block
             let new_next_current = Field::from(FieldConfig {
                 index_max: self.block_size,
-                version: head.get_version()
-                    + if next_current_give.get_index() >= next_current_give.get_index_max() {
-                        1
-                    } else {
-                        0
-                    },
+                version: head_vsn_inc_add.get_version(),
                 index: 0,
             });
-            #[cfg(feature = "debug")]
-            info!(?new_next_current);
 
-            let (old_give, old_take) = next_current.fetch_max_both(new_next_current);
-            #[cfg(feature = "debug")]
-            info!(?old_give, ?old_take);
-            let (_, _) = (old_give, old_take);
-
-            let head_vsn_inc_add = head.version_inc_add(1);
-            #[cfg(feature = "debug")]
-            info!(?head_vsn_inc_add);
+            let (_, old_take) = next_current.fetch_max_both(new_next_current);
+            if old_take.get_version() < new_next_current.get_version() {
+                fifo_event!(
+                    self,
+                    DEBUG,
+                    stage = ?tag,
+                    block = next,
+                    lap = new_next_current.get_version(),
+                    "block entered a new lap"
+                );
+            }
 
-            let old_head = self.get_head(tag).max(head_vsn_inc_add);
-            #[cfg(feature = "debug")]
-            info!(?old_head);
-            let _ = old_head;
+            self.get_head(tag, handle).max(head_vsn_inc_add);
+            fifo_event!(
+                self,
+                DEBUG,
+                stage = ?tag,
+                from = head.get_index(),
+                to = next,
+                "head advanced"
+            );
 
             // Forward success
             AdvanceHeadStatus::Success
         } else {
+            fifo_event!(
+                self,
+                DEBUG,
+                stage = ?tag,
+                block = next,
+                "head blocked, the chased stage is still writing"
+            );
+
             // Forward busy
             AdvanceHeadStatus::Busy
         }
@@ -1,3 +1,5 @@
+#[cfg(feature = "dyn_heads")]
+use crate::config::{FifoTag, TaggedHeads};
 use crate::{atom_pair::Line128, field::Field};
 
 #[cfg(not(loom))]
@@ -29,9 +31,13 @@ impl From<Field> for NonAtomicHead {
 impl Atomic for NonAtomicHead {
     fn load(&self) -> Field {
         #[cfg(not(loom))]
-        unsafe { self.0.get().read() }
+        unsafe {
+            self.0.get().read()
+        }
         #[cfg(loom)]
-        unsafe { self.0.get().deref().clone() }
+        unsafe {
+            *self.0.get().deref()
+        }
     }
 
     fn max(&self, rhs: Field) -> Field {
@@ -81,3 +87,94 @@ impl Atomic for AtomicHead {
         )
     }
 }
+
+/// The heads of a `broadcast` layer, every handle goes through the blocks on its own.
+pub struct BroadcastHeads(Box<[AtomicHead]>);
+
+impl BroadcastHeads {
+    pub fn new(num_blocks: usize, handles: usize) -> Self {
+        Self(
+            (0..handles)
+                .map(|_| Field::from_parts(num_blocks, 0, 0).into())
+                .collect(),
+        )
+    }
+
+    pub fn handle(&self, handle: usize) -> HeadRef<'_> {
+        (&self.0[handle]).into()
+    }
+}
+
+/// The heads behind `Box<dyn Atomic>`, the way the fifo kept them before `generate_union!` typed them.
+///
+/// Only the baseline `variadic_single_perf` compares the generated heads against, `broadcast` stages are not
+/// supported.
+#[cfg(feature = "dyn_heads")]
+pub struct DynHeads(Box<[Box<dyn Atomic>]>);
+
+#[cfg(feature = "dyn_heads")]
+impl<Tag: FifoTag> TaggedHeads<Tag> for DynHeads {
+    fn new(num_blocks: usize, handles: usize) -> Self {
+        assert_eq!(handles, 1, "DynHeads has no broadcast stages");
+        Self(
+            (0..Tag::num_transformations())
+                .map(|i| {
+                    let field = Field::from_parts(num_blocks, 0, 0);
+
+                    if Tag::try_from(i).unwrap().is_atomic() {
+                        Box::new(AtomicHead::from(field)) as Box<dyn Atomic>
+                    } else {
+                        Box::new(NonAtomicHead::from(field))
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn get(&self, tag: Tag, _handle: usize) -> HeadRef<'_> {
+        HeadRef::Dyn(self.0[tag.into()].as_ref())
+    }
+}
+
+/// Statically dispatched reference to the head of a single layer, see `TaggedHeads::get`.
+#[derive(Clone, Copy)]
+pub enum HeadRef<'a> {
+    Atomic(&'a AtomicHead),
+    NonAtomic(&'a NonAtomicHead),
+    #[cfg(feature = "dyn_heads")]
+    Dyn(&'a dyn Atomic),
+}
+
+impl<'a> From<&'a AtomicHead> for HeadRef<'a> {
+    fn from(value: &'a AtomicHead) -> Self {
+        Self::Atomic(value)
+    }
+}
+
+impl<'a> From<&'a NonAtomicHead> for HeadRef<'a> {
+    fn from(value: &'a NonAtomicHead) -> Self {
+        Self::NonAtomic(value)
+    }
+}
+
+impl Atomic for HeadRef<'_> {
+    #[inline]
+    fn load(&self) -> Field {
+        match self {
+            Self::Atomic(head) => head.load(),
+            Self::NonAtomic(head) => head.load(),
+            #[cfg(feature = "dyn_heads")]
+            Self::Dyn(head) => head.load(),
+        }
+    }
+
+    #[inline]
+    fn max(&self, rhs: Field) -> Field {
+        match self {
+            Self::Atomic(head) => head.max(rhs),
+            Self::NonAtomic(head) => head.max(rhs),
+            #[cfg(feature = "dyn_heads")]
+            Self::Dyn(head) => head.max(rhs),
+        }
+    }
+}
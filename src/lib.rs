@@ -2,21 +2,55 @@
 
 extern crate self as fastfifo;
 
-pub use fastfifoprocmacro::generate_union;
 pub use crate::error::Error;
+pub use fastfifoprocmacro::{generate_union, pipeline};
 
 pub mod mpmc;
-// pub mod two_buff;
 
+pub mod boundary;
 pub mod config;
 pub mod entry_descriptor;
 pub mod error;
+#[cfg(feature = "metrics")]
+pub mod export;
 pub mod fifo;
+pub mod select;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Keeps its items only if this crate is built with `metrics`, generated code cannot check our features itself.
+#[doc(hidden)]
+#[cfg(feature = "metrics")]
+#[macro_export]
+macro_rules! __if_metrics {
+    ($($item:tt)*) => { $($item)* };
+}
+
+#[doc(hidden)]
+#[cfg(not(feature = "metrics"))]
+#[macro_export]
+macro_rules! __if_metrics {
+    ($($item:tt)*) => {};
+}
+
+/// Keeps its items only outside of loom builds, whose cells cannot lend out references.
+#[doc(hidden)]
+#[cfg(not(loom))]
+#[macro_export]
+macro_rules! __if_not_loom {
+    ($($item:tt)*) => { $($item)* };
+}
+
+#[doc(hidden)]
+#[cfg(loom)]
+#[macro_export]
+macro_rules! __if_not_loom {
+    ($($item:tt)*) => {};
+}
+
 mod atom_pair;
 mod block;
 mod field;
 mod fifo_inner;
-mod head;
\ No newline at end of file
+mod head;
+mod trace;
@@ -1,9 +1,13 @@
 use crate::field::Field;
-use std::{
-    fmt::Debug,
-    sync::atomic::{AtomicUsize, Ordering},
-};
+use std::fmt::Debug;
 
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+#[repr(C)]
 pub struct AtomicField {
     index_max: usize,
     inner: AtomicUsize,
@@ -23,10 +27,32 @@ impl AtomicField {
         Field::from_raw_parts(self.index_max, self.inner.load(order))
     }
 
+    pub fn store(&self, val: Field, order: Ordering) {
+        self.inner.store(val.get_raw_inner(), order)
+    }
+
     pub fn fetch_add(&self, val: usize, order: Ordering) -> Field {
         Field::from_raw_parts(self.index_max, self.inner.fetch_add(val, order))
     }
 
+    pub fn compare_exchange(
+        &self,
+        current: Field,
+        new: Field,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Field, Field> {
+        self.inner
+            .compare_exchange(
+                current.get_raw_inner(),
+                new.get_raw_inner(),
+                success,
+                failure,
+            )
+            .map(|inner| Field::from_raw_parts(self.index_max, inner))
+            .map_err(|inner| Field::from_raw_parts(self.index_max, inner))
+    }
+
     pub fn fetch_max(&self, val: Field, order: Ordering) -> Field {
         Field::from_raw_parts(
             self.index_max,
@@ -1,8 +1,18 @@
-use crate::{atom_pair::Line128, field::FieldConfig, mpmc::fifo_inner::FifoIndex};
+use crate::{atom_pair::Line128, field::{Field, FieldConfig}, mpmc::fifo_inner::FifoIndex};
 
 use super::{atomic::AtomicField, entries::EntryDescription};
-use std::{fmt::Debug, mem::MaybeUninit, sync::atomic::Ordering};
+#[cfg(feature = "serde")]
+use std::ops::Range;
+use std::{fmt::Debug, mem::MaybeUninit};
 
+#[cfg(not(loom))]
+use std::sync::atomic::{fence, Ordering};
+
+#[cfg(loom)]
+use loom::sync::atomic::{fence, Ordering};
+
+/// `repr(C)` so a [`persist`](super::persist)ed block keeps its layout across runs.
+#[repr(C)]
 pub struct Block<T> {
     pub(crate) allocated: Line128<AtomicField>,
     pub(crate) committed: Line128<AtomicField>,
@@ -12,82 +22,113 @@ pub struct Block<T> {
     pub(crate) entries: *mut [MaybeUninit<T>],
 }
 
-pub enum AllocState<'a, T> {
-    Allocated(EntryDescription<'a, T>),
+pub enum AllocState<R> {
+    Allocated(R),
     BlockDone,
 }
 
-pub enum ReserveState<'a, T> {
-    Reserved(EntryDescription<'a, T>),
+impl<R> AllocState<R> {
+    pub fn map<U>(self, f: impl FnOnce(R) -> U) -> AllocState<U> {
+        match self {
+            AllocState::Allocated(allocated) => AllocState::Allocated(f(allocated)),
+            AllocState::BlockDone => AllocState::BlockDone,
+        }
+    }
+}
+
+pub enum ReserveState<R> {
+    Reserved(R),
     NoEntry,
     NotAvailable,
     BlockDone(usize),
 }
 
+impl<R> ReserveState<R> {
+    pub fn map<U>(self, f: impl FnOnce(R) -> U) -> ReserveState<U> {
+        match self {
+            ReserveState::Reserved(reserved) => ReserveState::Reserved(f(reserved)),
+            ReserveState::NoEntry => ReserveState::NoEntry,
+            ReserveState::NotAvailable => ReserveState::NotAvailable,
+            ReserveState::BlockDone(version) => ReserveState::BlockDone(version),
+        }
+    }
+}
+
 impl<T> Block<T> {
     pub fn new(block_size: usize) -> Self {
-        Self {
-            allocated: AtomicField::new(FieldConfig {
-                index_max: block_size,
-                version: 0,
-                index: 0,
-            }).into(),
-            committed: AtomicField::new(FieldConfig {
-                index_max: block_size,
-                version: 0,
-                index: 0,
-            }).into(),
-            reserved: AtomicField::new(FieldConfig {
-                index_max: block_size,
-                version: 0,
-                index: 0,
-            }).into(),
-            consumed: AtomicField::new(FieldConfig {
-                index_max: block_size,
-                version: 0,
-                index: 0,
-            }).into(),
-            entries: Box::into_raw({
-                let mut vec = Vec::with_capacity(block_size);
-                vec.extend((0..block_size).map(|_| MaybeUninit::uninit()));
-                vec.into_boxed_slice()
-            }),
-            block_size,
-        }
+        Self::with_entries(block_size, 0, Self::alloc_entries(block_size))
     }
 
     pub fn new_full(block_size: usize) -> Self {
+        Self::with_entries(block_size, block_size, Self::alloc_entries(block_size))
+    }
+
+    fn alloc_entries(block_size: usize) -> *mut [MaybeUninit<T>] {
+        Box::into_raw({
+            let mut vec = Vec::with_capacity(block_size);
+            vec.extend((0..block_size).map(|_| MaybeUninit::uninit()));
+            vec.into_boxed_slice()
+        })
+    }
+
+    /// A block over `entries` with every counter at `index`, either 0 for an empty block or `block_size` for a
+    /// full one. The caller keeps owning `entries`.
+    pub fn with_entries(block_size: usize, index: usize, entries: *mut [MaybeUninit<T>]) -> Self {
         Self {
             allocated: AtomicField::new(FieldConfig {
                 index_max: block_size,
                 version: 0,
-                index: block_size,
+                index,
             }).into(),
             committed: AtomicField::new(FieldConfig {
                 index_max: block_size,
                 version: 0,
-                index: block_size,
+                index,
             }).into(),
             reserved: AtomicField::new(FieldConfig {
                 index_max: block_size,
                 version: 0,
-                index: block_size,
+                index,
             }).into(),
             consumed: AtomicField::new(FieldConfig {
                 index_max: block_size,
                 version: 0,
-                index: block_size,
+                index,
             }).into(),
-            entries: Box::into_raw({
-                let mut vec = Vec::with_capacity(block_size);
-                vec.extend((0..block_size).map(|_| MaybeUninit::uninit()));
-                vec.into_boxed_slice()
-            }),
+            entries,
             block_size,
         }
     }
 
-    pub fn allocate_entry(&self, block_idx: usize) -> AllocState<'_, T> {
+    /// Marks a block built by `new_full` as consumed in `version`, nobody may use it yet.
+    pub fn set_consumed(&self, version: usize) {
+        let full = FieldConfig {
+            index_max: self.block_size,
+            version,
+            index: self.block_size,
+        }.into();
+
+        self.allocated.fetch_max(full, Ordering::Relaxed);
+        self.committed.fetch_max(full, Ordering::Relaxed);
+        self.reserved.fetch_max(full, Ordering::Relaxed);
+        self.consumed.fetch_max(full, Ordering::Relaxed);
+    }
+
+    /// Empties a block nobody uses anymore for its next `version`.
+    pub fn set_empty(&self, version: usize) {
+        let empty = FieldConfig {
+            index_max: self.block_size,
+            version,
+            index: 0,
+        }.into();
+
+        self.allocated.fetch_max(empty, Ordering::Relaxed);
+        self.committed.fetch_max(empty, Ordering::Relaxed);
+        self.reserved.fetch_max(empty, Ordering::Relaxed);
+        self.consumed.fetch_max(empty, Ordering::Relaxed);
+    }
+
+    pub fn allocate_entry(&self, block_idx: usize) -> AllocState<EntryDescription<'_, T>> {
         if self.allocated.load(Ordering::Relaxed).get_index() >= self.block_size {
             AllocState::BlockDone
         } else {
@@ -97,7 +138,7 @@ impl<T> Block<T> {
                 AllocState::BlockDone
             } else {
                 AllocState::Allocated(EntryDescription {
-                    block: &self,
+                    block: self,
                     index: FifoIndex {
                         block_idx,
                         sub_block_idx: old,
@@ -108,7 +149,57 @@ impl<T> Block<T> {
         }
     }
 
-    pub fn reserve_entry(&self) -> ReserveState<'_, T> {
+    /// Allocates up to `max` consecutive entries at once, as many as are left in the block.
+    ///
+    /// Returns where the allocated entries start along with how many there are.
+    pub fn allocate_entries(&self, max: usize) -> AllocState<(usize, usize)> {
+        loop {
+            let allocated = self.allocated.load(Ordering::Relaxed);
+
+            if allocated.get_index() >= self.block_size {
+                break AllocState::BlockDone;
+            }
+            // Never past the end of the block, unlike `allocate_entry`.
+            let count = max.min(self.block_size - allocated.get_index());
+            if self
+                .allocated
+                .compare_exchange(
+                    allocated,
+                    allocated.overflowing_add(count),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                break AllocState::Allocated((allocated.get_index(), count));
+            }
+        }
+    }
+
+    pub fn reserve_entry(&self) -> ReserveState<EntryDescription<'_, T>> {
+        self.reserve_entries(|_| 1).map(|(reserved, _)| self.reserved_entry(reserved))
+    }
+
+    /// [`Self::reserve_entry`] for a thief, see [`Self::steal_entries`].
+    pub fn steal_entry(&self) -> ReserveState<EntryDescription<'_, T>> {
+        self.steal_entries(|_| 1).map(|(reserved, _)| self.reserved_entry(reserved))
+    }
+
+    fn reserved_entry(&self, reserved: Field) -> EntryDescription<'_, T> {
+        EntryDescription {
+            block: self,
+            index: FifoIndex {
+                block_idx: 0,
+                sub_block_idx: reserved.get_index(),
+            },
+            version: reserved.get_version(),
+        }
+    }
+
+    /// Reserves `count(available)` consecutive entries at once, out of the `available` committed ones.
+    ///
+    /// Returns where the reserved entries start along with how many there are.
+    pub fn reserve_entries(&self, count: impl Fn(usize) -> usize) -> ReserveState<(Field, usize)> {
         loop {
             let reserved = self.reserved.load(Ordering::Relaxed);
 
@@ -125,19 +216,77 @@ impl<T> Block<T> {
                         break ReserveState::NotAvailable;
                     }
                 }
+                let count = count(committed.get_index() - reserved.get_index());
+                if self
+                    .reserved
+                    .fetch_max(reserved.overflowing_add(count), Ordering::Relaxed)
+                    == reserved
+                {
+                    break ReserveState::Reserved((reserved, count));
+                }
+            } else {
+                break ReserveState::BlockDone(reserved.get_version());
+            }
+        }
+    }
+
+    /// [`Self::reserve_entries`] racing the owner of a [`Worker`](super::stealing::Worker) taking entries
+    /// back from the end of the block.
+    ///
+    /// The owner may take back any entry past the reserved ones, so a single reservation of several entries
+    /// could hold some it took meanwhile. Entries are reserved one at a time instead, like crossbeam's deque
+    /// steals batches, and the batch ends early once the next entry is gone.
+    pub fn steal_entries(&self, count: impl Fn(usize) -> usize) -> ReserveState<(Field, usize)> {
+        let (start, available) = match self.steal_next(None) {
+            ReserveState::Reserved(reserved) => reserved,
+            ReserveState::NoEntry => return ReserveState::NoEntry,
+            ReserveState::NotAvailable => return ReserveState::NotAvailable,
+            ReserveState::BlockDone(version) => return ReserveState::BlockDone(version),
+        };
+        let count = count(available);
+        let mut stolen = 1;
+
+        while stolen < count {
+            match self.steal_next(Some(start.overflowing_add(stolen))) {
+                ReserveState::Reserved(_) => stolen += 1,
+                _ => break,
+            }
+        }
+        ReserveState::Reserved((start, stolen))
+    }
+
+    /// Reserves a single entry, the one at `next` if given. Returns it along with how many were available.
+    fn steal_next(&self, next: Option<Field>) -> ReserveState<(Field, usize)> {
+        loop {
+            let reserved = self.reserved.load(Ordering::Relaxed);
+
+            if next.is_some_and(|next| next != reserved) {
+                // Another thief reserved the entry after ours.
+                break ReserveState::NoEntry;
+            }
+            if reserved.get_index() < self.block_size {
+                // Pairs with the fence in `FastFifoInner::take_back`: either the owner sees this
+                // reservation or this thief sees the entry taken back.
+                fence(Ordering::SeqCst);
+                let committed = self.committed.load(Ordering::Acquire);
+
+                // The owner may have taken back entries down to the reserved ones.
+                if reserved.get_index() >= committed.get_index() {
+                    break ReserveState::NoEntry;
+                }
+                if committed.get_index() != self.block_size {
+                    let allocated = self.allocated.load(Ordering::Relaxed);
+                    if allocated.get_index() != committed.get_index() {
+                        break ReserveState::NotAvailable;
+                    }
+                }
                 if self
                     .reserved
                     .fetch_max(reserved.overflowing_add(1), Ordering::Relaxed)
                     == reserved
                 {
-                    break ReserveState::Reserved(EntryDescription {
-                        block: &self,
-                        index: FifoIndex {
-                            block_idx: 0,
-                            sub_block_idx: reserved.get_index(),
-                        },
-                        version: reserved.get_version(),
-                    });
+                    let available = committed.get_index() - reserved.get_index();
+                    break ReserveState::Reserved((reserved, available));
                 }
             } else {
                 break ReserveState::BlockDone(reserved.get_version());
@@ -145,6 +294,25 @@ impl<T> Block<T> {
         }
     }
 
+    /// Entries committed and not reserved yet, `None` while a producer still writes in this block.
+    ///
+    /// Only meaningful while nobody else uses the block.
+    #[cfg(feature = "serde")]
+    pub fn queued(&self) -> Option<Range<usize>> {
+        let allocated = self.allocated.load(Ordering::Relaxed);
+        let committed = self.committed.load(Ordering::Relaxed);
+        let reserved = self.reserved.load(Ordering::Relaxed);
+
+        if allocated.get_index().min(self.block_size) != committed.get_index() {
+            return None;
+        }
+        // The consumer head has not entered the lap the producer head filled this block in.
+        if reserved.get_version() != committed.get_version() {
+            return Some(0..committed.get_index());
+        }
+        Some(reserved.get_index()..committed.get_index())
+    }
+
     /// Drop the valid values inside self.
     pub(crate) fn drop(&mut self) {
         let Block {
@@ -190,49 +358,8 @@ impl<T> Block<T> {
     }
 }
 
-impl<T: Debug> Debug for Block<T> {
+impl<T> Debug for Block<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // f.debug_struct("Block")
-        //     .field("allocated", &self.allocated)
-        //     .field("committed", &self.committed)
-        //     .field("reserved", &self.reserved)
-        //     .field("consumed", &self.consumed)
-        //     .finish()?;
-
-        //         v Consumed (1)
-        //         |         v Reserved (2)
-        //         |         |  v Committed (3)
-        //         |         |  |          v Allocated (4)
-        // [Uninit, Reserved, 0, Allocated, Uninit]
-
-        let allocated = self.allocated.load(Ordering::Relaxed).get_index();
-        let committed = self.committed.load(Ordering::Relaxed).get_index();
-        let consumed = self.consumed.load(Ordering::Relaxed).get_index();
-        let reserved = self.reserved.load(Ordering::Relaxed).get_index();
-
-        f.debug_list()
-            .entries(unsafe { &*self.entries }.iter().enumerate().map(|(i, t)| {
-                if i >= allocated
-                    || (allocated >= self.block_size
-                        && committed >= self.block_size
-                        && consumed >= self.block_size
-                        && reserved >= self.block_size)
-                {
-                    format!("Uninit")
-                } else if i >= committed {
-                    format!("Allocated")
-                } else if i >= reserved
-                    || (consumed == self.block_size && reserved == self.block_size)
-                {
-                    format!("{:?}", unsafe {
-                        (t as *const MaybeUninit<T>).read().assume_init_read()
-                    })
-                } else if i >= consumed {
-                    format!("Reserved")
-                } else {
-                    format!("Uninit")
-                }
-            }))
-            .finish()
+        f.debug_list().entries(self.inspect()).finish()
     }
 }
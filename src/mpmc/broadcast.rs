@@ -0,0 +1,319 @@
+//! A fifo handing every item to every subscriber, like the ring of a Disruptor.
+//!
+//! Producers take entries from the blocks of a ring exactly like for [`FastFifo`](super::FastFifo). There is no
+//! shared consumer head: every [`Subscriber`] has its own cursor and clones the items out of the blocks, so an
+//! item stays in its block until the producer head comes around again. Before reusing a block the producer head
+//! waits for the slowest subscriber to have left it in the lap before, and drops the items in it.
+//!
+//! A fifo built with [`FastFifo::detaching`] does not wait for a subscriber a whole lap behind, it detaches it
+//! instead. Moving the producer head to another block and (un)subscribing happen under a lock, once every
+//! `block_size` entries.
+
+use super::{
+    Error, Result,
+    atomic::AtomicField,
+    block::{AllocState, Block},
+    entries::ProducingEntry,
+};
+use crate::{field::Field, trace::fifo_event};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+
+/// Set in a cursor while its subscriber clones an item, the block it reads cannot be taken away then.
+const READING: usize = 1;
+/// A cursor the producer head went past, it never moves again.
+const DETACHED: usize = usize::MAX;
+
+/// An mpmc ring where every item goes to every [`Subscriber`], clones share the fifo.
+///
+/// Items pushed before a subscriber subscribed are not handed to it.
+pub struct FastFifo<T>(Arc<BroadcastInner<T>>);
+
+/// Receives a clone of every item pushed into its [`FastFifo`] after it subscribed.
+pub struct Subscriber<T> {
+    inner: Arc<BroadcastInner<T>>,
+    /// Sequence number of the block to read from, counted over all laps, shifted left past [`READING`].
+    cursor: Arc<AtomicUsize>,
+    /// Entry to read next in that block.
+    index: usize,
+}
+
+struct BroadcastInner<T> {
+    phead: AtomicField,
+    block_size: usize,
+    blocks: Box<[Block<T>]>,
+    /// Detach subscribers a lap behind instead of reporting `Error::Full`.
+    detach: bool,
+    /// Cursors of the subscribers still attached.
+    subscribers: Mutex<Vec<Arc<AtomicUsize>>>,
+    #[cfg(feature = "debug")]
+    span: tracing::Span,
+}
+
+#[rustfmt::skip]
+unsafe impl<T: Send + Sync> Send for BroadcastInner<T> {}
+#[rustfmt::skip]
+unsafe impl<T: Send + Sync> Sync for BroadcastInner<T> {}
+
+impl<T> BroadcastInner<T> {
+    fn new(num_blocks: usize, block_size: usize, detach: bool) -> Self {
+        Self {
+            phead: AtomicField::new(Field::from_parts(num_blocks, 0, 0)),
+            block_size,
+            blocks: (0..num_blocks).map(|_| Block::new(block_size)).collect(),
+            detach,
+            subscribers: Mutex::new(Vec::new()),
+            #[cfg(feature = "debug")]
+            span: crate::trace::fifo_span("broadcast", num_blocks, block_size),
+        }
+    }
+
+    /// The block `head` is at, counted over all laps.
+    fn sequence(&self, head: Field) -> usize {
+        head.get_version() * self.blocks.len() + head.get_index()
+    }
+
+    fn get_producer_entry(&self) -> Result<ProducingEntry<'_, T>> {
+        loop {
+            let ph = self.phead.load(Ordering::Acquire);
+
+            match self.blocks[ph.get_index()].allocate_entry(ph.get_index()) {
+                AllocState::Allocated(entry_description) => {
+                    fifo_event!(
+                        self,
+                        TRACE,
+                        block = ph.get_index(),
+                        index = entry_description.index.sub_block_idx,
+                        "producer entry allocated"
+                    );
+                    break Ok(ProducingEntry(entry_description));
+                }
+                AllocState::BlockDone => self.advance_phead(ph)?,
+            }
+        }
+    }
+
+    /// Moves the producer head past `ph` once every subscriber left the next block in the lap before.
+    fn advance_phead(&self, ph: Field) -> Result<()> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+
+        if self.phead.load(Ordering::Relaxed) != ph {
+            return Ok(());
+        }
+
+        let num_blocks = self.blocks.len();
+        let next = if ph.get_index() + 1 < num_blocks {
+            Field::from_parts(num_blocks, ph.get_version(), ph.get_index() + 1)
+        } else {
+            Field::from_parts(num_blocks, ph.get_version() + 1, 0)
+        };
+        let block = &self.blocks[next.get_index()];
+
+        if let Some(previous) = self.sequence(next).checked_sub(num_blocks) {
+            let mut i = 0;
+            while i < subscribers.len() {
+                // Acquire: the subscriber's clones of the items in the block happen before it left the block.
+                let cursor = subscribers[i].load(Ordering::Acquire);
+
+                if cursor >> 1 > previous {
+                    i += 1;
+                    continue;
+                }
+                if !self.detach {
+                    fifo_event!(
+                        self,
+                        DEBUG,
+                        block = next.get_index(),
+                        "push full, a subscriber lags"
+                    );
+                    return Err(Error::Full);
+                }
+                if cursor & READING != 0
+                    || subscribers[i]
+                        .compare_exchange(cursor, DETACHED, Ordering::Acquire, Ordering::Relaxed)
+                        .is_err()
+                {
+                    fifo_event!(
+                        self,
+                        DEBUG,
+                        block = next.get_index(),
+                        "push busy, a subscriber is reading"
+                    );
+                    return Err(Error::Busy);
+                }
+                subscribers.swap_remove(i);
+                fifo_event!(self, DEBUG, block = next.get_index(), "subscriber detached");
+            }
+
+            if block.committed.load(Ordering::Acquire).get_index() < self.block_size {
+                fifo_event!(self, DEBUG, "push busy, a producer is still writing");
+                return Err(Error::Busy);
+            }
+            // Nobody reads the items of the lap before anymore.
+            unsafe { &mut *block.entries }
+                .iter_mut()
+                .for_each(|entry| unsafe { entry.assume_init_drop() });
+        }
+
+        block.set_empty(next.get_version());
+        self.phead.fetch_max(next, Ordering::Release);
+
+        fifo_event!(
+            self,
+            DEBUG,
+            from = ph.get_index(),
+            to = next.get_index(),
+            "producer head advanced"
+        );
+        Ok(())
+    }
+
+    fn subscribe(self: &Arc<Self>) -> Subscriber<T> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let ph = self.phead.load(Ordering::Relaxed);
+        let allocated = self.blocks[ph.get_index()]
+            .allocated
+            .load(Ordering::Relaxed)
+            .get_index();
+
+        let (sequence, index) = if allocated < self.block_size {
+            (self.sequence(ph), allocated)
+        } else {
+            (self.sequence(ph) + 1, 0)
+        };
+        let cursor = Arc::new(AtomicUsize::new(sequence << 1));
+        subscribers.push(cursor.clone());
+
+        fifo_event!(self, DEBUG, subscribers = subscribers.len(), "subscribed");
+        Subscriber {
+            inner: self.clone(),
+            cursor,
+            index,
+        }
+    }
+
+    /// Clones the item at `index` of the block with the `sequence` number, moving on to the next block once
+    /// every item in it was read. Returns the block to read next.
+    fn read(&self, mut sequence: usize, index: &mut usize) -> (usize, Result<T>)
+    where
+        T: Clone,
+    {
+        let num_blocks = self.blocks.len();
+        let block = &self.blocks[sequence % num_blocks];
+        let committed = block.committed.load(Ordering::Acquire);
+
+        if committed.get_version() != sequence / num_blocks || committed.get_index() <= *index {
+            return (sequence, Err(Error::Empty));
+        }
+        if committed.get_index() != self.block_size {
+            let allocated = block.allocated.load(Ordering::Relaxed);
+            if allocated.get_index() != committed.get_index() {
+                fifo_event!(self, DEBUG, "recv busy, a producer is still writing");
+                return (sequence, Err(Error::Busy));
+            }
+        }
+
+        let val = unsafe { (*block.entries)[*index].assume_init_ref() }.clone();
+        *index += 1;
+        if *index == self.block_size {
+            (sequence, *index) = (sequence + 1, 0);
+        }
+        (sequence, Ok(val))
+    }
+}
+
+impl<T> Drop for BroadcastInner<T> {
+    fn drop(&mut self) {
+        self.blocks.iter_mut().for_each(|block| {
+            Block::drop(block);
+            drop(unsafe { Box::from_raw(block.entries) });
+        });
+    }
+}
+
+impl<T> Clone for FastFifo<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> FastFifo<T> {
+    /// Producers get `Error::Full` while a subscriber is a whole lap behind.
+    pub fn new(num_blocks: usize, block_size: usize) -> Self {
+        Self(Arc::new(BroadcastInner::new(num_blocks, block_size, false)))
+    }
+
+    /// Producers detach a subscriber a whole lap behind instead of waiting for it.
+    ///
+    /// A subscriber in the middle of cloning an item is not detached, producers get `Error::Busy` then.
+    pub fn detaching(num_blocks: usize, block_size: usize) -> Self {
+        Self(Arc::new(BroadcastInner::new(num_blocks, block_size, true)))
+    }
+
+    pub fn subscribe(&self) -> Subscriber<T> {
+        self.0.subscribe()
+    }
+
+    /// Subscribers that are neither dropped nor detached.
+    pub fn subscribers(&self) -> usize {
+        self.0.subscribers.lock().unwrap().len()
+    }
+
+    pub fn try_get_producer_entry(&self) -> Result<ProducingEntry<'_, T>> {
+        self.0.get_producer_entry()
+    }
+
+    pub fn push_in_place<F: FnOnce(*mut T)>(&self, producer: F) -> Result<()> {
+        self.try_get_producer_entry()
+            .map(|mut entry| entry.produce_t_in_place(producer))
+    }
+
+    pub fn push(&self, val: T) -> Result<()> {
+        self.push_in_place(|ptr| unsafe { ptr.write(val) })
+    }
+}
+
+impl<T: Clone> Subscriber<T> {
+    /// Returns `Error::Detached` once a producer detached this subscriber.
+    pub fn try_recv(&mut self) -> Result<T> {
+        let cursor = self.cursor.load(Ordering::Relaxed);
+
+        if cursor == DETACHED
+            || self.inner.detach
+                && self
+                    .cursor
+                    .compare_exchange(
+                        cursor,
+                        cursor | READING,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+        {
+            return Err(Error::Detached);
+        }
+
+        let (sequence, val) = self.inner.read(cursor >> 1, &mut self.index);
+        // Release: producers reuse the blocks before `sequence` once they see this.
+        self.cursor.store(sequence << 1, Ordering::Release);
+        val
+    }
+}
+
+impl<T> Subscriber<T> {
+    pub fn is_detached(&self) -> bool {
+        self.cursor.load(Ordering::Relaxed) == DETACHED
+    }
+}
+
+impl<T> Drop for Subscriber<T> {
+    fn drop(&mut self) {
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|cursor| !Arc::ptr_eq(cursor, &self.cursor));
+    }
+}
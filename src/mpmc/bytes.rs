@@ -0,0 +1,255 @@
+//! Variable-length byte records framed into the blocks of a [`FastFifo`](super::FastFifo).
+//!
+//! The entries of a block are bytes, so its `allocated`/`committed`/`reserved`/`consumed` counters are byte
+//! offsets. A record takes a frame of a length prefix followed by its bytes. A producer whose frame does not fit
+//! in what is left of its block takes the rest as padding instead and moves on to the next block, consumers skip
+//! the padding. Records are written and read in place, through guards.
+
+use super::{
+    Error, Result,
+    block::{AllocState, Block, ReserveState},
+    fifo_inner::FastFifoInner,
+};
+use std::{
+    array,
+    ops::{Deref, DerefMut},
+    slice,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+};
+
+const HEADER: usize = size_of::<u32>();
+/// Length prefix of the padding ending a block, a rest too short for a prefix is padding as well.
+const PADDING: u32 = u32::MAX;
+
+/// An mpmc ring of byte records, clones share the fifo.
+#[derive(Clone)]
+pub struct ByteFifo {
+    inner: Arc<FastFifoInner<u8>>,
+    max_len: usize,
+}
+
+/// The bytes of a record being written, zeroed at first. The record becomes visible to consumers once dropped.
+pub struct ProducingRecord<'a> {
+    block: &'a Block<u8>,
+    start: usize,
+    len: usize,
+}
+
+/// The bytes of a record being read, its frame is given back once dropped.
+pub struct ConsumingRecord<'a> {
+    block: &'a Block<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl Block<u8> {
+    fn bytes(&self, start: usize) -> *mut u8 {
+        self.entries.cast::<u8>().wrapping_add(start)
+    }
+
+    /// The length prefix at `start`, loaded byte by byte.
+    ///
+    /// A consumer reads it before it may reserve the frame, so the block may be reused for its next lap and the
+    /// bytes written over meanwhile. Like in a seqlock the loads are atomic, and the consumer throws the length
+    /// away once its `fetch_max` on `reserved` fails.
+    fn header(&self, start: usize) -> u32 {
+        u32::from_le_bytes(array::from_fn(|i| {
+            unsafe { AtomicU8::from_ptr(self.bytes(start + i)) }.load(Ordering::Relaxed)
+        }))
+    }
+
+    fn set_header(&self, start: usize, len: u32) {
+        for (i, byte) in len.to_le_bytes().into_iter().enumerate() {
+            unsafe { AtomicU8::from_ptr(self.bytes(start + i)) }.store(byte, Ordering::Relaxed);
+        }
+    }
+
+    /// Allocates the frame of a record of `len` bytes, returns where the record starts.
+    ///
+    /// If the frame does not fit anymore, takes and commits the rest of the block as padding.
+    fn allocate_record(&self, len: usize) -> AllocState<usize> {
+        loop {
+            let allocated = self.allocated.load(Ordering::Relaxed);
+            let start = allocated.get_index();
+
+            if start >= self.block_size {
+                break AllocState::BlockDone;
+            }
+            // Never past the end of the block, so the offset cannot run into the version.
+            let end = (start + HEADER + len).min(self.block_size);
+            if self
+                .allocated
+                .compare_exchange(
+                    allocated,
+                    allocated.overflowing_add(end - start),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                continue;
+            }
+
+            if end - start == HEADER + len {
+                self.set_header(start, len as u32);
+                // The bytes are uninitialized in the first lap, the record hands them out as `&mut [u8]`.
+                unsafe { self.bytes(start + HEADER).write_bytes(0, len) };
+                break AllocState::Allocated(start + HEADER);
+            }
+            if end - start >= HEADER {
+                self.set_header(start, PADDING);
+            }
+            // The padding must be visible before consumers see it committed.
+            self.committed.fetch_add(end - start, Ordering::Release);
+            break AllocState::BlockDone;
+        }
+    }
+
+    /// Reserves the next record, skipping the padding. Returns where the record starts and its length.
+    fn reserve_record(&self) -> ReserveState<(usize, usize)> {
+        loop {
+            let reserved = self.reserved.load(Ordering::Relaxed);
+            let start = reserved.get_index();
+
+            if start >= self.block_size {
+                break ReserveState::BlockDone(reserved.get_version());
+            }
+            // All previous writes in this block must be visible before this load.
+            let committed = self.committed.load(Ordering::Acquire);
+
+            // The block was reused since `reserved` was loaded, its frames are gone.
+            if committed.get_version() != reserved.get_version() {
+                continue;
+            }
+            if start == committed.get_index() {
+                break ReserveState::NoEntry;
+            }
+            // Offsets only count bytes, every frame before `committed` is written once nothing else is allocated.
+            if committed.get_index() != self.block_size {
+                let allocated = self.allocated.load(Ordering::Relaxed);
+                if allocated.get_index() != committed.get_index() {
+                    break ReserveState::NotAvailable;
+                }
+            }
+
+            let len = if self.block_size - start < HEADER {
+                PADDING
+            } else {
+                self.header(start)
+            };
+            let end = if len == PADDING {
+                self.block_size
+            } else if len as usize > self.block_size - start - HEADER {
+                // Read while the block was reused, the bytes are some other frame's.
+                continue;
+            } else {
+                start + HEADER + len as usize
+            };
+
+            if self
+                .reserved
+                .fetch_max(reserved.overflowing_add(end - start), Ordering::Relaxed)
+                != reserved
+            {
+                continue;
+            }
+            if len == PADDING {
+                self.consumed.fetch_add(end - start, Ordering::Release);
+                continue;
+            }
+            break ReserveState::Reserved((start + HEADER, len as usize));
+        }
+    }
+}
+
+impl ByteFifo {
+    /// `num_blocks` blocks of `block_size` bytes, a record takes 4 more bytes than its length.
+    pub fn new(num_blocks: usize, block_size: usize) -> Self {
+        assert!(
+            block_size > HEADER && block_size - HEADER < PADDING as usize,
+            "block_size ({block_size}) must leave room for a length prefix."
+        );
+
+        Self {
+            inner: Arc::new(FastFifoInner::new(num_blocks, block_size, num_blocks)),
+            max_len: block_size - HEADER,
+        }
+    }
+
+    /// The longest record a block holds.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Returns `Error::TooLong` if `len` is over [`Self::max_len`].
+    pub fn try_get_producer_record(&self, len: usize) -> Result<ProducingRecord<'_>> {
+        if len > self.max_len {
+            return Err(Error::TooLong);
+        }
+
+        self.inner
+            .allocate(|block, _| block.allocate_record(len).map(|start| (block, start)))
+            .map(|(_, (block, start))| ProducingRecord { block, start, len })
+    }
+
+    pub fn push(&self, record: &[u8]) -> Result<()> {
+        self.try_get_producer_record(record.len())
+            .map(|mut entry| entry.copy_from_slice(record))
+    }
+
+    pub fn try_get_consumer_record(&self) -> Result<ConsumingRecord<'_>> {
+        self.inner
+            .reserve(|block| {
+                block
+                    .reserve_record()
+                    .map(|(start, len)| (block, start, len))
+            })
+            .map(|(_, (block, start, len))| ConsumingRecord { block, start, len })
+    }
+
+    pub fn pop(&self) -> Result<Vec<u8>> {
+        self.try_get_consumer_record().map(|record| record.to_vec())
+    }
+}
+
+impl Deref for ProducingRecord<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.block.bytes(self.start), self.len) }
+    }
+}
+
+impl DerefMut for ProducingRecord<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.block.bytes(self.start), self.len) }
+    }
+}
+
+impl Drop for ProducingRecord<'_> {
+    fn drop(&mut self) {
+        // All subsequent reads must be visible after this increment.
+        self.block
+            .committed
+            .fetch_add(HEADER + self.len, Ordering::Release);
+    }
+}
+
+impl Deref for ConsumingRecord<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.block.bytes(self.start), self.len) }
+    }
+}
+
+impl Drop for ConsumingRecord<'_> {
+    fn drop(&mut self) {
+        self.block
+            .consumed
+            .fetch_add(HEADER + self.len, Ordering::Release);
+    }
+}
@@ -0,0 +1,293 @@
+//! A fifo releasing items once their deadline passed, like a timing wheel.
+//!
+//! Time is cut into ticks of `slot_width`, counted from when the fifo was created. Every tick has a slot, an
+//! [`UnboundedFifo`] of the items whose deadline rounds up to it, and the slots are reused in a ring. Consumers
+//! pop from the slot of the oldest tick they did not finish yet, the cursor, once that tick began. A consumer
+//! finding the cursor's slot empty moves the cursor on, so an item is released at most one `slot_width` after its
+//! deadline. A fifo holding nothing at all has its cursor moved straight up to the current tick. Deadlines further
+//! out than the ring reaches wait in a map until the cursor gets close enough.
+//!
+//! The time comes from a [`Clock`], tests use a [`MockClock`] to move it without sleeping.
+
+use super::{Error, Result, UnboundedFifo};
+use crate::trace::fifo_event;
+use std::{
+    collections::BTreeMap,
+    iter,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Where a [`DelayFifo`] takes the current time from.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// [`Instant::now`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to, clones share the time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Arc<AtomicU64>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.elapsed
+            .fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + Duration::from_nanos(self.elapsed.load(Ordering::Relaxed))
+    }
+}
+
+/// Items released once their deadline passed, clones share the fifo.
+///
+/// `num_slots` slots of `slot_width` cover the deadlines kept in blocks, the ones further out are kept in a map
+/// under a lock instead. Pushing never fails.
+pub struct DelayFifo<T, C: Clock = SystemClock>(Arc<DelayInner<T, C>>);
+
+struct DelayInner<T, C> {
+    clock: C,
+    start: Instant,
+    slot_width: Duration,
+    /// One more than the ticks the cursor reaches ahead, the slot a tick leaves behind is emptied before the
+    /// cursor reaches the tick that reuses it.
+    slots: Box<[UnboundedFifo<(u64, T)>]>,
+    /// Oldest tick consumers did not finish yet.
+    cursor: AtomicU64,
+    /// Producers that placed an item by the cursor, by its parity. Moving the cursor waits for the ones that
+    /// placed by the old cursor.
+    pushing: [AtomicUsize; 2],
+    /// Items in the slots, counted before they land and after they leave so it is never below what the slots hold.
+    in_slots: AtomicUsize,
+    /// Items whose tick was too far ahead of the cursor to get a slot, also held while the cursor moves.
+    far: Mutex<BTreeMap<u64, Vec<T>>>,
+    #[cfg(feature = "debug")]
+    span: tracing::Span,
+}
+
+impl<T, C: Clock> Clone for DelayFifo<T, C> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> DelayFifo<T> {
+    /// `num_slots` ticks of `slot_width` ahead of the cursor get a slot, each slot is a list of blocks of
+    /// `block_size` entries.
+    pub fn new(slot_width: Duration, num_slots: usize, block_size: usize) -> Self {
+        Self::with_clock(slot_width, num_slots, block_size, SystemClock)
+    }
+}
+
+impl<T, C: Clock> DelayInner<T, C> {
+    /// The first tick that began at or after `at`.
+    fn tick_after(&self, at: Instant) -> u64 {
+        let nanos = at.saturating_duration_since(self.start).as_nanos();
+        nanos.div_ceil(self.slot_width.as_nanos()) as u64
+    }
+
+    /// The last tick that began by `at`.
+    fn tick_by(&self, at: Instant) -> u64 {
+        let nanos = at.saturating_duration_since(self.start).as_nanos();
+        (nanos / self.slot_width.as_nanos()) as u64
+    }
+
+    fn ahead(&self) -> u64 {
+        self.slots.len() as u64 - 1
+    }
+
+    fn slot(&self, tick: u64) -> &UnboundedFifo<(u64, T)> {
+        &self.slots[(tick % self.slots.len() as u64) as usize]
+    }
+
+    fn push(&self, tick: u64, val: T) {
+        loop {
+            let cursor = self.cursor.load(Ordering::SeqCst);
+            let pushing = &self.pushing[(cursor & 1) as usize];
+
+            // Announcing ourselves before checking the cursor again means the consumer moving it on either
+            // waits for us, or we see it moved.
+            pushing.fetch_add(1, Ordering::SeqCst);
+            if self.cursor.load(Ordering::SeqCst) != cursor {
+                pushing.fetch_sub(1, Ordering::Release);
+                continue;
+            }
+
+            let at = tick.max(cursor);
+            if at - cursor < self.ahead() {
+                self.in_slots.fetch_add(1, Ordering::SeqCst);
+                self.slot(at).push((at, val));
+                pushing.fetch_sub(1, Ordering::Release);
+                return;
+            }
+            pushing.fetch_sub(1, Ordering::Release);
+
+            // The cursor cannot move while we hold `far`, it may have moved past `tick` before.
+            let mut far = self.far.lock().unwrap();
+            let cursor = self.cursor.load(Ordering::SeqCst);
+            let tick = tick.max(cursor);
+            if tick - cursor < self.ahead() {
+                self.in_slots.fetch_add(1, Ordering::SeqCst);
+                self.slot(tick).push((tick, val));
+            } else {
+                far.entry(tick).or_default().push(val);
+                fifo_event!(self, DEBUG, tick, cursor, "item kept in far");
+            }
+            return;
+        }
+    }
+
+    fn pop(&self) -> Result<T> {
+        loop {
+            let cursor = self.cursor.load(Ordering::SeqCst);
+            let now = self.tick_by(self.clock.now());
+
+            if now < cursor {
+                fifo_event!(self, TRACE, "pop empty, nothing is due");
+                break Err(Error::Empty);
+            }
+
+            match self.slot(cursor).pop() {
+                Ok((tick, val)) if tick <= now => {
+                    self.in_slots.fetch_sub(1, Ordering::SeqCst);
+                    break Ok(val);
+                }
+                // The cursor moved around the ring since we loaded it.
+                Ok((tick, val)) => {
+                    self.in_slots.fetch_sub(1, Ordering::SeqCst);
+                    self.push(tick, val)
+                }
+                // Late items clamp to the cursor, it must not get ahead of the clock.
+                Err(Error::Empty) if cursor < now => self.advance(cursor, now),
+                Err(Error::Empty) => break Err(Error::Empty),
+                Err(error) => break Err(error),
+            }
+        }
+    }
+
+    /// Moves the cursor past `cursor` once its slot looked empty, up to `now` if the fifo holds nothing at all.
+    #[allow(unused_variables)]
+    fn advance(&self, cursor: u64, now: u64) {
+        let mut far = self.far.lock().unwrap();
+
+        if self.cursor.load(Ordering::SeqCst) != cursor {
+            return;
+        }
+        // An idle fifo would otherwise take the lock once per tick it missed. The cursor moves by an odd number of
+        // ticks, `pushing` tells the producers that placed by the old cursor apart by its parity.
+        let next =
+            if now > cursor + 1 && far.is_empty() && self.in_slots.load(Ordering::SeqCst) == 0 {
+                now - (now - cursor + 1) % 2
+            } else {
+                cursor + 1
+            };
+        self.cursor.store(next, Ordering::SeqCst);
+
+        // Whoever placed an item by `cursor` may still be writing it into its slot.
+        while self.pushing[(cursor & 1) as usize].load(Ordering::Acquire) != 0 {
+            thread::yield_now();
+        }
+
+        // Items that landed in the slots the cursor went past after they looked empty go to `next`, it began
+        // already.
+        let stragglers = (cursor..next.min(cursor + self.ahead()))
+            .flat_map(|tick| iter::from_fn(|| self.slot(tick).pop().ok()).collect::<Vec<_>>())
+            .map(|(tick, val)| {
+                let tick = tick.max(next);
+                self.slot(tick).push((tick, val))
+            })
+            .count();
+
+        // The tick that just came within reach gets its slot, `far` is empty when the cursor moved by more.
+        let reached = next + self.ahead() - 1;
+        if let Some(vals) = far.remove(&reached) {
+            self.in_slots.fetch_add(vals.len(), Ordering::SeqCst);
+            vals.into_iter()
+                .for_each(|val| self.slot(reached).push((reached, val)));
+        }
+
+        fifo_event!(
+            self,
+            DEBUG,
+            tick = next,
+            stragglers,
+            far = far.len(),
+            "cursor advanced"
+        );
+    }
+}
+
+impl<T, C: Clock> DelayFifo<T, C> {
+    pub fn with_clock(slot_width: Duration, num_slots: usize, block_size: usize, clock: C) -> Self {
+        assert!(!slot_width.is_zero() && num_slots != 0);
+
+        Self(Arc::new(DelayInner {
+            start: clock.now(),
+            clock,
+            slot_width,
+            slots: (0..=num_slots)
+                .map(|_| UnboundedFifo::new(block_size))
+                .collect(),
+            cursor: AtomicU64::new(0),
+            pushing: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            in_slots: AtomicUsize::new(0),
+            far: Mutex::new(BTreeMap::new()),
+            #[cfg(feature = "debug")]
+            span: crate::trace::fifo_span("delay", num_slots, block_size),
+        }))
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.0.clock
+    }
+
+    /// Releases `val` once `deadline` passed, rounded up to the next tick.
+    pub fn push_at(&self, deadline: Instant, val: T) {
+        self.0.push(self.0.tick_after(deadline), val)
+    }
+
+    pub fn push_after(&self, delay: Duration, val: T) {
+        self.push_at(self.0.clock.now() + delay, val)
+    }
+
+    /// Returns `Error::Empty` if no item is due yet.
+    pub fn pop(&self) -> Result<T> {
+        self.0.pop()
+    }
+
+    /// Items whose deadline is too far out for a slot.
+    pub fn far(&self) -> usize {
+        self.0.far.lock().unwrap().values().map(Vec::len).sum()
+    }
+}
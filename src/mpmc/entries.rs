@@ -1,7 +1,12 @@
 use crate::mpmc::fifo_inner::FifoIndex;
 
 use super::block::Block;
-use std::sync::atomic::Ordering;
+use std::{
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    ptr, slice,
+    sync::atomic::Ordering,
+};
 
 /// Think of this as an allocator giving you exactly one *mut T.
 pub struct ProducingEntry<'a, T>(pub(crate) EntryDescription<'a, T>);
@@ -48,3 +53,139 @@ impl<'a, T> EntryDescription<'a, T> {
         modifier(unsafe { &*self.block.entries }[self.index.sub_block_idx].as_ptr() as *mut T)
     }
 }
+
+/// Consecutive entries reserved at once, yields their values in order.
+///
+/// Values not taken out are dropped along with the batch, the entries only count as consumed then.
+pub struct ConsumingBatch<'a, T> {
+    pub(crate) block: &'a Block<T>,
+    pub(crate) next: usize,
+    pub(crate) end: usize,
+    pub(crate) count: usize,
+}
+
+impl<'a, T> Iterator for ConsumingBatch<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        (self.next < self.end).then(|| {
+            self.next += 1;
+            unsafe { (*self.block.entries)[self.next - 1].assume_init_read() }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.next, Some(self.end - self.next))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for ConsumingBatch<'a, T> {}
+
+/// Counts `count` entries of `block` as consumed once dropped, even if dropping one of their values panics.
+struct Consumed<'a, T> {
+    block: &'a Block<T>,
+    count: usize,
+}
+
+impl<T> Drop for Consumed<'_, T> {
+    fn drop(&mut self) {
+        self.block.consumed.fetch_add(self.count, Ordering::Release);
+    }
+}
+
+impl<'a, T> Drop for ConsumingBatch<'a, T> {
+    fn drop(&mut self) {
+        let _consumed = Consumed {
+            block: self.block,
+            count: self.count,
+        };
+        self.for_each(drop);
+    }
+}
+
+/// Consecutive entries allocated at once, to be written in place.
+///
+/// Every entry must be written before the slice is dropped, they all count as committed then. See
+/// [`FastFifo::try_reserve_slice`](super::FastFifo::try_reserve_slice).
+pub struct ProducingSlice<'a, T> {
+    pub(crate) block: &'a Block<T>,
+    pub(crate) start: usize,
+    pub(crate) count: usize,
+    pub(crate) requested: usize,
+}
+
+impl<'a, T> ProducingSlice<'a, T> {
+    /// Whether the slice has fewer entries than requested, because the producer head's block had fewer left.
+    pub fn is_short(&self) -> bool {
+        self.count < self.requested
+    }
+
+    fn as_ptr(&self) -> *mut MaybeUninit<T> {
+        self.block
+            .entries
+            .cast::<MaybeUninit<T>>()
+            .wrapping_add(self.start)
+    }
+}
+
+impl<'a, T> Deref for ProducingSlice<'a, T> {
+    type Target = [MaybeUninit<T>];
+
+    fn deref(&self) -> &[MaybeUninit<T>] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.count) }
+    }
+}
+
+impl<'a, T> DerefMut for ProducingSlice<'a, T> {
+    fn deref_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.count) }
+    }
+}
+
+impl<'a, T> Drop for ProducingSlice<'a, T> {
+    fn drop(&mut self) {
+        // All subsequent reads must be visible after this increment.
+        self.block
+            .committed
+            .fetch_add(self.count, Ordering::Release);
+    }
+}
+
+/// Consecutive entries reserved at once, read in place.
+///
+/// The values are dropped along with the slice, the entries only count as consumed then.
+pub struct ConsumingSlice<'a, T> {
+    pub(crate) block: &'a Block<T>,
+    pub(crate) start: usize,
+    pub(crate) count: usize,
+}
+
+impl<'a, T> ConsumingSlice<'a, T> {
+    fn as_ptr(&self) -> *mut T {
+        self.block.entries.cast::<T>().wrapping_add(self.start)
+    }
+}
+
+impl<'a, T> Deref for ConsumingSlice<'a, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.count) }
+    }
+}
+
+impl<'a, T> DerefMut for ConsumingSlice<'a, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.count) }
+    }
+}
+
+impl<'a, T> Drop for ConsumingSlice<'a, T> {
+    fn drop(&mut self) {
+        let _consumed = Consumed {
+            block: self.block,
+            count: self.count,
+        };
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.as_ptr(), self.count)) };
+    }
+}
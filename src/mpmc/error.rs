@@ -3,4 +3,8 @@ pub enum Error {
     Full,
     Busy,
     Empty,
+    /// A [`broadcast`](super::broadcast) subscriber the producer head went past.
+    Detached,
+    /// A [`bytes`](super::bytes) record longer than a block holds.
+    TooLong,
 }
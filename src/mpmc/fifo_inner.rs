@@ -2,17 +2,64 @@ use super::{
     Error, Result,
     atomic::AtomicField,
     block::{AllocState, Block, ReserveState},
-    entries::{ConsumingEntry, ProducingEntry},
+    entries::{
+        ConsumingBatch, ConsumingEntry, ConsumingSlice, EntryDescription, ProducingEntry,
+        ProducingSlice,
+    },
 };
-use crate::field::{Field, FieldConfig};
-use std::{fmt::Debug, mem::MaybeUninit, sync::atomic::Ordering};
+use crate::{
+    field::{Field, FieldConfig},
+    trace::fifo_event,
+};
+use std::{
+    fmt::Debug,
+    mem::MaybeUninit,
+    ptr,
+    sync::{
+        Mutex,
+        atomic::{AtomicPtr, AtomicUsize, Ordering},
+    },
+};
+
+#[cfg(not(loom))]
+use std::sync::atomic::fence;
+
+#[cfg(loom)]
+use loom::sync::atomic::fence;
+
+#[cfg(feature = "stats")]
+use super::stats::{Counters, Stats};
+
+/// Bumps one of the `stats` counters, compiling to nothing without the feature.
+macro_rules! count {
+    ($self:ident, $side:ident . $counter:ident) => {
+        count!($self, $side.$counter, 1)
+    };
+    ($self:ident, $side:ident . $counter:ident, $n:expr) => {
+        #[cfg(feature = "stats")]
+        $self
+            .stats
+            .$side
+            .$counter
+            .fetch_add($n as u64, Ordering::Relaxed);
+    };
+}
 
 pub(crate) struct FastFifoInner<T> {
     phead: AtomicField,
     chead: AtomicField,
-    num_blocks: usize,
     block_size: usize,
-    blocks: *mut [Block<T>],
+    /// One slot per block the fifo can grow to, null until `grow` allocates the block.
+    blocks: Box<[AtomicPtr<Block<T>>]>,
+    geometry: Mutex<Geometry>,
+    /// Blocks every lap a head can still be in goes through, heads advance within them without locking.
+    settled: AtomicUsize,
+    /// The blocks live in memory the fifo does not own, like a mapped file, and are left alone on drop.
+    borrowed: bool,
+    #[cfg(feature = "stats")]
+    stats: Counters,
+    #[cfg(feature = "debug")]
+    span: tracing::Span,
 }
 
 #[rustfmt::skip]
@@ -20,6 +67,28 @@ unsafe impl<T> Send for FastFifoInner<T> {}
 #[rustfmt::skip]
 unsafe impl<T> Sync for FastFifoInner<T> {}
 
+/// How many blocks each lap of the heads goes through.
+struct Geometry {
+    /// First lap going through `blocks`, the ones before go through `previous`.
+    lap: usize,
+    previous: usize,
+    blocks: usize,
+    /// Blocks allocated so far, the ones past `blocks` are waiting to be linked in.
+    allocated: usize,
+    /// Laps before this one have their number of blocks fixed.
+    sealed: usize,
+}
+
+impl Geometry {
+    fn blocks_in(&self, lap: usize) -> usize {
+        if lap < self.lap {
+            self.previous
+        } else {
+            self.blocks
+        }
+    }
+}
+
 enum AdvancePheadState {
     Success,
     NoEntry,
@@ -33,74 +102,193 @@ pub struct FifoIndex {
 }
 
 impl<T> FastFifoInner<T> {
-    pub fn new(num_blocks: usize, block_size: usize) -> Self {
+    pub fn new(num_blocks: usize, block_size: usize, max_blocks: usize) -> Self {
         assert!(
             num_blocks > 1,
             "If you want only one block, use a different Fifo."
         );
+        assert!(
+            max_blocks >= num_blocks,
+            "max_blocks ({max_blocks}) is smaller than num_blocks ({num_blocks})."
+        );
 
         Self {
             phead: AtomicField::new(FieldConfig {
-                index_max: num_blocks,
+                index_max: max_blocks,
                 version: 0,
                 index: 0,
             }),
             chead: AtomicField::new(FieldConfig {
-                index_max: num_blocks,
+                index_max: max_blocks,
                 version: 0,
                 index: 0,
             }),
-            blocks: Box::into_raw({
-                let mut vec = Vec::with_capacity(num_blocks);
-                vec.extend((0..num_blocks).map(|i| {
-                    if i == 0 {
-                        Block::new(block_size)
-                    } else {
-                        Block::new_full(block_size)
-                    }
-                }));
-                vec.into_boxed_slice()
+            blocks: (0..max_blocks)
+                .map(|i| {
+                    AtomicPtr::new(match i {
+                        0 => Box::into_raw(Box::new(Block::new(block_size))),
+                        i if i < num_blocks => Box::into_raw(Box::new(Block::new_full(block_size))),
+                        _ => ptr::null_mut(),
+                    })
+                })
+                .collect(),
+            geometry: Mutex::new(Geometry {
+                lap: 0,
+                previous: num_blocks,
+                blocks: num_blocks,
+                allocated: num_blocks,
+                sealed: 0,
             }),
+            settled: AtomicUsize::new(num_blocks),
+            borrowed: false,
+            block_size,
+            #[cfg(feature = "stats")]
+            stats: Counters::default(),
+            #[cfg(feature = "debug")]
+            span: crate::trace::fifo_span("mpmc", num_blocks, block_size),
+        }
+    }
+
+    /// A fifo over `blocks` it does not own, its heads at `phead` and `chead`. The blocks must outlive the fifo.
+    #[cfg(feature = "persist")]
+    pub unsafe fn from_blocks(
+        blocks: &[*mut Block<T>],
+        block_size: usize,
+        phead: Field,
+        chead: Field,
+    ) -> Self {
+        let num_blocks = blocks.len();
 
-            // array::from_fn(|i| {
-            //     if i == 0 {
-            //         Default::default()
-            //     } else {
-            //         // {.idx = BLOCK_SIZE}
-            //         Block {
-            //             allocated: AtomicField::new(FieldConfig {
-            //                 index: BLOCK_SIZE,
-            //                 ..Default::default()
-            //             }),
-            //             committed: AtomicField::new(FieldConfig {
-            //                 index: BLOCK_SIZE,
-            //                 ..Default::default()
-            //             }),
-            //             reserved: AtomicField::new(FieldConfig {
-            //                 // index: BLOCK_SIZE,
-            //                 ..Default::default()
-            //             }),
-            //             consumed: AtomicField::new(FieldConfig {
-            //                 index: BLOCK_SIZE,
-            //                 ..Default::default()
-            //             }),
-
-            //             ..Default::default()
-            //         }
-            //     }
-            // }),
-            num_blocks,
+        Self {
+            phead: AtomicField::new(phead),
+            chead: AtomicField::new(chead),
+            blocks: blocks.iter().map(|&block| AtomicPtr::new(block)).collect(),
+            geometry: Mutex::new(Geometry {
+                lap: 0,
+                previous: num_blocks,
+                blocks: num_blocks,
+                allocated: num_blocks,
+                sealed: 0,
+            }),
+            settled: AtomicUsize::new(num_blocks),
+            borrowed: true,
             block_size,
+            #[cfg(feature = "stats")]
+            stats: Counters::default(),
+            #[cfg(feature = "debug")]
+            span: crate::trace::fifo_span("persist", num_blocks, block_size),
+        }
+    }
+
+    /// Allocates `extra_blocks` blocks, the producer head links them in behind the last block once it finishes
+    /// its current lap.
+    pub fn grow(&self, extra_blocks: usize) -> Result<()> {
+        let mut new_blocks = (0..extra_blocks).map(|_| Box::new(Block::new_full(self.block_size)));
+        let mut geometry = self.geometry.lock().unwrap();
+
+        if geometry.allocated + extra_blocks > self.blocks.len() {
+            return Err(Error::Full);
+        }
+        for slot in &self.blocks[geometry.allocated..geometry.allocated + extra_blocks] {
+            slot.store(Box::into_raw(new_blocks.next().unwrap()), Ordering::Relaxed);
+        }
+        geometry.allocated += extra_blocks;
+
+        Ok(())
+    }
+
+    /// Blocks the producer head goes through in its current lap.
+    pub fn num_blocks(&self) -> usize {
+        self.geometry.lock().unwrap().blocks
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn max_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn block(&self, index: usize) -> &Block<T> {
+        unsafe { &*self.blocks[index].load(Ordering::Relaxed) }
+    }
+
+    /// Every block the fifo uses, in index order.
+    pub fn blocks(&self) -> impl Iterator<Item = &Block<T>> {
+        let blocks = self.geometry.lock().unwrap().blocks;
+        (0..blocks).map(|index| self.block(index))
+    }
+
+    /// Where `head` goes once its block is done. Only the producer head may `seal` the lap it is in.
+    fn next(&self, head: Field, seal: bool) -> Field {
+        let (lap, next) = (head.get_version(), head.get_index() + 1);
+        let settled = self.settled.load(Ordering::Acquire);
+
+        let blocks = if next < settled || settled == self.blocks.len() {
+            settled
+        } else {
+            self.blocks_in(lap, seal)
+        };
+
+        if next < blocks {
+            Field::from_parts(self.blocks.len(), lap, next)
+        } else {
+            Field::from_parts(self.blocks.len(), lap + 1, 0)
+        }
+    }
+
+    /// Looks up how many blocks `lap` goes through, linking in grown blocks first if `seal`ing it.
+    ///
+    /// The first producer head asking about a lap seals it, so every head that leaves the lap's last block
+    /// afterwards agrees on where it goes. Grown blocks are only linked into a lap nobody asked about yet, and
+    /// only once the consumer head left every lap that still goes through fewer blocks.
+    fn blocks_in(&self, lap: usize, seal: bool) -> usize {
+        let mut geometry = self.geometry.lock().unwrap();
+
+        if self.chead.load(Ordering::Acquire).get_version() >= geometry.lap {
+            geometry.previous = geometry.blocks;
+        }
+        if seal && lap >= geometry.sealed {
+            geometry.sealed = lap + 1;
+
+            if geometry.allocated > geometry.blocks && geometry.previous == geometry.blocks {
+                for index in geometry.blocks..geometry.allocated {
+                    self.block(index).set_consumed(lap);
+                }
+                geometry.lap = lap;
+                geometry.blocks = geometry.allocated;
+
+                fifo_event!(
+                    self,
+                    INFO,
+                    lap,
+                    blocks = geometry.blocks,
+                    "grown blocks linked in"
+                );
+            }
         }
+        self.settled.store(geometry.previous, Ordering::Release);
+
+        geometry.blocks_in(lap)
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn snapshot(&self) -> Stats {
+        self.stats.snapshot()
     }
 
     fn get_phead_and_block(&self) -> (Field, &Block<T>) {
-        let ph = self.phead.load(Ordering::Relaxed);
-        (ph, &unsafe { &*self.blocks }[ph.get_index()])
+        let ph = self.phead.load(Ordering::Acquire);
+        (ph, self.block(ph.get_index()))
     }
 
     fn advance_phead(&self, ph: Field) -> AdvancePheadState {
-        let ref nblk = unsafe { &*self.blocks }[(ph.get_index() + 1) % self.num_blocks];
+        let new_ph = self.next(ph, true);
+        let next = new_ph.get_index();
+        let nblk = self.block(next);
         // /* retry-new begin
         let consumed = nblk.consumed.load(Ordering::Acquire);
 
@@ -110,9 +298,22 @@ impl<T> FastFifoInner<T> {
         {
             let reserved = nblk.reserved.load(Ordering::Relaxed);
 
+            count!(self, producer.phead_advance_failures);
             if reserved.get_index() == consumed.get_index() {
+                fifo_event!(
+                    self,
+                    DEBUG,
+                    block = next,
+                    "producer head blocked, the fifo is full"
+                );
                 AdvancePheadState::NoEntry
             } else {
+                fifo_event!(
+                    self,
+                    DEBUG,
+                    block = next,
+                    "producer head blocked, a consumer is still reading"
+                );
                 AdvancePheadState::NotAvailable
             }
         }
@@ -134,25 +335,51 @@ impl<T> FastFifoInner<T> {
             nblk.committed.fetch_max(new_field, Ordering::Relaxed);
             nblk.allocated.fetch_max(new_field, Ordering::Relaxed);
 
-            self.phead
-                .fetch_max(ph.version_inc_add(1), Ordering::Relaxed);
+            // Releases the blocks linked in by `next`.
+            self.phead.fetch_max(new_ph, Ordering::Release);
 
+            count!(self, producer.phead_advances);
+            fifo_event!(
+                self,
+                DEBUG,
+                from = ph.get_index(),
+                to = next,
+                "producer head advanced"
+            );
+            if next == 0 {
+                count!(self, producer.wraparounds);
+                fifo_event!(
+                    self,
+                    DEBUG,
+                    lap = ph.get_version() + 1,
+                    "producer head wrapped"
+                );
+            }
             AdvancePheadState::Success
         }
     }
 
     fn get_chead_and_block(&self) -> (Field, &Block<T>) {
-        let ch = self.chead.load(Ordering::Relaxed);
-        (ch, &unsafe { &*self.blocks }[ch.get_index()])
+        let ch = self.chead.load(Ordering::Acquire);
+        (ch, self.block(ch.get_index()))
     }
 
     #[allow(unused_variables)]
     fn advance_chead(&self, ch: Field, version: usize) -> bool {
-        let ref nblk = unsafe { &*self.blocks }[(ch.get_index() + 1) % self.num_blocks];
+        let new_ch = self.next(ch, false);
+        let next = new_ch.get_index();
+        let nblk = self.block(next);
         let committed = nblk.committed.load(Ordering::Acquire);
 
         // /* retry-new begin
         if committed.get_version() != ch.get_version() + 1 {
+            count!(self, consumer.chead_advance_failures);
+            fifo_event!(
+                self,
+                TRACE,
+                block = next,
+                "consumer head blocked, nothing produced yet"
+            );
             return false;
         }
         let new_field = FieldConfig {
@@ -177,22 +404,80 @@ impl<T> FastFifoInner<T> {
         );
         // */ // drop-old end
 
-        self.chead
-            .fetch_max(ch.version_inc_add(1), Ordering::Relaxed);
+        self.chead.fetch_max(new_ch, Ordering::Release);
+        count!(self, consumer.chead_advances);
+        fifo_event!(
+            self,
+            DEBUG,
+            from = ch.get_index(),
+            to = next,
+            "consumer head advanced"
+        );
         true
     }
 
     /// Try to reserve a production entry
+    #[allow(unused_variables)]
     pub fn get_producer_entry(&self) -> Result<ProducingEntry<'_, T>> {
+        self.allocate(Block::allocate_entry)
+            .map(|(ph, entry_description)| {
+                count!(self, producer.pushes);
+                fifo_event!(
+                    self,
+                    TRACE,
+                    block = ph.get_index(),
+                    index = entry_description.index.sub_block_idx,
+                    "producer entry allocated"
+                );
+                ProducingEntry(entry_description)
+            })
+    }
+
+    /// Allocates up to `max` consecutive entries in the producer head's block.
+    #[allow(unused_variables)]
+    pub fn get_producer_slice(&self, max: usize) -> Result<ProducingSlice<'_, T>> {
+        self.allocate(|block, _| {
+            block
+                .allocate_entries(max)
+                .map(|(start, count)| ProducingSlice {
+                    block,
+                    start,
+                    count,
+                    requested: max,
+                })
+        })
+        .map(|(ph, slice)| {
+            count!(self, producer.pushes, slice.count);
+            fifo_event!(
+                self,
+                TRACE,
+                block = ph.get_index(),
+                index = slice.start,
+                count = slice.count,
+                "producer slice allocated"
+            );
+            slice
+        })
+    }
+
+    /// Runs `allocate` on the producer head's block and its index, moving the head on while the block is done.
+    pub fn allocate<'a, R>(
+        &'a self,
+        allocate: impl Fn(&'a Block<T>, usize) -> AllocState<R>,
+    ) -> Result<(Field, R)> {
         loop {
             let (ph, blk) = self.get_phead_and_block();
-            match blk.allocate_entry(ph.get_index()) {
-                AllocState::Allocated(entry_description) => {
-                    break Ok(ProducingEntry(entry_description));
-                }
+            match allocate(blk, ph.get_index()) {
+                AllocState::Allocated(allocated) => break Ok((ph, allocated)),
                 AllocState::BlockDone => match self.advance_phead(ph) {
-                    AdvancePheadState::NoEntry => break Err(Error::Full),
-                    AdvancePheadState::NotAvailable => break Err(Error::Busy),
+                    AdvancePheadState::NoEntry => {
+                        count!(self, producer.full);
+                        break Err(Error::Full);
+                    }
+                    AdvancePheadState::NotAvailable => {
+                        count!(self, producer.busy);
+                        break Err(Error::Busy);
+                    }
                     AdvancePheadState::Success => { /* continue loop */ }
                 },
             }
@@ -210,32 +495,173 @@ impl<T> FastFifoInner<T> {
     }
 
     pub fn indexed_push(&self, val: T, index: FifoIndex) {
-        let blk = unsafe { &(*self.blocks)[index.block_idx] };
+        let blk = self.block(index.block_idx);
         blk.allocated.fetch_add(1, Ordering::Relaxed);
         unsafe { (*blk.entries)[index.sub_block_idx].write(val) };
         blk.committed.fetch_add(1, Ordering::Release);
+        count!(self, producer.pushes);
     }
 
     pub fn get_consumer_entry(&self) -> Result<ConsumingEntry<'_, T>> {
+        self.consumer_entry(Block::reserve_entry)
+    }
+
+    /// [`Self::get_consumer_entry`] for a thief, see [`Block::steal_entries`].
+    pub fn get_stolen_entry(&self) -> Result<ConsumingEntry<'_, T>> {
+        self.consumer_entry(Block::steal_entry)
+    }
+
+    #[allow(unused_variables)]
+    fn consumer_entry<'a>(
+        &'a self,
+        reserve: impl Fn(&'a Block<T>) -> ReserveState<EntryDescription<'a, T>>,
+    ) -> Result<ConsumingEntry<'a, T>> {
+        self.reserve(reserve).map(|(ch, entry_description)| {
+            count!(self, consumer.pops);
+            fifo_event!(
+                self,
+                TRACE,
+                block = ch.get_index(),
+                index = entry_description.index.sub_block_idx,
+                "consumer entry reserved"
+            );
+            ConsumingEntry(entry_description)
+        })
+    }
+
+    /// Steals half of the entries waiting in the consumer head's block, rounded up, see [`Block::steal_entries`].
+    #[allow(unused_variables)]
+    pub fn get_consumer_batch(&self) -> Result<ConsumingBatch<'_, T>> {
+        self.reserve(|block| {
+            block
+                .steal_entries(|available| available.div_ceil(2))
+                .map(|(reserved, count)| ConsumingBatch {
+                    block,
+                    next: reserved.get_index(),
+                    end: reserved.get_index() + count,
+                    count,
+                })
+        })
+        .map(|(ch, batch)| {
+            count!(self, consumer.pops, batch.count);
+            fifo_event!(
+                self,
+                TRACE,
+                block = ch.get_index(),
+                index = batch.next,
+                count = batch.count,
+                "consumer batch reserved"
+            );
+            batch
+        })
+    }
+
+    /// Reserves up to `max` of the entries waiting in the consumer head's block.
+    #[allow(unused_variables)]
+    pub fn get_consumer_slice(&self, max: usize) -> Result<ConsumingSlice<'_, T>> {
+        self.reserve(|block| {
+            block
+                .reserve_entries(|available| available.min(max))
+                .map(|(reserved, count)| ConsumingSlice {
+                    block,
+                    start: reserved.get_index(),
+                    count,
+                })
+        })
+        .map(|(ch, slice)| {
+            count!(self, consumer.pops, slice.count);
+            fifo_event!(
+                self,
+                TRACE,
+                block = ch.get_index(),
+                index = slice.start,
+                count = slice.count,
+                "consumer slice reserved"
+            );
+            slice
+        })
+    }
+
+    /// Runs `reserve` on the consumer head's block, moving the head on while the block is done.
+    pub fn reserve<'a, R>(
+        &'a self,
+        reserve: impl Fn(&'a Block<T>) -> ReserveState<R>,
+    ) -> Result<(Field, R)> {
         loop {
             let (ch, blk) = self.get_chead_and_block();
-            match blk.reserve_entry() {
+            match reserve(blk) {
                 ReserveState::BlockDone(version) => {
                     if !self.advance_chead(ch, version) {
+                        count!(self, consumer.empty);
                         break Err(Error::Empty);
                     } else {
                         /* continue loop */
                     }
                 }
-                ReserveState::Reserved(entry_description) => {
-                    break Ok(ConsumingEntry(entry_description));
+                ReserveState::Reserved(reserved) => break Ok((ch, reserved)),
+                ReserveState::NoEntry => {
+                    count!(self, consumer.empty);
+                    fifo_event!(self, TRACE, block = ch.get_index(), "pop empty");
+                    break Err(Error::Empty);
+                }
+                ReserveState::NotAvailable => {
+                    count!(self, consumer.busy);
+                    fifo_event!(
+                        self,
+                        DEBUG,
+                        block = ch.get_index(),
+                        "pop busy, a producer is still writing"
+                    );
+                    break Err(Error::Busy);
                 }
-                ReserveState::NoEntry => break Err(Error::Empty),
-                ReserveState::NotAvailable => break Err(Error::Busy),
             }
         }
     }
 
+    /// Takes the last entry pushed back out of the producer head's block. `None` if that block has nothing left
+    /// or a thief may be reserving its last entry.
+    ///
+    /// # Safety
+    ///
+    /// Only the thread pushing may call this, and every other consumer must reserve through
+    /// [`Block::steal_entries`].
+    #[allow(unused_variables)]
+    pub unsafe fn take_back(&self) -> Option<T> {
+        let (ph, blk) = self.get_phead_and_block();
+        // Only the pushing thread writes `committed` in the producer head's block.
+        let committed = blk.committed.load(Ordering::Relaxed);
+
+        let last = committed.get_index().checked_sub(1)?;
+        let back: Field = FieldConfig {
+            index_max: self.block_size,
+            version: committed.get_version(),
+            index: last,
+        }
+        .into();
+        blk.committed.store(back, Ordering::Relaxed);
+        // Pairs with the fence in `Block::steal_entries`: either a thief sees `back` or this sees its reservation.
+        fence(Ordering::SeqCst);
+        let reserved = blk.reserved.load(Ordering::Relaxed);
+
+        // Thieves reserve one entry at a time, the one `reserved` points at, so the last one is left to this thread
+        // unless that is it. None may while `reserved` is still in the previous lap of the block.
+        if reserved.get_version() != committed.get_version() || reserved.get_index() < last {
+            blk.allocated.store(back, Ordering::Relaxed);
+            count!(self, consumer.pops);
+            fifo_event!(
+                self,
+                TRACE,
+                block = ph.get_index(),
+                index = last,
+                "entry taken back"
+            );
+            Some(unsafe { (*blk.entries)[last].assume_init_read() })
+        } else {
+            blk.committed.store(committed, Ordering::Release);
+            None
+        }
+    }
+
     /// F consumes T at address *mut T
     pub fn pop_in_place<F: FnOnce(*mut T)>(&self, consumer: F) -> Result<()> {
         self.get_consumer_entry()
@@ -266,16 +692,47 @@ impl<T> FastFifoInner<T> {
     }
 }
 
-impl<T: Debug> Debug for FastFifoInner<T> {
+impl<T> FastFifoInner<T> {
+    /// The items waiting in the fifo in the order consumers would get them, `None` while a producer still writes.
+    #[cfg(feature = "serde")]
+    pub fn items(&mut self) -> Option<Vec<&T>> {
+        let blocks = self.geometry.get_mut().unwrap().blocks;
+        let chead = self.chead.load(Ordering::Relaxed).get_index();
+
+        let mut items = Vec::new();
+        for index in (chead..blocks).chain(0..chead) {
+            let block = self.block(index);
+            let entries = unsafe { &*block.entries };
+
+            items.extend(
+                entries[block.queued()?]
+                    .iter()
+                    .map(|entry| unsafe { entry.assume_init_ref() }),
+            );
+        }
+        Some(items)
+    }
+}
+
+impl<T> Debug for FastFifoInner<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_list().entries(unsafe { &*self.blocks }).finish()
+        f.debug_list().entries(self.blocks()).finish()
     }
 }
 
 impl<T> Drop for FastFifoInner<T> {
     fn drop(&mut self) {
-        unsafe { &mut *self.blocks }
+        if self.borrowed {
+            return;
+        }
+        self.blocks
             .iter_mut()
-            .for_each(Block::drop);
+            .map(|slot| *slot.get_mut())
+            .filter(|block| !block.is_null())
+            .for_each(|block| {
+                let mut block = unsafe { Box::from_raw(block) };
+                Block::drop(&mut block);
+                drop(unsafe { Box::from_raw(block.entries) });
+            });
     }
 }
@@ -0,0 +1,123 @@
+//! What the entries of a [`FastFifo`] hold, read from the counters of its blocks.
+//!
+//! [`FastFifo::inspect`] never touches an entry, so it can run next to producers and consumers. Showing the
+//! items themselves takes [`FastFifo::debug_values`], which needs the fifo to itself.
+
+use super::{FastFifo, block::Block, fifo_inner::FastFifoInner};
+use std::{
+    fmt::{self, Debug},
+    sync::{Arc, atomic::Ordering},
+};
+
+/// The state of an entry in the current lap of its block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotState {
+    /// Not written in this lap.
+    Uninit,
+    /// Taken by a producer that did not commit it yet.
+    Allocated,
+    /// Holds an item waiting for a consumer.
+    Committed,
+    /// Taken by a consumer that did not consume it yet.
+    Reserved,
+    /// Its item was moved out.
+    Consumed,
+}
+
+/// A fifo's `Debug`, with the items waiting in it shown by value. See [`FastFifo::debug_values`].
+pub struct DebugValues<'a, T>(&'a FastFifoInner<T>);
+
+/// An entry as [`DebugValues`] shows it.
+enum Shown<'a, T> {
+    Value(&'a T),
+    State(SlotState),
+}
+
+impl<T> Block<T> {
+    /// The state of every entry. A block both heads left behind shows every entry consumed.
+    pub fn inspect(&self) -> Vec<SlotState> {
+        let consumed = self.consumed.load(Ordering::Relaxed);
+        let reserved = self.reserved.load(Ordering::Relaxed);
+        let committed = self.committed.load(Ordering::Relaxed);
+        // Producers overshoot the block while racing for its last entry.
+        let allocated = self
+            .allocated
+            .load(Ordering::Relaxed)
+            .get_index()
+            .min(self.block_size);
+
+        // The consumer head has not entered the lap the producer head is filling this block in.
+        let (consumed, reserved) = if reserved.get_version() != committed.get_version() {
+            (0, 0)
+        } else {
+            (consumed.get_index(), reserved.get_index())
+        };
+        let committed = committed.get_index();
+
+        (0..self.block_size)
+            .map(|i| {
+                if i < consumed {
+                    SlotState::Consumed
+                } else if i < reserved {
+                    SlotState::Reserved
+                } else if i < committed {
+                    SlotState::Committed
+                } else if i < allocated {
+                    SlotState::Allocated
+                } else {
+                    SlotState::Uninit
+                }
+            })
+            .collect()
+    }
+}
+
+impl<T> FastFifo<T> {
+    /// The state of every entry, block by block.
+    ///
+    /// Only reads the counters of the blocks, while the fifo is in use the blocks are each seen at a different
+    /// moment.
+    pub fn inspect(&self) -> Vec<Vec<SlotState>> {
+        self.0.blocks().map(Block::inspect).collect()
+    }
+
+    /// Shows the fifo like `Debug` does, with committed entries shown by value instead of their state.
+    ///
+    /// Returns `None` while a clone shares the fifo, the items are only borrowed as long as nobody can move them
+    /// out.
+    pub fn debug_values(&mut self) -> Option<DebugValues<'_, T>>
+    where
+        T: Debug,
+    {
+        Arc::get_mut(&mut self.0).map(|inner| DebugValues(inner))
+    }
+}
+
+impl<T: Debug> Debug for DebugValues<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.blocks().map(|block| {
+                let entries = unsafe { &*block.entries };
+
+                block
+                    .inspect()
+                    .into_iter()
+                    .zip(entries)
+                    .map(|(state, entry)| match state {
+                        SlotState::Committed => Shown::Value(unsafe { entry.assume_init_ref() }),
+                        state => Shown::State(state),
+                    })
+                    .collect::<Vec<_>>()
+            }))
+            .finish()
+    }
+}
+
+impl<T: Debug> Debug for Shown<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shown::Value(value) => value.fmt(f),
+            Shown::State(state) => state.fmt(f),
+        }
+    }
+}
@@ -1,26 +1,46 @@
 use crate::mpmc::fifo_inner::FifoIndex;
 
-use self::{
-    entries::{ConsumingEntry, ProducingEntry},
-    error::Error,
-};
+use self::error::Error;
 use fifo_inner::FastFifoInner;
 use std::{fmt::Debug, sync::Arc};
 
+pub use self::entries::{
+    ConsumingBatch, ConsumingEntry, ConsumingSlice, ProducingEntry, ProducingSlice,
+};
+pub use self::inspect::{DebugValues, SlotState};
+#[cfg(feature = "serde")]
+pub use self::snapshot::Snapshot;
+#[cfg(feature = "stats")]
+pub use self::stats::Stats;
+pub use self::{priority::{PriorityFifo, WaitConsumerEntry}, unbounded::UnboundedFifo};
+
 mod atomic;
 mod block;
+pub mod broadcast;
+pub mod bytes;
+pub mod delay;
 mod entries;
 mod error;
 mod fifo_inner;
+mod inspect;
+#[cfg(feature = "persist")]
+pub mod persist;
+mod priority;
+#[cfg(feature = "serde")]
+mod snapshot;
+pub mod stealing;
+#[cfg(feature = "stats")]
+mod stats;
 #[cfg(test)]
 mod test;
+mod unbounded;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 #[derive(Clone)]
 pub struct FastFifo<T>(Arc<FastFifoInner<T>>);
 
-/// This type allows for the construction of a FastFifo from a CAPACITY instead of a NUM_BLOCKS.
+// This type allows for the construction of a FastFifo from a CAPACITY instead of a NUM_BLOCKS.
 // pub struct CohortFastFifo<T, const CAPACITY: usize, const BLOCK_SIZE: usize>(PhantomData<T>);
 
 // pub const fn ceiling_div(lhs: usize, rhs: usize) -> usize {
@@ -36,13 +56,50 @@ pub struct FastFifo<T>(Arc<FastFifoInner<T>>);
 
 impl<T> FastFifo<T> {
     pub fn new(num_blocks: usize, block_size: usize) -> Self {
-        Self(Arc::new(FastFifoInner::new(num_blocks, block_size)))
+        Self::with_max_blocks(num_blocks, block_size, num_blocks)
+    }
+
+    /// A fifo starting with `num_blocks` blocks that [`Self::grow`] can take up to `max_blocks`.
+    ///
+    /// Only the blocks in use are allocated, `max_blocks` costs a pointer per block up front.
+    pub fn with_max_blocks(num_blocks: usize, block_size: usize, max_blocks: usize) -> Self {
+        Self(Arc::new(FastFifoInner::new(
+            num_blocks, block_size, max_blocks,
+        )))
+    }
+
+    /// Allocates `extra_blocks` more blocks, returns `Error::Full` if that would exceed `max_blocks`.
+    ///
+    /// The blocks are linked in behind the last block the next time the producer head reaches the end of a lap,
+    /// and only take entries from then on. Until then a full fifo stays full, consumers have to make room for the
+    /// producer head to get there. Blocks stay allocated until the fifo is dropped: a head that loaded a block
+    /// just before it would be unlinked could still touch it at any point, which is why there is no `shrink`.
+    pub fn grow(&self, extra_blocks: usize) -> Result<()> {
+        self.0.grow(extra_blocks)
+    }
+
+    /// Blocks the producer head goes through in its current lap, grown blocks count once they are linked in.
+    pub fn num_blocks(&self) -> usize {
+        self.0.num_blocks()
     }
 
     pub fn try_get_producer_entry(&self) -> Result<ProducingEntry<'_, T>> {
         self.0.get_producer_entry()
     }
 
+    /// Allocates `n` consecutive entries to write in place, fewer if the producer head's block has less left.
+    ///
+    /// A slice never spans blocks, so `n` over the block size always comes back short, see
+    /// [`ProducingSlice::is_short`].
+    ///
+    /// # Safety
+    ///
+    /// Every entry of the slice must be written before it is dropped, unwinding included: consumers read every
+    /// entry of a dropped slice as a `T`.
+    pub unsafe fn try_reserve_slice(&self, n: usize) -> Result<ProducingSlice<'_, T>> {
+        self.0.get_producer_slice(n)
+    }
+
     pub fn push_in_place<F: FnOnce(*mut T)>(&self, producer: F) -> Result<()> {
         self.0.push_in_place(producer)
     }
@@ -59,6 +116,11 @@ impl<T> FastFifo<T> {
         self.0.get_consumer_entry()
     }
 
+    /// Reserves up to `n` consecutive entries to read in place, out of those waiting in the consumer head's block.
+    pub fn try_consume_slice(&self, n: usize) -> Result<ConsumingSlice<'_, T>> {
+        self.0.get_consumer_slice(n)
+    }
+
     pub fn pop_in_place<F: FnOnce(*mut T)>(&self, consumer: F) -> Result<()> {
         self.0.pop_in_place(consumer)
     }
@@ -70,9 +132,21 @@ impl<T> FastFifo<T> {
     pub fn indexed_pop(&self) -> Result<(T, FifoIndex)> {
         self.0.indexed_pop()
     }
+
+    /// Copies the counters shared by every clone of this fifo, see [`Stats`].
+    #[cfg(feature = "stats")]
+    pub fn snapshot(&self) -> Stats {
+        self.0.snapshot()
+    }
+
+    /// Publishes [`Self::snapshot`] into the `metrics` facade, see [`crate::export`].
+    #[cfg(feature = "metrics")]
+    pub fn export_metrics(&self, name: &str) {
+        crate::export::export_mpmc(name, &self.snapshot());
+    }
 }
 
-impl<T: Debug> Debug for FastFifo<T> {
+impl<T> Debug for FastFifo<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.0.as_ref())
     }
@@ -0,0 +1,293 @@
+//! A [`FastFifo`](super::FastFifo) whose blocks live in a memory-mapped file, so its items survive a crash.
+//!
+//! The file holds a header describing its layout, the blocks with their counters, then the entries. Every entry
+//! has a `written` flag next to its value: producers set it once the value is written, consumers clear it once
+//! they read the value. Items stay in the page cache when the process dies, [`FastFifo::flush`] also gets them
+//! to disk. An advisory lock on the file keeps a second process from opening it meanwhile.
+//!
+//! [`FastFifo::open`] rebuilds the fifo from the blocks. Producers that died between allocating and committing
+//! leave an entry allocated that is either written or not: every allocated entry counts as committed, consumers
+//! skip the unwritten ones. Consumers that died between reserving and consuming leave entries reserved: the
+//! reservation goes back to the first entry still written, so those items are handed out again. The heads are
+//! found from the versions of the blocks, a block the head entered in the current lap is one version ahead.
+
+use super::{Result, atomic::AtomicField, block::Block, fifo_inner::FastFifoInner};
+use crate::field::Field;
+use bytemuck::Pod;
+use memmap2::MmapMut;
+use std::{
+    any,
+    fs::{File, OpenOptions, TryLockError},
+    io,
+    mem::MaybeUninit,
+    path::Path,
+    ptr,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+const MAGIC: [u8; 8] = *b"FASTFIFO";
+
+#[repr(C)]
+struct Header {
+    magic: [u8; 8],
+    num_blocks: u64,
+    block_size: u64,
+    block_layout: u64,
+    entry_layout: u64,
+    /// Hash of the name of `T`, so a file of another type with the same size is refused.
+    type_hash: u64,
+}
+
+/// An entry of the file, `written` tells whether `val` holds an item not consumed yet.
+#[repr(C)]
+struct Entry<T> {
+    written: AtomicU32,
+    val: MaybeUninit<T>,
+}
+
+/// An mpmc ring kept in a file, clones share the mapping.
+///
+/// `T` is stored as its bytes, [`Pod`] makes sure any bytes the file holds are a valid `T` in the next process.
+pub struct FastFifo<T: Pod>(Arc<PersistInner<T>>);
+
+struct PersistInner<T> {
+    // Dropped before the mapping it points into.
+    fifo: FastFifoInner<Entry<T>>,
+    map: MmapMut,
+    /// Holds the lock on the file.
+    _file: File,
+}
+
+impl<T: Pod> Clone for FastFifo<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// Where the blocks and the entries start in the file, and how long it is.
+fn layout<T>(num_blocks: usize, block_size: usize) -> (usize, usize, usize) {
+    let blocks = size_of::<Header>().next_multiple_of(align_of::<Block<Entry<T>>>());
+    let entries = (blocks + num_blocks * size_of::<Block<Entry<T>>>())
+        .next_multiple_of(align_of::<Entry<T>>());
+
+    (
+        blocks,
+        entries,
+        entries + num_blocks * block_size * size_of::<Entry<T>>(),
+    )
+}
+
+/// FNV-1a, the hash must not change between builds.
+fn type_hash<T>() -> u64 {
+    any::type_name::<T>()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+impl<T: Pod> FastFifo<T> {
+    /// Opens the fifo in the file at `path`, creating it with `num_blocks` blocks of `block_size` entries if it is
+    /// empty or missing.
+    ///
+    /// The header is written and synced last, so a file whose creation was cut short has a zeroed header and is
+    /// created again.
+    ///
+    /// Returns an `InvalidData` error if the file holds a fifo of another geometry or type, and a `WouldBlock` error
+    /// if the file is open already, in this process or another one.
+    pub fn open(path: impl AsRef<Path>, num_blocks: usize, block_size: usize) -> io::Result<Self> {
+        assert!(
+            num_blocks > 1,
+            "If you want only one block, use a different Fifo."
+        );
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.try_lock().map_err(|error| match error {
+            TryLockError::WouldBlock => {
+                io::Error::new(io::ErrorKind::WouldBlock, "the file is open already")
+            }
+            TryLockError::Error(error) => error,
+        })?;
+        let (blocks_at, entries_at, len) = layout::<T>(num_blocks, block_size);
+        let header = Header {
+            magic: MAGIC,
+            num_blocks: num_blocks as u64,
+            block_size: block_size as u64,
+            block_layout: size_of::<Block<Entry<T>>>() as u64,
+            entry_layout: size_of::<Entry<T>>() as u64,
+            type_hash: type_hash::<T>(),
+        };
+
+        if file.metadata()?.len() == 0 {
+            file.set_len(len as u64)?;
+        }
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        if map.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the file does not hold a fifo of this geometry",
+            ));
+        }
+
+        let created = map[..size_of::<Header>()].iter().all(|&byte| byte == 0);
+        let base = map.as_mut_ptr();
+        if !created {
+            let found = unsafe { &*base.cast::<Header>() };
+            if found.magic != header.magic
+                || found.num_blocks != header.num_blocks
+                || found.block_size != header.block_size
+                || found.block_layout != header.block_layout
+                || found.entry_layout != header.entry_layout
+                || found.type_hash != header.type_hash
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the file does not hold a fifo of this geometry",
+                ));
+            }
+        }
+
+        let blocks: Vec<*mut Block<Entry<T>>> = (0..num_blocks)
+            .map(|i| {
+                let block = unsafe { base.add(blocks_at).cast::<Block<Entry<T>>>().add(i) };
+                let entries = ptr::slice_from_raw_parts_mut(
+                    unsafe {
+                        base.add(entries_at)
+                            .cast::<MaybeUninit<Entry<T>>>()
+                            .add(i * block_size)
+                    },
+                    block_size,
+                );
+
+                if created {
+                    let index = if i == 0 { 0 } else { block_size };
+                    unsafe { ptr::write(block, Block::with_entries(block_size, index, entries)) };
+                } else {
+                    // The entries were mapped somewhere else in the last process.
+                    unsafe { ptr::addr_of_mut!((*block).entries).write(entries) };
+                    recover(unsafe { &*block });
+                }
+                block
+            })
+            .collect();
+
+        if created {
+            // The blocks must be on disk before the header makes the file count as a fifo.
+            map.flush()?;
+            unsafe { ptr::write(base.cast::<Header>(), header) };
+            map.flush_range(0, size_of::<Header>())?;
+        }
+
+        let phead = head(
+            num_blocks,
+            blocks.iter().map(|&block| unsafe { &*(*block).allocated }),
+        );
+        let chead = head(
+            num_blocks,
+            blocks.iter().map(|&block| unsafe { &*(*block).reserved }),
+        );
+        let fifo = unsafe { FastFifoInner::from_blocks(&blocks, block_size, phead, chead) };
+
+        Ok(Self(Arc::new(PersistInner {
+            fifo,
+            map,
+            _file: file,
+        })))
+    }
+
+    pub fn push(&self, val: T) -> Result<()> {
+        self.0.fifo.push_in_place(|entry| unsafe {
+            ptr::addr_of_mut!((*entry).val).write(MaybeUninit::new(val));
+            // The value must be in the file before the flag says so.
+            (*entry).written.store(1, Ordering::Release);
+        })
+    }
+
+    pub fn pop(&self) -> Result<T> {
+        loop {
+            let mut val = None;
+            self.0.fifo.pop_in_place(|entry| unsafe {
+                if (*entry).written.load(Ordering::Acquire) != 0 {
+                    val = Some((*entry).val.assume_init());
+                    (*entry).written.store(0, Ordering::Release);
+                }
+            })?;
+
+            // An entry a producer died before writing is skipped.
+            if let Some(val) = val {
+                break Ok(val);
+            }
+        }
+    }
+
+    /// Writes the mapping back to the file, for the items to survive the machine going down too.
+    pub fn flush(&self) -> io::Result<()> {
+        self.0.map.flush()
+    }
+}
+
+/// Fixes the counters of a block left behind by producers and consumers that died while using it.
+fn recover<T>(block: &Block<Entry<T>>) {
+    let allocated = block.allocated.load(Ordering::Relaxed);
+    let committed = block.committed.load(Ordering::Relaxed);
+
+    // Producers overshoot the block while racing for its last entry. A producer head that died moving into
+    // the block may have bumped `committed` alone, it moves in again.
+    let allocated = Field::from_parts(
+        block.block_size,
+        allocated.get_version(),
+        allocated.get_index().min(block.block_size),
+    );
+    if allocated != committed {
+        block.allocated.store(allocated, Ordering::Relaxed);
+        block.committed.store(allocated, Ordering::Relaxed);
+    }
+
+    let reserved = block.reserved.load(Ordering::Relaxed);
+    let consumed = block.consumed.load(Ordering::Relaxed);
+
+    // Likewise a consumer head that died moving into the block may have bumped `consumed` alone.
+    if consumed.get_version() != reserved.get_version() {
+        block.consumed.store(reserved, Ordering::Relaxed);
+    } else if consumed != reserved {
+        let entries = unsafe { &*block.entries };
+        let first = entries[..reserved.get_index()]
+            .iter()
+            .position(|entry| {
+                unsafe { entry.assume_init_ref() }
+                    .written
+                    .load(Ordering::Relaxed)
+                    != 0
+            })
+            .unwrap_or(reserved.get_index());
+        let field = Field::from_parts(block.block_size, reserved.get_version(), first);
+
+        block.reserved.store(field, Ordering::Relaxed);
+        block.consumed.store(field, Ordering::Relaxed);
+    }
+}
+
+/// The head that leaves `counters` behind: the last block of the prefix the head entered in its current lap.
+///
+/// A head moving into a block sets the block's version one past its own, so in lap `n` the first block is at
+/// version `n` and the blocks after it at `n + 1`.
+fn head<'a>(num_blocks: usize, mut counters: impl Iterator<Item = &'a AtomicField>) -> Field {
+    let lap = counters
+        .next()
+        .unwrap()
+        .load(Ordering::Relaxed)
+        .get_version();
+    let entered = counters
+        .take_while(|counter| counter.load(Ordering::Relaxed).get_version() == lap + 1)
+        .count();
+
+    Field::from_parts(num_blocks, lap, entered)
+}
@@ -0,0 +1,181 @@
+use super::{
+    Error, Result,
+    entries::{ConsumingEntry, ProducingEntry},
+    fifo_inner::FastFifoInner,
+};
+use crate::select::Backoff;
+use std::{
+    array,
+    future::Future,
+    mem::MaybeUninit,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+};
+
+/// `N` lanes of [`FastFifo`](super::FastFifo), lane 0 has the highest priority.
+///
+/// Consumers take from the highest priority lane that has an entry. With [`Self::with_weights`] a lane that was
+/// popped from `weights[lane]` times in a row lets the lower lanes go first once, so they cannot starve. Clones
+/// share the lanes and the counts. [`Self::wait_pop`] and [`Self::wait_pop_async`] wait on every lane at once.
+pub struct PriorityFifo<T, const N: usize>(Arc<PriorityInner<T, N>>);
+
+struct PriorityInner<T, const N: usize> {
+    lanes: [FastFifoInner<T>; N],
+    /// Pops a lane gets in a row before it yields, 0 never yields.
+    weights: [usize; N],
+    /// Pops each lane got in a row.
+    streaks: [AtomicUsize; N],
+}
+
+impl<T, const N: usize> Clone for PriorityFifo<T, N> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T, const N: usize> PriorityFifo<T, N> {
+    /// Strict priorities, every lane has `num_blocks` blocks of `block_size` entries.
+    pub fn new(num_blocks: usize, block_size: usize) -> Self {
+        Self::with_weights(num_blocks, block_size, [0; N])
+    }
+
+    pub fn with_weights(num_blocks: usize, block_size: usize, weights: [usize; N]) -> Self {
+        Self(Arc::new(PriorityInner {
+            lanes: array::from_fn(|_| FastFifoInner::new(num_blocks, block_size, num_blocks)),
+            weights,
+            streaks: array::from_fn(|_| AtomicUsize::new(0)),
+        }))
+    }
+
+    /// Panics if `lane` is not below `N`.
+    pub fn try_get_producer_entry(&self, lane: usize) -> Result<ProducingEntry<'_, T>> {
+        self.0.lanes[lane].get_producer_entry()
+    }
+
+    pub fn push_in_place<F: FnOnce(*mut T)>(&self, lane: usize, producer: F) -> Result<()> {
+        self.0.lanes[lane].push_in_place(producer)
+    }
+
+    pub fn push(&self, lane: usize, val: T) -> Result<()> {
+        self.0.lanes[lane].push(val)
+    }
+
+    /// Returns `Error::Busy` if no lane had an entry but one of them was busy, `Error::Empty` otherwise.
+    pub fn try_get_consumer_entry(&self) -> Result<ConsumingEntry<'_, T>> {
+        let PriorityInner {
+            lanes,
+            weights,
+            streaks,
+        } = self.0.as_ref();
+        let mut yielded = [false; N];
+        let mut busy = false;
+
+        for lane in 0..N {
+            if weights[lane] != 0 && streaks[lane].load(Ordering::Relaxed) >= weights[lane] {
+                streaks[lane].store(0, Ordering::Relaxed);
+                yielded[lane] = true;
+                continue;
+            }
+            match lanes[lane].get_consumer_entry() {
+                Ok(entry) => {
+                    streaks[lane].fetch_add(1, Ordering::Relaxed);
+                    return Ok(entry);
+                }
+                Err(Error::Busy) => busy = true,
+                Err(_) => streaks[lane].store(0, Ordering::Relaxed),
+            }
+        }
+
+        // Nothing below the lanes that yielded, so they go after all.
+        for lane in (0..N).filter(|&lane| yielded[lane]) {
+            match lanes[lane].get_consumer_entry() {
+                Ok(entry) => {
+                    streaks[lane].fetch_add(1, Ordering::Relaxed);
+                    return Ok(entry);
+                }
+                Err(Error::Busy) => busy = true,
+                Err(_) => {}
+            }
+        }
+
+        Err(if busy { Error::Busy } else { Error::Empty })
+    }
+
+    pub fn pop_in_place<F: FnOnce(*mut T)>(&self, consumer: F) -> Result<()> {
+        self.try_get_consumer_entry()
+            .map(|mut entry| entry.consume_t_in_place(consumer))
+    }
+
+    pub fn pop(&self) -> Result<T> {
+        let mut uninit_mem = MaybeUninit::uninit();
+
+        self.pop_in_place(|ptr| {
+            uninit_mem.write(unsafe { ptr.read() });
+        })
+        .map(|()| unsafe { uninit_mem.assume_init() })
+    }
+
+    /// Waits until any lane has an entry, then takes one by priority like [`Self::try_get_consumer_entry`].
+    ///
+    /// The lanes cannot wake a waiting thread, this backs off like
+    /// [`Select::ready`](crate::select::Select::ready).
+    pub fn wait_consumer_entry(&self) -> ConsumingEntry<'_, T> {
+        let mut backoff = Backoff::default();
+
+        loop {
+            match self.try_get_consumer_entry() {
+                Ok(entry) => break entry,
+                Err(_) => backoff.snooze(),
+            }
+        }
+    }
+
+    pub fn wait_pop(&self) -> T {
+        let mut uninit_mem = MaybeUninit::uninit();
+
+        self.wait_consumer_entry().consume_t_in_place(|ptr| {
+            uninit_mem.write(unsafe { ptr.read() });
+        });
+        unsafe { uninit_mem.assume_init() }
+    }
+
+    /// [`Self::wait_consumer_entry`] for async code.
+    pub fn wait_consumer_entry_async(&self) -> WaitConsumerEntry<'_, T, N> {
+        WaitConsumerEntry(self)
+    }
+
+    pub async fn wait_pop_async(&self) -> T {
+        let mut uninit_mem = MaybeUninit::uninit();
+
+        self.wait_consumer_entry_async()
+            .await
+            .consume_t_in_place(|ptr| {
+                uninit_mem.write(unsafe { ptr.read() });
+            });
+        unsafe { uninit_mem.assume_init() }
+    }
+}
+
+/// Resolves to an entry of whichever lane has one first, see [`PriorityFifo::wait_consumer_entry_async`].
+///
+/// The lanes cannot wake a task, so a pending poll asks to be polled again right away and the executor runs the
+/// other tasks in between.
+pub struct WaitConsumerEntry<'a, T, const N: usize>(&'a PriorityFifo<T, N>);
+
+impl<'a, T, const N: usize> Future for WaitConsumerEntry<'a, T, N> {
+    type Output = ConsumingEntry<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.0.try_get_consumer_entry() {
+            Ok(entry) => Poll::Ready(entry),
+            Err(_) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Saving the items of a [`FastFifo`] and building a fifo holding them again, with `serde`.
+
+use super::{Error, FastFifo, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The geometry of a fifo and the items waiting in it, in the order consumers would get them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    pub num_blocks: usize,
+    pub block_size: usize,
+    /// How many blocks [`FastFifo::grow`] may take the fifo up to.
+    pub max_blocks: usize,
+    pub items: Vec<T>,
+}
+
+impl<T> FastFifo<T> {
+    /// The items waiting in the fifo, left in place.
+    ///
+    /// Returns `None` while a clone shares the fifo or a [`ProducingEntry`](super::ProducingEntry) was leaked.
+    pub fn save(&mut self) -> Option<Snapshot<&T>> {
+        let (block_size, max_blocks) = (self.0.block_size(), self.0.max_blocks());
+        let inner = Arc::get_mut(&mut self.0)?;
+
+        Some(Snapshot {
+            num_blocks: inner.num_blocks(),
+            block_size,
+            max_blocks,
+            items: inner.items()?,
+        })
+    }
+
+    /// A fifo of the saved geometry holding the saved items.
+    ///
+    /// Returns `Error::Full` if there are more items than the geometry holds.
+    pub fn restore(snapshot: Snapshot<T>) -> Result<Self> {
+        let fifo = Self::with_max_blocks(
+            snapshot.num_blocks,
+            snapshot.block_size,
+            snapshot.max_blocks,
+        );
+
+        for item in snapshot.items {
+            fifo.push(item).map_err(|_| Error::Full)?;
+        }
+        Ok(fifo)
+    }
+}
@@ -0,0 +1,83 @@
+use crate::atom_pair::Line128;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Point-in-time copy of the counters kept by a [`FastFifo`](super::FastFifo) built with the `stats` feature.
+///
+/// Every counter is cumulative since construction. They are read one by one with `Relaxed` loads, so a snapshot
+/// taken while other threads are pushing or popping is not guaranteed to be consistent across fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Entries handed to producers, including `indexed_push`.
+    pub pushes: u64,
+    /// Entries handed to consumers.
+    pub pops: u64,
+    /// Producer attempts that returned `Error::Full`.
+    pub full: u64,
+    /// Producer or consumer attempts that returned `Error::Busy`.
+    pub busy: u64,
+    /// Consumer attempts that returned `Error::Empty`.
+    pub empty: u64,
+    /// Times the producer head moved on to the next block.
+    pub phead_advances: u64,
+    /// Times the producer head could not move because the next block was still being consumed.
+    pub phead_advance_failures: u64,
+    /// Times the consumer head moved on to the next block.
+    pub chead_advances: u64,
+    /// Times the consumer head could not move because the next block was not yet produced into.
+    pub chead_advance_failures: u64,
+    /// Times the producer head wrapped from the last block back to the first one.
+    pub wraparounds: u64,
+}
+
+/// The counters, those bumped by producers on another line than those bumped by consumers.
+pub(crate) struct Counters {
+    pub producer: Line128<ProducerCounters>,
+    pub consumer: Line128<ConsumerCounters>,
+}
+
+#[derive(Default)]
+pub(crate) struct ProducerCounters {
+    pub pushes: AtomicU64,
+    pub full: AtomicU64,
+    pub busy: AtomicU64,
+    pub phead_advances: AtomicU64,
+    pub phead_advance_failures: AtomicU64,
+    pub wraparounds: AtomicU64,
+}
+
+#[derive(Default)]
+pub(crate) struct ConsumerCounters {
+    pub pops: AtomicU64,
+    pub empty: AtomicU64,
+    pub busy: AtomicU64,
+    pub chead_advances: AtomicU64,
+    pub chead_advance_failures: AtomicU64,
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Self {
+            producer: ProducerCounters::default().into(),
+            consumer: ConsumerCounters::default().into(),
+        }
+    }
+}
+
+impl Counters {
+    pub fn snapshot(&self) -> Stats {
+        let (producer, consumer) = (&*self.producer, &*self.consumer);
+
+        Stats {
+            pushes: producer.pushes.load(Ordering::Relaxed),
+            pops: consumer.pops.load(Ordering::Relaxed),
+            full: producer.full.load(Ordering::Relaxed),
+            busy: producer.busy.load(Ordering::Relaxed) + consumer.busy.load(Ordering::Relaxed),
+            empty: consumer.empty.load(Ordering::Relaxed),
+            phead_advances: producer.phead_advances.load(Ordering::Relaxed),
+            phead_advance_failures: producer.phead_advance_failures.load(Ordering::Relaxed),
+            chead_advances: consumer.chead_advances.load(Ordering::Relaxed),
+            chead_advance_failures: consumer.chead_advance_failures.load(Ordering::Relaxed),
+            wraparounds: producer.wraparounds.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! A work-stealing queue built from the blocks of a [`FastFifo`](super::FastFifo).
+//!
+//! The [`Worker`] owns the queue: it is the only one pushing, and pops the items it pushed last first, while
+//! they are still in the producer head's block. [`Stealer`]s take the oldest items, up to half of the entries
+//! waiting in the consumer head's block at once. A batch still reserves its entries one by one, since the owner
+//! may be taking entries back from the other end, but hands them over with a single `consumed` update.
+//! `stealing_perf` compares batched and per item steals.
+
+use super::{
+    Result,
+    entries::{ConsumingBatch, ConsumingEntry},
+    fifo_inner::FastFifoInner,
+};
+use std::{cell::Cell, marker::PhantomData, mem::MaybeUninit, sync::Arc};
+
+/// The owning end of the queue, only one thread pushes into it.
+pub struct Worker<T> {
+    inner: Arc<FastFifoInner<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+/// Takes work from a [`Worker`]'s queue, from any thread.
+pub struct Stealer<T>(Arc<FastFifoInner<T>>);
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Worker<T> {
+    pub fn new(num_blocks: usize, block_size: usize) -> Self {
+        Self {
+            inner: Arc::new(FastFifoInner::new(num_blocks, block_size, num_blocks)),
+            _not_sync: PhantomData,
+        }
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer(self.inner.clone())
+    }
+
+    /// Hands `val` back if the queue is full or a thief is still reading the next block.
+    pub fn push(&self, val: T) -> std::result::Result<(), T> {
+        match self.inner.get_producer_entry() {
+            Ok(mut entry) => {
+                entry.produce_t_in_place(|ptr| unsafe { ptr.write(val) });
+                Ok(())
+            }
+            Err(_) => Err(val),
+        }
+    }
+
+    /// Pops the last item pushed, or the oldest one once the producer head's block has none left to take back.
+    pub fn pop(&self) -> Result<T> {
+        // Only this thread pushes, and thieves reserve through `steal_entries`.
+        match unsafe { self.inner.take_back() } {
+            Some(val) => Ok(val),
+            None => self.inner.pop(),
+        }
+    }
+}
+
+impl<T> Stealer<T> {
+    pub fn try_get_consumer_entry(&self) -> Result<ConsumingEntry<'_, T>> {
+        self.0.get_stolen_entry()
+    }
+
+    /// Steals a single item.
+    pub fn steal(&self) -> Result<T> {
+        let mut uninit_mem = MaybeUninit::uninit();
+
+        self.try_get_consumer_entry()
+            .map(|mut entry| {
+                entry.consume_t_in_place(|ptr| {
+                    uninit_mem.write(unsafe { ptr.read() });
+                })
+            })
+            .map(|()| unsafe { uninit_mem.assume_init() })
+    }
+
+    /// Steals up to half of the items waiting in the oldest block, rounded up, fewer if the owner takes some back
+    /// meanwhile.
+    pub fn steal_batch(&self) -> Result<ConsumingBatch<'_, T>> {
+        self.0.get_consumer_batch()
+    }
+}
@@ -0,0 +1,289 @@
+//! An mpmc fifo that never reports `Error::Full`.
+//!
+//! Instead of a ring of blocks the fifo is a list of segments, each one a [`Block`]. Producers and consumers take
+//! entries from their segment exactly like from a block of the ring. Once the producers filled the tail segment a
+//! new one is linked behind it, once the consumers reserved every entry of the head segment it is retired to a
+//! free-list. Retired segments are emptied for a new version and linked in again when all of their entries are
+//! consumed, so a fifo in a steady state stops allocating. Segments are only freed when the fifo is dropped.
+//!
+//! Moving the head or the tail to another segment happens under a lock, once every `block_size` entries.
+
+use super::{
+    Error, Result,
+    block::{AllocState, Block},
+    entries::{ConsumingEntry, EntryDescription, ProducingEntry},
+    fifo_inner::FifoIndex,
+};
+use crate::trace::fifo_event;
+use std::{
+    collections::VecDeque,
+    mem::MaybeUninit,
+    ptr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicPtr, Ordering},
+    },
+};
+
+/// Like [`FastFifo`](super::FastFifo), but links in another block of `block_size` entries instead of reporting
+/// `Error::Full`. Clones share the fifo.
+#[derive(Clone)]
+pub struct UnboundedFifo<T>(Arc<UnboundedInner<T>>);
+
+struct Segment<T> {
+    block: Block<T>,
+    /// The segment behind this one, null while this is the tail and [`retired`] while it is on the free-list.
+    next: AtomicPtr<Segment<T>>,
+}
+
+/// Marks a segment on the free-list, so a producer that still sees it as the tail does not link behind it.
+fn retired<T>() -> *mut Segment<T> {
+    ptr::dangling_mut()
+}
+
+struct Segments<T> {
+    /// Version the last linked segment was emptied for.
+    version: usize,
+    allocated: usize,
+    /// Retired segments, oldest first.
+    free: VecDeque<*mut Segment<T>>,
+}
+
+struct UnboundedInner<T> {
+    head: AtomicPtr<Segment<T>>,
+    tail: AtomicPtr<Segment<T>>,
+    block_size: usize,
+    segments: Mutex<Segments<T>>,
+    #[cfg(feature = "debug")]
+    span: tracing::Span,
+}
+
+#[rustfmt::skip]
+unsafe impl<T: Send> Send for UnboundedInner<T> {}
+#[rustfmt::skip]
+unsafe impl<T: Send> Sync for UnboundedInner<T> {}
+
+impl<T> UnboundedInner<T> {
+    fn new(block_size: usize) -> Self {
+        let first = Box::into_raw(Box::new(Segment {
+            block: Block::new(block_size),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+
+        Self {
+            head: AtomicPtr::new(first),
+            tail: AtomicPtr::new(first),
+            block_size,
+            segments: Mutex::new(Segments {
+                version: 0,
+                allocated: 1,
+                free: VecDeque::new(),
+            }),
+            #[cfg(feature = "debug")]
+            span: crate::trace::fifo_span("unbounded", 1, block_size),
+        }
+    }
+
+    fn get_producer_entry(&self) -> ProducingEntry<'_, T> {
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            let block = unsafe { &(*tail).block };
+
+            match block.allocate_entry(0) {
+                AllocState::Allocated(entry_description) => {
+                    fifo_event!(
+                        self,
+                        TRACE,
+                        index = entry_description.index.sub_block_idx,
+                        "producer entry allocated"
+                    );
+                    break ProducingEntry(entry_description);
+                }
+                AllocState::BlockDone => self.advance_tail(tail),
+            }
+        }
+    }
+
+    /// Links a segment behind `tail` once it is full, unless someone did already.
+    fn advance_tail(&self, tail: *mut Segment<T>) {
+        let mut segments = self.segments.lock().unwrap();
+        let full = unsafe { &(*tail).block }.allocated.load(Ordering::Relaxed);
+
+        if self.tail.load(Ordering::Relaxed) != tail || full.get_index() < self.block_size {
+            return;
+        }
+
+        let recycled = segments.free.front().is_some_and(|&segment| {
+            let consumed = unsafe { &(*segment).block }
+                .consumed
+                .load(Ordering::Acquire);
+            consumed.get_index() >= self.block_size
+        });
+        let next = if recycled {
+            segments.free.pop_front().unwrap()
+        } else {
+            segments.allocated += 1;
+            Box::into_raw(Box::new(Segment {
+                block: Block::new(self.block_size),
+                next: AtomicPtr::new(ptr::null_mut()),
+            }))
+        };
+
+        segments.version += 1;
+        unsafe {
+            (*next).block.set_empty(segments.version);
+            (*next).next.store(ptr::null_mut(), Ordering::Relaxed);
+            (*tail).next.store(next, Ordering::Release);
+        }
+        self.tail.store(next, Ordering::Release);
+
+        fifo_event!(
+            self,
+            DEBUG,
+            recycled,
+            allocated = segments.allocated,
+            "segment linked"
+        );
+    }
+
+    fn get_consumer_entry(&self) -> Result<ConsumingEntry<'_, T>> {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let block = unsafe { &(*head).block };
+            let reserved = block.reserved.load(Ordering::Acquire);
+
+            // A segment can be recycled under a consumer that loaded it as the head. Only reserve entries it got
+            // while it was still the head, the versions tell them apart from entries it gets once linked again.
+            if self.head.load(Ordering::Acquire) != head {
+                continue;
+            }
+            if reserved.get_index() >= self.block_size {
+                if self.advance_head(head) {
+                    continue;
+                }
+                fifo_event!(self, TRACE, "pop empty");
+                break Err(Error::Empty);
+            }
+
+            let committed = block.committed.load(Ordering::Acquire);
+            if committed.get_version() != reserved.get_version() {
+                continue;
+            }
+            if reserved.get_index() == committed.get_index() {
+                fifo_event!(self, TRACE, "pop empty");
+                break Err(Error::Empty);
+            }
+            if committed.get_index() != self.block_size {
+                let allocated = block.allocated.load(Ordering::Relaxed);
+                if allocated.get_index() != committed.get_index() {
+                    fifo_event!(self, DEBUG, "pop busy, a producer is still writing");
+                    break Err(Error::Busy);
+                }
+            }
+            if block
+                .reserved
+                .fetch_max(reserved.overflowing_add(1), Ordering::Relaxed)
+                == reserved
+            {
+                fifo_event!(
+                    self,
+                    TRACE,
+                    index = reserved.get_index(),
+                    "consumer entry reserved"
+                );
+                break Ok(ConsumingEntry(EntryDescription {
+                    block,
+                    index: FifoIndex {
+                        block_idx: 0,
+                        sub_block_idx: reserved.get_index(),
+                    },
+                    version: reserved.get_version(),
+                }));
+            }
+        }
+    }
+
+    /// Retires `head` once every entry of it is reserved, returns false if there is no segment behind it yet.
+    fn advance_head(&self, head: *mut Segment<T>) -> bool {
+        let mut segments = self.segments.lock().unwrap();
+        let reserved = unsafe { &(*head).block }.reserved.load(Ordering::Relaxed);
+
+        if self.head.load(Ordering::Relaxed) != head || reserved.get_index() < self.block_size {
+            return true;
+        }
+
+        let next = unsafe { &(*head).next }.load(Ordering::Acquire);
+        if next.is_null() {
+            return false;
+        }
+
+        self.head.store(next, Ordering::Release);
+        unsafe { &(*head).next }.store(retired(), Ordering::Relaxed);
+        segments.free.push_back(head);
+
+        fifo_event!(self, DEBUG, free = segments.free.len(), "segment retired");
+        true
+    }
+}
+
+impl<T> Drop for UnboundedInner<T> {
+    fn drop(&mut self) {
+        let segments = self.segments.get_mut().unwrap();
+        let mut linked = *self.head.get_mut();
+
+        while !linked.is_null() {
+            let next = *unsafe { &mut (*linked).next }.get_mut();
+            segments.free.push_back(linked);
+            linked = next;
+        }
+
+        segments.free.drain(..).for_each(|segment| {
+            let mut segment = unsafe { Box::from_raw(segment) };
+            Block::drop(&mut segment.block);
+            drop(unsafe { Box::from_raw(segment.block.entries) });
+        });
+    }
+}
+
+impl<T> UnboundedFifo<T> {
+    /// An empty fifo with a single segment of `block_size` entries.
+    pub fn new(block_size: usize) -> Self {
+        Self(Arc::new(UnboundedInner::new(block_size)))
+    }
+
+    /// Never fails, links a new segment if the tail is full.
+    pub fn get_producer_entry(&self) -> ProducingEntry<'_, T> {
+        self.0.get_producer_entry()
+    }
+
+    pub fn push_in_place<F: FnOnce(*mut T)>(&self, producer: F) {
+        self.get_producer_entry().produce_t_in_place(producer);
+    }
+
+    pub fn push(&self, val: T) {
+        self.push_in_place(|ptr| unsafe { ptr.write(val) });
+    }
+
+    pub fn try_get_consumer_entry(&self) -> Result<ConsumingEntry<'_, T>> {
+        self.0.get_consumer_entry()
+    }
+
+    pub fn pop_in_place<F: FnOnce(*mut T)>(&self, consumer: F) -> Result<()> {
+        self.try_get_consumer_entry()
+            .map(|mut entry| entry.consume_t_in_place(consumer))
+    }
+
+    pub fn pop(&self) -> Result<T> {
+        let mut uninit_mem = MaybeUninit::uninit();
+
+        self.pop_in_place(|ptr| {
+            uninit_mem.write(unsafe { ptr.read() });
+        })
+        .map(|()| unsafe { uninit_mem.assume_init() })
+    }
+
+    /// Segments allocated so far, linked or waiting on the free-list.
+    pub fn allocated_segments(&self) -> usize {
+        self.0.segments.lock().unwrap().allocated
+    }
+}
@@ -0,0 +1,161 @@
+//! Waiting for the first of several fifos that has an entry.
+//!
+//! ```
+//! use fastfifo::{
+//!     mpmc::{ConsumingEntry, FastFifo},
+//!     select::Select,
+//! };
+//!
+//! let read = |mut entry: ConsumingEntry<'_, u32>| {
+//!     let mut job = 0;
+//!     entry.consume_t_in_place(|ptr| job = unsafe { ptr.read() });
+//!     job
+//! };
+//!
+//! let (jobs, urgent) = (FastFifo::new(2, 4), FastFifo::new(2, 4));
+//! urgent.push(7).unwrap();
+//!
+//! let mut select = Select::new()
+//!     .recv(&jobs, read)
+//!     .recv(&urgent, |entry| read(entry) + 100);
+//! assert_eq!(select.ready(), Ok(107));
+//! ```
+
+use crate::{Error, Result, mpmc};
+use std::{
+    hint,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// Tries a set of operations in a rotating order and hands out the entry of the first one that is ready.
+///
+/// Every operation maps its entry into an `R`, so an operation that is not selected never takes an entry and no
+/// item gets lost. Sends return the [`ProducingEntry`](mpmc::ProducingEntry) too, the value is only written
+/// once the send was selected.
+pub struct Select<'a, R> {
+    operations: Vec<Box<dyn FnMut() -> Result<R> + 'a>>,
+    /// Operation tried first by the next call, so no operation starves the ones behind it.
+    next: usize,
+}
+
+impl<R> Default for Select<'_, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, R> Select<'a, R> {
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        Self {
+            operations: Vec::new(),
+            next: NEXT.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Pops from `fifo`, `map` gets the entry.
+    pub fn recv<T>(
+        mut self,
+        fifo: &'a mpmc::FastFifo<T>,
+        mut map: impl FnMut(mpmc::ConsumingEntry<'a, T>) -> R + 'a,
+    ) -> Self {
+        self.operations.push(Box::new(move || {
+            fifo.try_get_consumer_entry()
+                .map(&mut map)
+                .map_err(|_| Error::NotAvailable)
+        }));
+        self
+    }
+
+    /// Pushes into `fifo`, `map` gets the entry to write the value into.
+    pub fn send<T>(
+        mut self,
+        fifo: &'a mpmc::FastFifo<T>,
+        mut map: impl FnMut(mpmc::ProducingEntry<'a, T>) -> R + 'a,
+    ) -> Self {
+        self.operations.push(Box::new(move || {
+            fifo.try_get_producer_entry()
+                .map(&mut map)
+                .map_err(|_| Error::NotAvailable)
+        }));
+        self
+    }
+
+    /// Takes an entry from a generated stage fifo, usually `|| stage.get_entry()`.
+    ///
+    /// The stage counts as closed once `get_entry` returns `Error::Closed`.
+    pub fn stage<E>(
+        mut self,
+        mut get_entry: impl FnMut() -> Result<E> + 'a,
+        mut map: impl FnMut(E) -> R + 'a,
+    ) -> Self {
+        self.operations.push(Box::new(move || {
+            get_entry().map(&mut map).map_err(|error| match error {
+                Error::Closed => Error::Closed,
+                _ => Error::NotAvailable,
+            })
+        }));
+        self
+    }
+
+    /// Tries every operation once, starting after the one tried first last time.
+    ///
+    /// Returns `Error::NotAvailable` if no operation was ready and `Error::Closed` if all of them are closed.
+    pub fn try_ready(&mut self) -> Result<R> {
+        let count = self.operations.len();
+        let start = self.next % count.max(1);
+        let mut closed = 0;
+
+        self.next = start + 1;
+        for index in (start..count).chain(0..start) {
+            match (self.operations[index])() {
+                Err(Error::Closed) => closed += 1,
+                Err(_) => {}
+                ready => return ready,
+            }
+        }
+
+        Err(if count != 0 && closed == count {
+            Error::Closed
+        } else {
+            Error::NotAvailable
+        })
+    }
+
+    /// Waits until an operation is ready, or returns `Error::Closed` once all of them are closed.
+    ///
+    /// The fifos cannot wake a waiting thread, so this backs off from spinning over yielding to parking for a
+    /// short while between attempts.
+    pub fn ready(&mut self) -> Result<R> {
+        let mut backoff = Backoff::default();
+
+        loop {
+            match self.try_ready() {
+                Err(Error::NotAvailable) => {}
+                result => break result,
+            }
+            backoff.snooze();
+        }
+    }
+}
+
+/// Waits between attempts at a fifo that cannot wake a waiting thread, from spinning over yielding to parking
+/// for a short while.
+#[derive(Default)]
+pub(crate) struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn snooze(&mut self) {
+        match self.attempt {
+            0..6 => (0..1 << self.attempt).for_each(|_| hint::spin_loop()),
+            6..10 => thread::yield_now(),
+            _ => thread::park_timeout(Duration::from_micros(100)),
+        }
+        self.attempt = (self.attempt + 1).min(10);
+    }
+}
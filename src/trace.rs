@@ -0,0 +1,37 @@
+//! Instrumentation behind the `debug` feature.
+//!
+//! Every fifo owns a `fastfifo` span carrying a process-unique `id`, and all of its events are emitted with that
+//! span as their parent so concurrent fifos can be told apart. Levels are picked by how often an event fires:
+//! `TRACE` for every entry handed out or refused, `DEBUG` for head advances, block laps and contention,
+//! `INFO` for lifecycle changes such as a shutdown. Without the feature [`fifo_event!`] expands to nothing.
+
+#[cfg(feature = "debug")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Emits a `tracing` event under `$fifo`'s span, which has to have a `span` field under the `debug` feature.
+///
+/// ```ignore
+/// fifo_event!(self, DEBUG, ?tag, block, "head advanced");
+/// ```
+macro_rules! fifo_event {
+    ($fifo:expr, $level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "debug")]
+        ::tracing::event!(parent: &$fifo.span, ::tracing::Level::$level, $($arg)+);
+    };
+}
+
+pub(crate) use fifo_event;
+
+/// The span all events of a new fifo are emitted under.
+#[cfg(feature = "debug")]
+pub(crate) fn fifo_span(kind: &'static str, num_blocks: usize, block_size: usize) -> tracing::Span {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    tracing::info_span!(
+        "fastfifo",
+        id = NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        kind,
+        num_blocks,
+        block_size,
+    )
+}
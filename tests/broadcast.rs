@@ -0,0 +1,115 @@
+use fastfifo::mpmc::broadcast::FastFifo;
+use std::{sync::Arc, thread};
+
+#[test]
+fn every_subscriber_gets_every_item() {
+    let fifo = FastFifo::new(2, 4);
+    let mut subscribers = [fifo.subscribe(), fifo.subscribe()];
+
+    for i in 0..6 {
+        fifo.push(i).unwrap();
+    }
+    for subscriber in &mut subscribers {
+        let received: Vec<_> = (0..6).map(|_| subscriber.try_recv().unwrap()).collect();
+        assert_eq!(received, [0, 1, 2, 3, 4, 5]);
+        assert!(subscriber.try_recv().is_err());
+    }
+}
+
+#[test]
+fn late_subscribers_start_at_the_producer_head() {
+    let fifo = FastFifo::new(2, 4);
+    for i in 0..2 {
+        fifo.push(i).unwrap();
+    }
+
+    let mut subscriber = fifo.subscribe();
+    fifo.push(2).unwrap();
+    assert_eq!(subscriber.try_recv(), Ok(2));
+    assert!(subscriber.try_recv().is_err());
+}
+
+#[test]
+fn slowest_subscriber_holds_the_producer_back() {
+    let fifo = FastFifo::new(2, 2);
+    let (mut fast, mut slow) = (fifo.subscribe(), fifo.subscribe());
+
+    for lap in 0..3 {
+        for i in 0..4 {
+            fifo.push(lap * 4 + i).unwrap();
+            assert_eq!(fast.try_recv(), Ok(lap * 4 + i));
+        }
+        // Reusing the first block has to wait for `slow` to leave it.
+        assert!(fifo.push(0).is_err());
+        assert_eq!(slow.try_recv(), Ok(lap * 4));
+        assert!(fifo.push(0).is_err());
+        assert_eq!(slow.try_recv(), Ok(lap * 4 + 1));
+        (2..4).for_each(|i| assert_eq!(slow.try_recv(), Ok(lap * 4 + i)));
+    }
+
+    // A dropped subscriber does not hold it back.
+    drop(slow);
+    assert_eq!(fifo.subscribers(), 1);
+    (12..16).for_each(|i| fifo.push(i).unwrap());
+    assert!(fifo.push(16).is_err());
+}
+
+#[test]
+fn detaching_skips_lagging_subscribers() {
+    let fifo = FastFifo::detaching(2, 2);
+    let (mut active, mut lagging) = (fifo.subscribe(), fifo.subscribe());
+
+    for i in 0..8 {
+        fifo.push(i).unwrap();
+        assert_eq!(active.try_recv(), Ok(i));
+    }
+
+    assert!(lagging.is_detached());
+    assert!(lagging.try_recv().is_err());
+    assert!(!active.is_detached());
+    assert_eq!(fifo.subscribers(), 1);
+}
+
+#[test]
+fn items_are_dropped_once() {
+    let value = Arc::new(());
+    {
+        let fifo = FastFifo::new(2, 2);
+        let mut subscriber = fifo.subscribe();
+        for _ in 0..7 {
+            fifo.push(value.clone()).unwrap();
+            drop(subscriber.try_recv().unwrap());
+        }
+        // The ring keeps the items of the last lap around until they are overwritten.
+        assert_eq!(Arc::strong_count(&value), 4);
+    }
+    assert_eq!(Arc::strong_count(&value), 1);
+}
+
+#[test]
+fn subscribers_on_other_threads() {
+    const ITEMS: usize = 10_000;
+    let fifo = FastFifo::new(4, 16);
+    let subscribers: Vec<_> = (0..3).map(|_| fifo.subscribe()).collect();
+
+    thread::scope(|s| {
+        for mut subscriber in subscribers {
+            s.spawn(move || {
+                for i in 0..ITEMS {
+                    loop {
+                        match subscriber.try_recv() {
+                            Ok(val) => break assert_eq!(val, i),
+                            Err(_) => thread::yield_now(),
+                        }
+                    }
+                }
+            });
+        }
+
+        for i in 0..ITEMS {
+            while fifo.push(i).is_err() {
+                thread::yield_now();
+            }
+        }
+    });
+}
@@ -0,0 +1,166 @@
+use fastfifo::mpmc::bytes::ByteFifo;
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    thread,
+};
+
+#[test]
+fn records_keep_their_length() {
+    let fifo = ByteFifo::new(2, 32);
+    fifo.push(b"hello").unwrap();
+    fifo.push(b"").unwrap();
+    fifo.push(b"world!").unwrap();
+
+    assert_eq!(&*fifo.try_get_consumer_record().unwrap(), b"hello");
+    assert_eq!(fifo.pop().unwrap(), b"");
+    assert_eq!(fifo.pop().unwrap(), b"world!");
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn record_not_fitting_moves_to_the_next_block() {
+    let fifo = ByteFifo::new(2, 16);
+    // Frames of 4 + 6 bytes, the second one pads out the first block.
+    fifo.push(&[1; 6]).unwrap();
+    fifo.push(&[2; 6]).unwrap();
+    assert!(fifo.push(&[3; 6]).is_err());
+
+    assert_eq!(fifo.pop().unwrap(), [1; 6]);
+    assert_eq!(fifo.pop().unwrap(), [2; 6]);
+    assert!(fifo.pop().is_err());
+
+    // The padded block is free again once its padding was skipped.
+    fifo.push(&[3; 6]).unwrap();
+    assert_eq!(fifo.pop().unwrap(), [3; 6]);
+}
+
+#[test]
+fn rest_shorter_than_a_prefix_is_padding() {
+    let fifo = ByteFifo::new(2, 16);
+    fifo.push(&[1; 10]).unwrap();
+    fifo.push(&[2; 12]).unwrap();
+
+    assert_eq!(fifo.pop().unwrap(), [1; 10]);
+    assert_eq!(fifo.pop().unwrap(), [2; 12]);
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn too_long_records_are_refused() {
+    let fifo = ByteFifo::new(2, 16);
+    assert_eq!(fifo.max_len(), 12);
+    assert!(fifo.push(&[0; 13]).is_err());
+
+    fifo.push(&[0; 12]).unwrap();
+    assert_eq!(fifo.pop().unwrap(), [0; 12]);
+}
+
+#[test]
+fn records_are_written_in_place() {
+    let fifo = ByteFifo::new(2, 32);
+    {
+        let mut record = fifo.try_get_producer_record(4).unwrap();
+        assert_eq!(&*record, [0; 4]);
+        record.copy_from_slice(b"abcd");
+        assert!(fifo.pop().is_err());
+    }
+
+    let record = fifo.try_get_consumer_record().unwrap();
+    assert_eq!(&*record, b"abcd");
+}
+
+#[test]
+fn threads_get_every_record_once() {
+    const RECORDS: u32 = 2000;
+    let fifo = ByteFifo::new(4, 64);
+    let popped = AtomicU32::new(0);
+
+    let mut seen: Vec<u32> = thread::scope(|s| {
+        for p in 0..2 {
+            let fifo = fifo.clone();
+            s.spawn(move || {
+                for i in (p..RECORDS).step_by(2) {
+                    let mut record = i.to_le_bytes().to_vec();
+                    record.resize(4 + i as usize % 40, i as u8);
+                    while fifo.push(&record).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut seen = Vec::new();
+                    while popped.load(Ordering::Relaxed) < RECORDS {
+                        match fifo.try_get_consumer_record() {
+                            Ok(record) => {
+                                let i = u32::from_le_bytes(record[..4].try_into().unwrap());
+                                assert_eq!(record.len(), 4 + i as usize % 40);
+                                assert!(record[4..].iter().all(|&b| b == i as u8));
+                                seen.push(i);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(_) => thread::yield_now(),
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+        consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect()
+    });
+
+    seen.sort();
+    assert_eq!(seen, (0..RECORDS).collect::<Vec<_>>());
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn consumers_racing_over_wrapping_laps() {
+    const RECORDS: u32 = 20_000;
+    // Small blocks wrap every few records, payload bytes look like any length.
+    let fifo = ByteFifo::new(2, 24);
+    let popped = AtomicU32::new(0);
+
+    let mut seen: Vec<u32> = thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..RECORDS {
+                let mut record = i.to_le_bytes().to_vec();
+                record.resize(4 + i as usize % 9, 0xff);
+                while fifo.push(&record).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+        let consumers: Vec<_> = (0..4)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut seen = Vec::new();
+                    while popped.load(Ordering::Relaxed) < RECORDS {
+                        match fifo.pop() {
+                            Ok(record) => {
+                                let i = u32::from_le_bytes(record[..4].try_into().unwrap());
+                                assert_eq!(record.len(), 4 + i as usize % 9);
+                                seen.push(i);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(_) => thread::yield_now(),
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+        consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect()
+    });
+
+    seen.sort();
+    assert_eq!(seen, (0..RECORDS).collect::<Vec<_>>());
+}
@@ -0,0 +1,142 @@
+use fastfifo::mpmc::delay::{Clock, DelayFifo, MockClock};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+const SLOT: Duration = Duration::from_millis(10);
+
+#[test]
+fn pop_waits_for_the_deadline() {
+    let fifo = DelayFifo::with_clock(SLOT, 4, 4, MockClock::new());
+    let clock = fifo.clock().clone();
+
+    fifo.push_after(Duration::from_millis(25), 2);
+    fifo.push_after(Duration::from_millis(5), 1);
+    fifo.push_after(Duration::ZERO, 0);
+    assert_eq!(fifo.pop(), Ok(0));
+    assert!(fifo.pop().is_err());
+
+    clock.advance(Duration::from_millis(10));
+    assert_eq!(fifo.pop(), Ok(1));
+    assert!(fifo.pop().is_err());
+
+    clock.advance(Duration::from_millis(15));
+    assert!(fifo.pop().is_err());
+    clock.advance(Duration::from_millis(5));
+    assert_eq!(fifo.pop(), Ok(2));
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn past_deadlines_are_due() {
+    let fifo = DelayFifo::with_clock(SLOT, 2, 4, MockClock::new());
+    let clock = fifo.clock().clone();
+    let start = clock.now();
+
+    clock.advance(Duration::from_millis(100));
+    assert!(fifo.pop().is_err());
+    fifo.push_at(start, 0);
+    fifo.push_at(start + Duration::from_millis(50), 1);
+
+    let mut popped = [fifo.pop().unwrap(), fifo.pop().unwrap()];
+    popped.sort();
+    assert_eq!(popped, [0, 1]);
+}
+
+#[test]
+fn far_deadlines_come_within_reach() {
+    let fifo = DelayFifo::with_clock(SLOT, 2, 4, MockClock::new());
+    let clock = fifo.clock().clone();
+
+    for i in 0..8 {
+        fifo.push_after(SLOT * (8 - i), 8 - i);
+    }
+    assert_eq!(fifo.far(), 7);
+
+    for i in 1..=8 {
+        assert!(fifo.pop().is_err());
+        clock.advance(SLOT);
+        assert_eq!(fifo.pop(), Ok(i));
+    }
+    assert_eq!(fifo.far(), 0);
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn idle_fifo_catches_up_at_once() {
+    let width = Duration::from_micros(1);
+    let fifo = DelayFifo::with_clock(width, 4, 4, MockClock::new());
+    let clock = fifo.clock().clone();
+
+    fifo.push_after(Duration::ZERO, 0);
+    assert_eq!(fifo.pop(), Ok(0));
+
+    // A billion ticks, one lock each would take ages.
+    clock.advance(Duration::from_secs(1000));
+    assert!(fifo.pop().is_err());
+
+    fifo.push_after(width * 2, 2);
+    fifo.push_after(Duration::ZERO, 1);
+    assert_eq!(fifo.pop(), Ok(1));
+    assert!(fifo.pop().is_err());
+    clock.advance(width * 2);
+    assert_eq!(fifo.pop(), Ok(2));
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn due_items_drop_with_the_fifo() {
+    let value = std::sync::Arc::new(());
+    {
+        let fifo = DelayFifo::with_clock(SLOT, 2, 4, MockClock::new());
+        for i in 0..8 {
+            fifo.push_after(SLOT * i, value.clone());
+        }
+    }
+    assert_eq!(std::sync::Arc::strong_count(&value), 1);
+}
+
+#[test]
+fn threads_pop_every_item_once() {
+    const ITEMS: usize = 4000;
+    let fifo = DelayFifo::with_clock(SLOT, 8, 16, MockClock::new());
+    let clock = fifo.clock().clone();
+    let popped = AtomicUsize::new(0);
+
+    let sum: usize = thread::scope(|s| {
+        for p in 0..2 {
+            let fifo = fifo.clone();
+            s.spawn(move || {
+                for i in 0..ITEMS / 2 {
+                    fifo.push_after(SLOT * (i % 20) as u32, p * ITEMS / 2 + i);
+                }
+            });
+        }
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut sum = 0;
+                    while popped.load(Ordering::Relaxed) < ITEMS {
+                        match fifo.pop() {
+                            Ok(val) => {
+                                sum += val;
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(_) => {
+                                clock.advance(SLOT / 2);
+                                thread::yield_now();
+                            }
+                        }
+                    }
+                    sum
+                })
+            })
+            .collect();
+        consumers.into_iter().map(|c| c.join().unwrap()).sum()
+    });
+    assert_eq!(popped.into_inner(), ITEMS);
+    assert_eq!(sum, ITEMS * (ITEMS - 1) / 2);
+    assert!(fifo.pop().is_err());
+}
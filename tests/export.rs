@@ -0,0 +1,98 @@
+#![cfg(feature = "metrics")]
+
+use fastfifo::{export, generate_union, mpmc};
+use metrics::{SharedString, Unit};
+use metrics_util::{
+    CompositeKey,
+    debugging::{DebugValue, DebuggingRecorder},
+};
+
+generate_union! {
+    pub ExportUnion {
+        Producer: usize, atomic = false;
+        Consumer: (), atomic = true;
+    }
+}
+
+type Values = Vec<(CompositeKey, Option<Unit>, Option<SharedString>, DebugValue)>;
+
+/// The value recorded for `name` whose labels include all of `labels`. Taking a snapshot resets the
+/// recorder's counters, so every check works on one `Values`.
+fn value(values: &Values, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+    values
+        .iter()
+        .find(|(key, ..)| {
+            let key = key.key();
+            key.name() == name
+                && labels.iter().all(|&(label, value)| {
+                    key.labels().any(|l| l.key() == label && l.value() == value)
+                })
+        })
+        .map(|(.., value)| match value {
+            DebugValue::Counter(value) => *value as f64,
+            DebugValue::Gauge(value) => value.into_inner(),
+            DebugValue::Histogram(_) => unreachable!(),
+        })
+}
+
+#[test]
+fn export_pipeline() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let producer_labels = [("fifo", "pipeline"), ("stage", "Producer")];
+    let consumer_labels = [("fifo", "pipeline"), ("stage", "Consumer")];
+
+    let fifo = ExportUnionFifo::new(2, 2);
+    metrics::with_local_recorder(&recorder, || fifo.export_metrics("pipeline"));
+    let values = snapshotter.snapshot().into_vec();
+    assert_eq!(value(&values, export::QUEUED, &producer_labels), Some(4.0));
+    assert_eq!(value(&values, export::QUEUED, &consumer_labels), Some(0.0));
+
+    let (producer, consumer) = fifo.split();
+    assert!(consumer.get_entry().is_err());
+    for i in 0..3 {
+        producer.transform(|| i).unwrap();
+    }
+    consumer.transform(|_| {}).unwrap();
+    let entry = consumer.get_entry().unwrap();
+
+    // Any stage fifo publishes the whole pipeline.
+    metrics::with_local_recorder(&recorder, || consumer.export_metrics("pipeline"));
+    let values = snapshotter.snapshot().into_vec();
+    assert_eq!(value(&values, export::QUEUED, &producer_labels), Some(2.0));
+    assert_eq!(value(&values, export::TAKEN, &producer_labels), Some(3.0));
+    assert_eq!(value(&values, export::QUEUED, &consumer_labels), Some(1.0));
+    assert_eq!(
+        value(&values, export::IN_FLIGHT, &consumer_labels),
+        Some(1.0)
+    );
+    assert_eq!(value(&values, export::TAKEN, &consumer_labels), Some(2.0));
+    assert_eq!(
+        value(&values, export::NOT_AVAILABLE, &consumer_labels),
+        Some(1.0)
+    );
+    assert_eq!(value(&values, export::BUSY, &consumer_labels), Some(0.0));
+    drop(entry);
+}
+
+#[test]
+fn export_mpmc() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    let fifo = mpmc::FastFifo::new(2, 2);
+    for i in 0..3 {
+        fifo.push(i).unwrap();
+    }
+    fifo.pop().unwrap();
+
+    metrics::with_local_recorder(&recorder, || fifo.export_metrics("mpmc"));
+    let values = snapshotter.snapshot().into_vec();
+
+    let labels = [("fifo", "mpmc")];
+    assert_eq!(value(&values, export::DEPTH, &labels), Some(2.0));
+    assert_eq!(value(&values, export::PUSHES, &labels), Some(3.0));
+    assert_eq!(value(&values, export::POPS, &labels), Some(1.0));
+    assert_eq!(value(&values, export::PHEAD_ADVANCES, &labels), Some(1.0));
+    assert_eq!(value(&values, export::FULL, &labels), Some(0.0));
+}
@@ -1,6 +1,6 @@
 #![cfg(loom)]
 
-use fastfifo::generate_union;
+use fastfifo::{generate_union, mpmc::stealing::Worker};
 use loom::cell::UnsafeCell;
 use loom::sync::Arc;
 use loom::sync::atomic::{
@@ -42,6 +42,21 @@ generate_union! {
     }
 }
 
+generate_union! {
+    pub SingleUnion<T> {
+        Producer: T, atomic = false;
+        Consumer: (), atomic = false;
+    }
+}
+
+generate_union! {
+    pub MixedUnion<T, U> {
+        Producer: T, atomic = false;
+        Transformer: U, atomic = true;
+        Consumer: (), atomic = false;
+    }
+}
+
 #[test]
 fn simple_publishing_test() {
     loom::model(|| {
@@ -108,6 +123,167 @@ fn full_publishing_test() {
     });
 }
 
+#[test]
+fn non_atomic_publishing_test() {
+    loom::model(|| {
+        let (prod, cons) = SingleUnionFifo::<usize>::new(2, 1).split();
+
+        let p = {
+            thread::spawn(move || {
+                for i in 0..3 {
+                    while prod.transform(|| i).is_err() {
+                        loom::hint::spin_loop();
+                        loom::thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        let c = {
+            thread::spawn(move || {
+                for i in 0..3 {
+                    while cons.transform(|cont| assert_eq!(cont, i)).is_err() {
+                        loom::hint::spin_loop();
+                        loom::thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        p.join().unwrap();
+        c.join().unwrap();
+    })
+}
+
+#[test]
+fn mixed_publishing_test() {
+    loom::model(|| {
+        let (producer, transformer, consumer) = MixedUnionFifo::<usize, usize>::new(2, 10).split();
+
+        let p = {
+            thread::spawn(move || {
+                while producer.transform(|| 1).is_err() {
+                    loom::hint::spin_loop();
+                    loom::thread::yield_now();
+                }
+            })
+        };
+
+        let t = {
+            thread::spawn(move || {
+                while transformer.transform(|i| i + 1).is_err() {
+                    loom::hint::spin_loop();
+                    loom::thread::yield_now();
+                }
+            })
+        };
+
+        let c = {
+            thread::spawn(move || {
+                while consumer.transform(|i| assert_eq!(i, 2)).is_err() {
+                    loom::hint::spin_loop();
+                    loom::thread::yield_now();
+                }
+            })
+        };
+
+        p.join().unwrap();
+        t.join().unwrap();
+        c.join().unwrap();
+    });
+}
+
+#[test]
+fn shutdown_drains_test() {
+    loom::model(|| {
+        let (producer, consumer) = SimpleUnionFifo::<usize>::new(1, 2).split();
+
+        let p = thread::spawn(move || {
+            for i in 0..2 {
+                while producer.transform(|| i).is_err() {
+                    loom::thread::yield_now();
+                }
+            }
+            producer.shutdown();
+        });
+
+        let mut consumed = Vec::new();
+        loop {
+            match consumer.transform(|i| consumed.push(i)) {
+                Err(fastfifo::Error::Closed) => break,
+                Err(_) => loom::thread::yield_now(),
+                Ok(()) => {}
+            }
+        }
+
+        p.join().unwrap();
+
+        assert_eq!(consumed, [0, 1]);
+    })
+}
+
+#[test]
+fn shutdown_races_producer_test() {
+    loom::model(|| {
+        let (producer, consumer) = SimpleUnionFifo::<usize>::new(1, 2)
+            .concurrent_shutdown()
+            .split();
+
+        // Whatever the producer got in before the shutdown has to reach the consumer.
+        let p = thread::spawn(move || {
+            loop {
+                match producer.transform(|| 1) {
+                    Ok(()) => break true,
+                    Err(fastfifo::Error::Closed) => break false,
+                    Err(_) => loom::thread::yield_now(),
+                }
+            }
+        });
+
+        consumer.shutdown();
+
+        let mut consumed = 0;
+        loop {
+            match consumer.transform(|i| assert_eq!(i, 1)) {
+                Err(fastfifo::Error::Closed) => break,
+                Err(_) => loom::thread::yield_now(),
+                Ok(()) => consumed += 1,
+            }
+        }
+
+        assert_eq!(consumed, p.join().unwrap() as usize);
+    })
+}
+
+#[test]
+fn owner_races_steal_batch_test() {
+    loom::model(|| {
+        let worker = Worker::new(2, 4);
+        for i in 0..3 {
+            worker.push(i).unwrap();
+        }
+
+        let thief = {
+            let stealer = worker.stealer();
+            thread::spawn(move || {
+                stealer
+                    .steal_batch()
+                    .map(Iterator::collect)
+                    .unwrap_or_else(|_| Vec::new())
+            })
+        };
+
+        // The owner takes entries back from the end while the thief reserves from the start.
+        let mut taken: Vec<_> = (0..2).filter_map(|_| worker.pop().ok()).collect();
+        worker.push(3).unwrap();
+
+        taken.extend(thief.join().unwrap());
+        taken.extend(std::iter::from_fn(|| worker.pop().ok()));
+        taken.sort_unstable();
+        assert_eq!(taken, [0, 1, 2, 3]);
+    });
+}
+
 #[test]
 fn publish_then_consume_is_visible() {
     loom::model(|| {
@@ -0,0 +1,522 @@
+#[cfg(feature = "stats")]
+use fastfifo::mpmc::Stats;
+use fastfifo::mpmc::{FastFifo, PriorityFifo, SlotState, UnboundedFifo};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+#[test]
+fn grow_links_blocks_at_lap_end() {
+    let fifo = FastFifo::<usize>::with_max_blocks(2, 2, 4);
+
+    for i in 0..4 {
+        fifo.push(i).unwrap();
+    }
+    assert!(fifo.push(4).is_err());
+
+    fifo.grow(2).unwrap();
+    assert!(fifo.grow(1).is_err());
+
+    // The producer head already left the last block of its lap when it found the fifo full, so the new blocks
+    // wait for the next lap.
+    assert_eq!(fifo.num_blocks(), 2);
+    assert!(fifo.push(4).is_err());
+
+    // Blocks are reused once they have been popped from completely.
+    for block in 0..2 {
+        for i in block * 2..block * 2 + 2 {
+            assert_eq!(fifo.pop().unwrap(), i);
+        }
+        for i in block * 2..block * 2 + 2 {
+            fifo.push(i + 4).unwrap();
+        }
+    }
+    for i in 8..12 {
+        fifo.push(i).unwrap();
+    }
+    assert_eq!(fifo.num_blocks(), 4);
+    assert!(fifo.push(12).is_err());
+
+    for i in 4..12 {
+        assert_eq!(fifo.pop().unwrap(), i);
+    }
+    assert!(fifo.pop().is_err());
+
+    // Later laps go through all four blocks.
+    for lap in 0..3 {
+        for i in 0..8 {
+            fifo.push(lap * 8 + i).unwrap();
+        }
+        assert!(fifo.push(0).is_err());
+        for i in 0..8 {
+            assert_eq!(fifo.pop().unwrap(), lap * 8 + i);
+        }
+    }
+}
+
+#[test]
+fn grow_while_in_use() {
+    const PRODUCERS: usize = 2;
+    const CONSUMERS: usize = 2;
+    const ITEMS: usize = 20_000;
+    const MAX_BLOCKS: usize = 16;
+
+    let fifo = FastFifo::<(usize, usize)>::with_max_blocks(2, 8, MAX_BLOCKS);
+
+    let received: Vec<Vec<(usize, usize)>> = thread::scope(|s| {
+        for producer in 0..PRODUCERS {
+            let fifo = fifo.clone();
+            s.spawn(move || {
+                for i in 0..ITEMS {
+                    while fifo.push((producer, i)).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+        {
+            let fifo = fifo.clone();
+            // Grows one step per lap so the blocks get linked in while items flow.
+            s.spawn(move || {
+                let mut blocks = fifo.num_blocks();
+                while fifo.grow(2).is_ok() {
+                    blocks += 2;
+                    while fifo.num_blocks() < blocks {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let fifo = fifo.clone();
+                s.spawn(move || {
+                    let mut received = Vec::with_capacity(PRODUCERS * ITEMS);
+                    while received.len() < PRODUCERS * ITEMS / CONSUMERS {
+                        match fifo.pop() {
+                            Ok(item) => received.push(item),
+                            Err(_) => thread::yield_now(),
+                        }
+                    }
+                    received
+                })
+            })
+            .collect();
+
+        consumers.into_iter().map(|c| c.join().unwrap()).collect()
+    });
+
+    assert_fifo_order::<PRODUCERS, ITEMS>(&received);
+    assert_eq!(fifo.num_blocks(), MAX_BLOCKS);
+}
+
+#[test]
+fn unbounded_recycles_segments() {
+    const BLOCK_SIZE: usize = 4;
+
+    let fifo = UnboundedFifo::<usize>::new(BLOCK_SIZE);
+    assert!(fifo.pop().is_err());
+
+    for i in 0..100 {
+        fifo.push(i);
+    }
+    assert_eq!(fifo.allocated_segments(), 100 / BLOCK_SIZE);
+    for i in 0..100 {
+        assert_eq!(fifo.pop().unwrap(), i);
+    }
+    assert!(fifo.pop().is_err());
+
+    // The retired segments take the next burst, all but the tail which stays linked until the one behind it.
+    for i in 0..100 {
+        fifo.push(i);
+    }
+    for i in 0..100 {
+        assert_eq!(fifo.pop().unwrap(), i);
+    }
+    assert_eq!(fifo.allocated_segments(), 100 / BLOCK_SIZE + 1);
+
+    // A steady state never allocates.
+    for i in 0..1000 {
+        fifo.push(i);
+        assert_eq!(fifo.pop().unwrap(), i);
+    }
+    assert_eq!(fifo.allocated_segments(), 100 / BLOCK_SIZE + 1);
+
+    // Items still queued are dropped with the fifo.
+    let fifo = UnboundedFifo::new(BLOCK_SIZE);
+    for i in 0..10 {
+        fifo.push(vec![i]);
+    }
+}
+
+#[test]
+fn unbounded_threads() {
+    const PRODUCERS: usize = 2;
+    const CONSUMERS: usize = 2;
+    const ITEMS: usize = 20_000;
+
+    let fifo = UnboundedFifo::<(usize, usize)>::new(8);
+
+    let received: Vec<Vec<(usize, usize)>> = thread::scope(|s| {
+        for producer in 0..PRODUCERS {
+            let fifo = fifo.clone();
+            s.spawn(move || {
+                for i in 0..ITEMS {
+                    fifo.push((producer, i));
+                }
+            });
+        }
+
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let fifo = fifo.clone();
+                s.spawn(move || {
+                    let mut received = Vec::with_capacity(PRODUCERS * ITEMS);
+                    while received.len() < PRODUCERS * ITEMS / CONSUMERS {
+                        match fifo.pop() {
+                            Ok(item) => received.push(item),
+                            Err(_) => thread::yield_now(),
+                        }
+                    }
+                    received
+                })
+            })
+            .collect();
+
+        consumers.into_iter().map(|c| c.join().unwrap()).collect()
+    });
+
+    assert_fifo_order::<PRODUCERS, ITEMS>(&received);
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn priority_lanes() {
+    let fifo = PriorityFifo::<usize, 3>::new(2, 4);
+    assert!(fifo.pop().is_err());
+
+    for i in 0..4 {
+        fifo.push(2, 20 + i).unwrap();
+        fifo.push(0, i).unwrap();
+        fifo.push(1, 10 + i).unwrap();
+    }
+    for lane in 0..3 {
+        for i in 0..4 {
+            assert_eq!(fifo.clone().pop().unwrap(), lane * 10 + i);
+        }
+    }
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn priority_wait_covers_every_lane() {
+    let fifo = PriorityFifo::<usize, 3>::new(2, 4);
+
+    thread::scope(|s| {
+        let waiter = s.spawn(|| fifo.wait_pop());
+        thread::sleep(std::time::Duration::from_millis(10));
+        fifo.push(2, 20).unwrap();
+        assert_eq!(waiter.join().unwrap(), 20);
+    });
+
+    fifo.push(2, 21).unwrap();
+    fifo.push(1, 10).unwrap();
+    assert_eq!(fifo.wait_pop(), 10);
+    assert_eq!(fifo.wait_pop(), 21);
+}
+
+#[test]
+fn priority_wait_async_covers_every_lane() {
+    let fifo = PriorityFifo::<usize, 3>::new(2, 4);
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut wait = pin!(fifo.wait_pop_async());
+    assert!(wait.as_mut().poll(&mut cx).is_pending());
+    fifo.push(2, 20).unwrap();
+    assert_eq!(wait.as_mut().poll(&mut cx), Poll::Ready(20));
+
+    fifo.push(2, 21).unwrap();
+    fifo.push(1, 10).unwrap();
+    let mut wait = pin!(fifo.wait_pop_async());
+    assert_eq!(wait.as_mut().poll(&mut cx), Poll::Ready(10));
+}
+
+#[test]
+fn priority_weights() {
+    // Lane 0 lets lane 1 go once every two pops, lane 1 never yields to lane 2.
+    let fifo = PriorityFifo::<usize, 3>::with_weights(2, 8, [2, 0, 0]);
+
+    for i in 0..6 {
+        fifo.push(0, i).unwrap();
+        fifo.push(1, 10 + i).unwrap();
+        fifo.push(2, 20 + i).unwrap();
+    }
+
+    let popped: Vec<_> = (0..18).map(|_| fifo.pop().unwrap()).collect();
+    assert_eq!(
+        popped,
+        [
+            0, 1, 10, 2, 3, 11, 4, 5, 12, 13, 14, 15, 20, 21, 22, 23, 24, 25
+        ]
+    );
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn slices_stay_within_a_block() {
+    let fifo = FastFifo::<usize>::new(2, 4);
+
+    // Every slice below is written in full before it is dropped.
+    let mut slice = unsafe { fifo.try_reserve_slice(3) }.unwrap();
+    assert_eq!(slice.len(), 3);
+    assert!(!slice.is_short());
+    for (i, entry) in slice.iter_mut().enumerate() {
+        entry.write(i);
+    }
+    assert!(fifo.pop().is_err());
+    drop(slice);
+
+    // Only one entry is left in the first block.
+    let mut slice = unsafe { fifo.try_reserve_slice(3) }.unwrap();
+    assert_eq!(slice.len(), 1);
+    assert!(slice.is_short());
+    slice[0].write(3);
+    drop(slice);
+
+    let mut slice = unsafe { fifo.try_reserve_slice(8) }.unwrap();
+    assert_eq!(slice.len(), 4);
+    assert!(slice.is_short());
+    slice.iter_mut().zip(4..).for_each(|(entry, i)| {
+        entry.write(i);
+    });
+    drop(slice);
+    assert!(unsafe { fifo.try_reserve_slice(1) }.is_err());
+
+    assert_eq!(&*fifo.try_consume_slice(2).unwrap(), [0, 1]);
+    assert_eq!(&*fifo.try_consume_slice(8).unwrap(), [2, 3]);
+    assert_eq!(fifo.pop().unwrap(), 4);
+    assert_eq!(&*fifo.try_consume_slice(8).unwrap(), [5, 6, 7]);
+    assert!(fifo.try_consume_slice(1).is_err());
+}
+
+#[test]
+fn consuming_slice_drops_its_values() {
+    let value = std::sync::Arc::new(());
+    let fifo = FastFifo::new(2, 4);
+
+    for _ in 0..4 {
+        fifo.push(value.clone()).unwrap();
+    }
+    let slice = fifo.try_consume_slice(3).unwrap();
+    assert_eq!(std::sync::Arc::strong_count(&value), 5);
+    drop(slice);
+    assert_eq!(std::sync::Arc::strong_count(&value), 2);
+
+    drop(fifo);
+    assert_eq!(std::sync::Arc::strong_count(&value), 1);
+}
+
+#[test]
+fn panicking_drop_in_a_slice_frees_its_entries() {
+    #[derive(Debug)]
+    struct PanicOnDrop(bool);
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            if self.0 {
+                panic!("dropping a poisoned item");
+            }
+        }
+    }
+
+    let fifo = FastFifo::new(2, 4);
+    for i in 0..4 {
+        fifo.push(PanicOnDrop(i == 1)).unwrap();
+    }
+
+    let slice = fifo.try_consume_slice(4).unwrap();
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(slice))).is_err());
+
+    // The block counts as consumed, so the next laps have room again.
+    for _ in 0..2 {
+        for _ in 0..4 {
+            fifo.push(PanicOnDrop(false)).unwrap();
+        }
+        while fifo.pop().is_ok() {}
+    }
+}
+
+#[test]
+fn slice_threads() {
+    const ITEMS: usize = 10_000;
+    let fifo = FastFifo::<usize>::new(4, 16);
+
+    let received = thread::scope(|s| {
+        s.spawn(|| {
+            let mut next = 0;
+            while next < ITEMS {
+                let Ok(mut slice) = (unsafe { fifo.try_reserve_slice(next % 7 + 1) }) else {
+                    thread::yield_now();
+                    continue;
+                };
+                // Every entry must be written, the last slice may go past ITEMS.
+                for entry in slice.iter_mut() {
+                    entry.write(next);
+                    next += 1;
+                }
+            }
+        });
+        s.spawn(|| {
+            let mut received = Vec::<usize>::new();
+            while received.len() < ITEMS {
+                match fifo.try_consume_slice(5) {
+                    Ok(slice) => received.extend(slice.iter().filter(|&&i| i < ITEMS)),
+                    Err(_) => thread::yield_now(),
+                }
+            }
+            received
+        })
+        .join()
+        .unwrap()
+    });
+    assert_eq!(received, (0..ITEMS).collect::<Vec<_>>());
+}
+
+#[test]
+fn inspect_slot_states() {
+    use SlotState::*;
+
+    let fifo = FastFifo::new(2, 4);
+    for i in 0..3 {
+        fifo.push(i).unwrap();
+    }
+    assert_eq!(fifo.pop(), Ok(0));
+
+    let reserved = fifo.try_get_consumer_entry().unwrap();
+    let allocated = fifo.try_get_producer_entry().unwrap();
+
+    assert_eq!(
+        fifo.inspect(),
+        [
+            [Consumed, Reserved, Committed, Allocated],
+            // Left behind by both heads since the fifo was built.
+            [Consumed; 4],
+        ]
+    );
+
+    drop(reserved);
+    drop(allocated);
+    assert_eq!(
+        fifo.inspect()[0],
+        [Consumed, Consumed, Committed, Committed]
+    );
+}
+
+#[test]
+fn debug_leaves_values_alone() {
+    let counter = Arc::new(());
+    let fifo = FastFifo::new(2, 2);
+    fifo.push(counter.clone()).unwrap();
+
+    assert_eq!(
+        format!("{fifo:?}"),
+        "[[Committed, Uninit], [Consumed, Consumed]]"
+    );
+    assert_eq!(Arc::strong_count(&counter), 2);
+    assert_eq!(fifo.pop().map(|_| ()), Ok(()));
+    assert_eq!(Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn debug_values_is_exclusive() {
+    let mut fifo = FastFifo::new(2, 2);
+    fifo.push("a").unwrap();
+    fifo.push("b").unwrap();
+    fifo.push("c").unwrap();
+    assert_eq!(fifo.pop(), Ok("a"));
+
+    let clone = fifo.clone();
+    assert!(fifo.debug_values().is_none());
+    drop(clone);
+
+    assert_eq!(
+        format!("{:?}", fifo.debug_values().unwrap()),
+        r#"[[Consumed, "b"], ["c", Uninit]]"#
+    );
+}
+
+/// Every consumer sees each producer's items in the order they were pushed, and every item is seen once.
+fn assert_fifo_order<const PRODUCERS: usize, const ITEMS: usize>(received: &[Vec<(usize, usize)>]) {
+    let mut seen = vec![vec![false; ITEMS]; PRODUCERS];
+    for received in received {
+        let mut last = [None; PRODUCERS];
+        for &(producer, i) in received {
+            assert!(last[producer] < Some(i));
+            last[producer] = Some(i);
+            assert!(!seen[producer][i]);
+            seen[producer][i] = true;
+        }
+    }
+    assert!(seen.iter().flatten().all(|&seen| seen));
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats_snapshot() {
+    const NUM_BLOCKS: usize = 2;
+    const BLOCK_SIZE: usize = 2;
+
+    let fifo = FastFifo::<usize>::new(NUM_BLOCKS, BLOCK_SIZE);
+    assert_eq!(fifo.snapshot(), Stats::default());
+
+    assert!(fifo.pop().is_err());
+    for i in 0..NUM_BLOCKS * BLOCK_SIZE {
+        fifo.push(i).unwrap();
+    }
+    assert!(fifo.push(0).is_err());
+
+    // Clones share the counters.
+    assert_eq!(
+        fifo.clone().snapshot(),
+        Stats {
+            pushes: 4,
+            full: 1,
+            empty: 1,
+            phead_advances: 1,
+            phead_advance_failures: 1,
+            ..Default::default()
+        }
+    );
+
+    for i in 0..NUM_BLOCKS * BLOCK_SIZE {
+        assert_eq!(fifo.pop().unwrap(), i);
+    }
+    assert!(fifo.pop().is_err());
+
+    // Another lap, the producer head wraps back to the first block. Consumers have to wait for the
+    // uncommitted entry before they can take the committed one in front of it.
+    fifo.push(4).unwrap();
+    let entry = fifo.try_get_producer_entry().unwrap();
+    assert!(fifo.pop().is_err());
+    drop(entry);
+    assert_eq!(fifo.pop().unwrap(), 4);
+
+    assert_eq!(
+        fifo.snapshot(),
+        Stats {
+            pushes: 6,
+            pops: 5,
+            full: 1,
+            busy: 1,
+            empty: 2,
+            phead_advances: 2,
+            phead_advance_failures: 1,
+            chead_advances: 2,
+            chead_advance_failures: 1,
+            wraparounds: 1,
+        }
+    );
+}
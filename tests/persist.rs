@@ -0,0 +1,167 @@
+#![cfg(feature = "persist")]
+
+use fastfifo::mpmc::persist::FastFifo;
+use std::{
+    env, fs, io,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+const CHILD: &str = "FASTFIFO_PERSIST_CHILD";
+
+/// A file of its own for each test, removed when done.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("fastfifo-{}-{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn items_survive_reopening() {
+    let file = TempFile::new("reopen");
+    {
+        let fifo = FastFifo::<u64>::open(&file.0, 2, 4).unwrap();
+        for i in 0..6 {
+            fifo.push(i).unwrap();
+        }
+        assert_eq!(fifo.pop(), Ok(0));
+        fifo.flush().unwrap();
+    }
+
+    let fifo = FastFifo::<u64>::open(&file.0, 2, 4).unwrap();
+    fifo.push(6).unwrap();
+    fifo.push(7).unwrap();
+    // The first block is not consumed yet.
+    assert!(fifo.push(8).is_err());
+
+    let popped: Vec<_> = (1..8).map(|_| fifo.pop().unwrap()).collect();
+    assert_eq!(popped, (1..8).collect::<Vec<_>>());
+    assert!(fifo.pop().is_err());
+}
+
+#[test]
+fn laps_survive_reopening() {
+    let file = TempFile::new("laps");
+
+    for lap in 0..5u64 {
+        let fifo = FastFifo::<u64>::open(&file.0, 3, 2).unwrap();
+        for i in 0..5 {
+            fifo.push(lap * 10 + i).unwrap();
+        }
+        drop(fifo);
+
+        let fifo = FastFifo::<u64>::open(&file.0, 3, 2).unwrap();
+        let popped: Vec<_> = (0..5).map(|_| fifo.pop().unwrap()).collect();
+        assert_eq!(popped, (0..5).map(|i| lap * 10 + i).collect::<Vec<_>>());
+        assert!(fifo.pop().is_err());
+    }
+}
+
+#[test]
+fn other_geometry_is_refused() {
+    let file = TempFile::new("geometry");
+    drop(FastFifo::<u64>::open(&file.0, 2, 4).unwrap());
+
+    assert!(FastFifo::<u64>::open(&file.0, 4, 2).is_err());
+    assert!(FastFifo::<u32>::open(&file.0, 2, 4).is_err());
+    // Same size, another type.
+    assert!(FastFifo::<f64>::open(&file.0, 2, 4).is_err());
+    assert!(FastFifo::<u64>::open(&file.0, 2, 4).is_ok());
+}
+
+#[test]
+fn cut_short_creation_is_redone() {
+    let file = TempFile::new("cut-short");
+    drop(FastFifo::<u64>::open(&file.0, 2, 4).unwrap());
+
+    // A crash right after the file got its length leaves it zeroed, header included.
+    let len = fs::metadata(&file.0).unwrap().len();
+    fs::write(&file.0, vec![0; len as usize]).unwrap();
+
+    let fifo = FastFifo::<u64>::open(&file.0, 2, 4).unwrap();
+    assert!(fifo.pop().is_err());
+    fifo.push(1).unwrap();
+    drop(fifo);
+
+    let fifo = FastFifo::<u64>::open(&file.0, 2, 4).unwrap();
+    assert_eq!(fifo.pop(), Ok(1));
+}
+
+#[test]
+fn open_file_is_locked() {
+    let file = TempFile::new("locked");
+    let fifo = FastFifo::<u64>::open(&file.0, 2, 4).unwrap();
+
+    let error = FastFifo::<u64>::open(&file.0, 2, 4).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+
+    // Clones share the lock.
+    drop(fifo.clone());
+    drop(fifo);
+    assert!(FastFifo::<u64>::open(&file.0, 2, 4).is_ok());
+}
+
+/// Only does something when run by [`killed_writer_loses_nothing`], pushes and pops until killed.
+#[test]
+fn child_writer() {
+    let Some(path) = env::var_os(CHILD) else {
+        return;
+    };
+    let fifo = FastFifo::<u64>::open(path, 4, 8).unwrap();
+
+    for i in 0.. {
+        while fifo.push(i).is_err() {
+            fifo.pop().unwrap();
+        }
+    }
+}
+
+#[test]
+fn killed_writer_loses_nothing() {
+    let file = TempFile::new("killed");
+
+    for _ in 0..3 {
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(["child_writer", "--exact", "--nocapture"])
+            .env(CHILD, &file.0)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // Every item left is there once and in order, the one being written may be missing and the one being
+        // read may come again.
+        let fifo = FastFifo::<u64>::open(&file.0, 4, 8).unwrap();
+        let mut popped = Vec::new();
+        while let Ok(i) = fifo.pop() {
+            popped.push(i);
+        }
+        assert!(!popped.is_empty());
+        assert!(
+            popped.windows(2).all(|pair| pair[1] == pair[0] + 1),
+            "{popped:?}"
+        );
+
+        // The fifo goes on where it was.
+        for i in 0..16 {
+            fifo.push(i).unwrap();
+        }
+        let popped: Vec<_> = (0..16).map(|_| fifo.pop().unwrap()).collect();
+        assert_eq!(popped, (0..16).collect::<Vec<_>>());
+        assert!(fifo.pop().is_err());
+    }
+}
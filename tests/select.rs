@@ -0,0 +1,112 @@
+use fastfifo::{
+    Error, generate_union,
+    mpmc::{ConsumingEntry, FastFifo},
+    select::Select,
+};
+use std::thread;
+
+generate_union! {
+    pub SelectUnion<Input, Output> {
+        Producer: Input, atomic = false;
+        Transformer: Output, atomic = false;
+        Consumer: (), atomic = false;
+    }
+}
+
+fn read<T>(mut entry: ConsumingEntry<'_, T>) -> T {
+    let mut val = None;
+    entry.consume_t_in_place(|ptr| val = Some(unsafe { ptr.read() }));
+    val.unwrap()
+}
+
+#[test]
+fn recv_takes_turns() {
+    let (a, b) = (FastFifo::new(2, 4), FastFifo::new(2, 4));
+    for i in 0..4 {
+        a.push(i).unwrap();
+        b.push(10 + i).unwrap();
+    }
+
+    let mut select = Select::new().recv(&a, read).recv(&b, read);
+    let mut popped: Vec<_> = (0..8).map(|_| select.try_ready().unwrap()).collect();
+    assert_eq!(select.try_ready(), Err(Error::NotAvailable));
+
+    // Neither fifo is tried first twice in a row while both have items.
+    assert!(
+        popped
+            .windows(2)
+            .all(|pair| (pair[0] < 10) != (pair[1] < 10))
+    );
+    popped.sort();
+    assert_eq!(popped, [0, 1, 2, 3, 10, 11, 12, 13]);
+}
+
+#[test]
+fn send_only_takes_the_selected_entry() {
+    let (full, empty) = (FastFifo::new(2, 1), FastFifo::<usize>::new(2, 1));
+    full.push(1).unwrap();
+    full.push(2).unwrap();
+
+    for _ in 0..2 {
+        let mut select = Select::new()
+            .send(&full, |_| unreachable!("the fifo is full"))
+            .send(&empty, |mut entry| {
+                entry.produce_t_in_place(|ptr| unsafe { ptr.write(3) })
+            });
+        select.try_ready().unwrap();
+    }
+
+    assert_eq!(full.pop(), Ok(1));
+    assert_eq!(empty.pop(), Ok(3));
+    assert_eq!(empty.pop(), Ok(3));
+}
+
+#[test]
+fn stage_closes() {
+    let (producer, transformer, consumer) = SelectUnionFifo::<usize, usize>::new(2, 2).split();
+    let requests = FastFifo::<usize>::new(2, 2);
+
+    producer.transform(|| 1).unwrap();
+    producer.shutdown();
+
+    let mut select = Select::new()
+        .stage(
+            || transformer.get_entry(),
+            |mut entry| entry.transform(|input| input + 1),
+        )
+        .stage(
+            || consumer.get_entry(),
+            |mut entry| entry.transform(|output| assert_eq!(output, 2)),
+        );
+
+    // The transformer drains before the consumer can, then both stages are closed.
+    assert_eq!(select.ready(), Ok(()));
+    assert_eq!(select.ready(), Ok(()));
+    assert_eq!(select.ready(), Err(Error::Closed));
+
+    // An mpmc fifo never closes.
+    let mut select = Select::new()
+        .stage(|| consumer.get_entry(), |_| 0)
+        .recv(&requests, read);
+    assert_eq!(select.try_ready(), Err(Error::NotAvailable));
+}
+
+#[test]
+fn ready_waits_for_another_thread() {
+    let (a, b) = (FastFifo::<usize>::new(2, 2), FastFifo::new(2, 2));
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..100 {
+                while b.push(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut select = Select::new().recv(&a, read).recv(&b, read);
+        for i in 0..100 {
+            assert_eq!(select.ready(), Ok(i));
+        }
+    });
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "serde")]
+
+use fastfifo::mpmc::{FastFifo, Snapshot};
+
+#[test]
+fn save_is_exclusive() {
+    let mut fifo = FastFifo::new(2, 2);
+    fifo.push(1).unwrap();
+
+    let clone = fifo.clone();
+    assert!(fifo.save().is_none());
+    drop(clone);
+    assert_eq!(fifo.save().unwrap().items, [&1]);
+}
+
+#[test]
+fn save_in_consumer_order() {
+    let mut fifo = FastFifo::new(3, 2);
+
+    // Wrap around so the consumer head is not in the first block.
+    for i in 0..5 {
+        fifo.push(i).unwrap();
+    }
+    for i in 0..3 {
+        assert_eq!(fifo.pop(), Ok(i));
+    }
+    for i in 5..8 {
+        fifo.push(i).unwrap();
+    }
+
+    assert_eq!(
+        fifo.save().unwrap(),
+        Snapshot {
+            num_blocks: 3,
+            block_size: 2,
+            max_blocks: 3,
+            items: vec![&3, &4, &5, &6, &7],
+        }
+    );
+}
+
+#[test]
+fn json_round_trip() {
+    let mut fifo = FastFifo::new(2, 4);
+    for word in ["a", "bb", "ccc"] {
+        fifo.push(word.to_string()).unwrap();
+    }
+    assert_eq!(fifo.pop(), Ok("a".to_string()));
+
+    let json = serde_json::to_string(&fifo.save().unwrap()).unwrap();
+    let mut restored = FastFifo::<String>::restore(serde_json::from_str(&json).unwrap()).unwrap();
+
+    let snapshot = restored.save().unwrap();
+    assert_eq!((snapshot.num_blocks, snapshot.block_size), (2, 4));
+    assert_eq!(restored.pop(), Ok("bb".to_string()));
+    assert_eq!(restored.pop(), Ok("ccc".to_string()));
+    assert!(restored.pop().is_err());
+}
+
+#[test]
+fn restore_keeps_max_blocks() {
+    let mut fifo = FastFifo::with_max_blocks(2, 2, 4);
+    fifo.grow(1).unwrap();
+    fifo.push(1).unwrap();
+
+    let snapshot = fifo.save().unwrap();
+    assert_eq!(snapshot.max_blocks, 4);
+
+    let restored = FastFifo::restore(Snapshot {
+        num_blocks: snapshot.num_blocks,
+        block_size: snapshot.block_size,
+        max_blocks: snapshot.max_blocks,
+        items: snapshot.items.into_iter().copied().collect(),
+    })
+    .unwrap();
+    restored.grow(2).unwrap();
+    assert!(restored.grow(1).is_err());
+    assert_eq!(restored.pop(), Ok(1));
+}
+
+#[test]
+fn restore_too_many() {
+    let snapshot = Snapshot {
+        num_blocks: 2,
+        block_size: 2,
+        max_blocks: 2,
+        items: (0..5).collect(),
+    };
+
+    assert!(FastFifo::restore(snapshot).is_err());
+}
@@ -0,0 +1,164 @@
+use fastfifo::mpmc::stealing::Worker;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+#[test]
+fn owner_pops_last_pushed_first() {
+    let worker = Worker::new(2, 4);
+    for i in 0..8 {
+        worker.push(i).unwrap();
+    }
+    assert_eq!(worker.push(8), Err(8));
+
+    // The producer head's block is taken back from its end, the older one is popped from its start.
+    let popped: Vec<_> = (0..8).map(|_| worker.pop().unwrap()).collect();
+    assert_eq!(popped, [7, 6, 5, 4, 0, 1, 2, 3]);
+    assert!(worker.pop().is_err());
+
+    worker.push(8).unwrap();
+    worker.push(9).unwrap();
+    assert_eq!(worker.pop(), Ok(9));
+    assert_eq!(worker.pop(), Ok(8));
+}
+
+#[test]
+fn owner_leaves_the_last_entry_to_thieves() {
+    let worker = Worker::new(2, 4);
+    let stealer = worker.stealer();
+    for i in 0..3 {
+        worker.push(i).unwrap();
+    }
+
+    assert_eq!(stealer.steal(), Ok(0));
+    assert_eq!(worker.pop(), Ok(2));
+    // Only one entry is left, the owner reserves it like a thief would.
+    assert_eq!(worker.pop(), Ok(1));
+    assert!(stealer.steal().is_err());
+}
+
+#[test]
+fn steal_batch_takes_half_a_block() {
+    let worker = Worker::new(2, 8);
+    let stealer = worker.stealer();
+    for i in 0..7 {
+        worker.push(i).unwrap();
+    }
+
+    let batch = stealer.steal_batch().unwrap();
+    assert_eq!(batch.len(), 4);
+    assert_eq!(batch.collect::<Vec<_>>(), [0, 1, 2, 3]);
+
+    assert_eq!(stealer.steal_batch().unwrap().collect::<Vec<_>>(), [4, 5]);
+    assert_eq!(worker.pop(), Ok(6));
+    assert!(stealer.steal_batch().is_err());
+}
+
+#[test]
+fn dropped_batch_drops_its_values() {
+    let worker = Worker::new(2, 4);
+    let value = std::sync::Arc::new(());
+    for _ in 0..4 {
+        worker.push(value.clone()).unwrap();
+    }
+
+    let stealer = worker.stealer();
+    let mut batch = stealer.steal_batch().unwrap();
+    batch.next();
+    drop(batch);
+    assert_eq!(std::sync::Arc::strong_count(&value), 3);
+
+    // The block is consumed once the rest is popped, so the next laps have room again.
+    while worker.pop().is_ok() {}
+    for _ in 0..2 {
+        for _ in 0..4 {
+            worker.push(value.clone()).unwrap();
+        }
+        while worker.pop().is_ok() {}
+    }
+}
+
+#[test]
+fn panicking_drop_in_a_batch_frees_its_entries() {
+    #[derive(Debug)]
+    struct PanicOnDrop(bool);
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            if self.0 {
+                panic!("dropping a poisoned item");
+            }
+        }
+    }
+
+    let worker = Worker::new(2, 4);
+    for i in 0..4 {
+        worker.push(PanicOnDrop(i == 1)).unwrap();
+    }
+
+    let stealer = worker.stealer();
+    let batch = stealer.steal_batch().unwrap();
+    assert_eq!(batch.len(), 2);
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(batch))).is_err());
+
+    // The block is consumed once the rest is popped, so the next laps have room again.
+    while worker.pop().is_ok() {}
+    for _ in 0..2 {
+        for _ in 0..4 {
+            worker.push(PanicOnDrop(false)).unwrap();
+        }
+        while worker.pop().is_ok() {}
+    }
+}
+
+#[test]
+fn every_item_is_taken_once() {
+    const ITEMS: usize = 10_000;
+    let worker = Worker::new(4, 16);
+    let taken: Vec<_> = (0..ITEMS).map(|_| AtomicUsize::new(0)).collect();
+    let remaining = AtomicUsize::new(ITEMS);
+
+    thread::scope(|s| {
+        for thief in 0..3 {
+            let stealer = worker.stealer();
+            let (taken, remaining) = (&taken, &remaining);
+            let take = move |i: usize| {
+                taken[i].fetch_add(1, Ordering::Relaxed);
+                remaining.fetch_sub(1, Ordering::Relaxed);
+            };
+            s.spawn(move || {
+                while remaining.load(Ordering::Relaxed) != 0 {
+                    // One thief steals single items, racing the owner for the last entries.
+                    let stolen = match thief {
+                        0 => stealer.steal().map(take),
+                        _ => stealer.steal_batch().map(|batch| batch.for_each(take)),
+                    };
+                    if stolen.is_err() {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+
+        for i in 0..ITEMS {
+            let mut val = i;
+            while let Err(back) = worker.push(val) {
+                val = back;
+                if let Ok(i) = worker.pop() {
+                    taken[i].fetch_add(1, Ordering::Relaxed);
+                    remaining.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+        }
+        while remaining.load(Ordering::Relaxed) != 0 {
+            if let Ok(i) = worker.pop() {
+                taken[i].fetch_add(1, Ordering::Relaxed);
+                remaining.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    });
+
+    assert!(taken.iter().all(|count| count.load(Ordering::Relaxed) == 1));
+}
@@ -0,0 +1,7 @@
+#![cfg(not(loom))]
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
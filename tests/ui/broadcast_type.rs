@@ -0,0 +1,11 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<Input, Output> {
+        Producer: Input, atomic = false;
+        Observer: Output, atomic = false, broadcast = true;
+        Consumer: (), atomic = false;
+    }
+}
+
+fn main() {}
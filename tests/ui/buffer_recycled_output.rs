@@ -0,0 +1,11 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<T> {
+        Producer: T, atomic = false;
+        Transformer: T, atomic = true, buffer;
+        Consumer: T, atomic = false;
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<T> {
+        ProducerA: T, atomic = true;
+        producer_a: (), atomic = true;
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<T, const N: usize> {
+        Producer: [T; N], atomic = true;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {}
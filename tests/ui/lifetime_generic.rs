@@ -0,0 +1,10 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<'a, T> {
+        Producer: &'a T, atomic = true;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<T> {
+        Producer: T;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {}
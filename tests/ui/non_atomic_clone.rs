@@ -0,0 +1,15 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<T> {
+        Producer: T, atomic = false;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {
+    let (producer, consumer) = PipelineFifo::<usize>::new(2, 2).split();
+
+    let _consumer = consumer.clone();
+    let _producer = producer.clone();
+}
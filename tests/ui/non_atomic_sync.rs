@@ -0,0 +1,18 @@
+use fastfifo::generate_union;
+use std::thread;
+
+generate_union! {
+    pub Pipeline<T> {
+        Producer: T, atomic = false;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {
+    let (producer, _consumer) = PipelineFifo::<usize>::new(2, 2).split();
+
+    thread::scope(|s| {
+        s.spawn(|| producer.transform(|| 1));
+        s.spawn(|| producer.transform(|| 2));
+    });
+}
@@ -0,0 +1,14 @@
+use fastfifo::{config::TaggedClone, generate_union};
+
+generate_union! {
+    pub Pipeline<T> {
+        Producer: T, atomic = !true;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {
+    let (producer, _consumer) = PipelineFifo::<usize>::new(2, 2).split();
+
+    let _producer = producer.tagged_clone(PipelineTag::Producer);
+}
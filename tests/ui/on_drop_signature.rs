@@ -0,0 +1,12 @@
+use fastfifo::generate_union;
+
+fn log_string(_: String) {}
+
+generate_union! {
+    pub Pipeline {
+        Producer: usize, atomic = true, on_drop = log_string;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[fastfifo::pipeline]
+pub enum Stages<T> {
+    #[atomic(true)]
+    Producer(T),
+    Consumer,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[fastfifo::pipeline]
+pub enum Stages<T> {
+    Producer(T),
+    Consumer { value: T },
+}
+
+fn main() {}
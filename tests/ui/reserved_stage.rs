@@ -0,0 +1,10 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<T> {
+        Type: T, atomic = true;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {}
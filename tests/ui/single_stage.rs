@@ -0,0 +1,9 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<T> {
+        Producer: T, atomic = true;
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use fastfifo::generate_union;
+
+generate_union! {
+    pub Pipeline<T> {
+        Producer: T, atomic = true, on_free = drop;
+        Consumer: (), atomic = true;
+    }
+}
+
+fn main() {}
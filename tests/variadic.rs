@@ -0,0 +1,637 @@
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use fastfifo::{Error, config::FifoTag, generate_union};
+use std::thread;
+
+generate_union! {
+    pub InOutUnion<Input, Output> {
+        Producer: Input, atomic = false;
+        Transformer: Output, atomic = true;
+        Consumer: (), atomic = false;
+    }
+}
+
+#[test]
+fn lockstep_wraps_around() {
+    const NUM_BLOCKS: usize = 2;
+    const BLOCK_SIZE: usize = 2;
+
+    let (producer, transformer, consumer) =
+        InOutUnionFifo::<usize, usize>::new(NUM_BLOCKS, BLOCK_SIZE).split();
+
+    // Several laps, every block has to be handed over to the next version each time.
+    for start in (0..10 * NUM_BLOCKS * BLOCK_SIZE).step_by(BLOCK_SIZE) {
+        for i in start..start + BLOCK_SIZE {
+            producer.transform(|| i).unwrap();
+        }
+        for _ in start..start + BLOCK_SIZE {
+            transformer.transform(|input| input + 1).unwrap();
+        }
+        for i in start..start + BLOCK_SIZE {
+            consumer
+                .transform(|output| assert_eq!(output, i + 1))
+                .unwrap();
+        }
+    }
+}
+
+#[test]
+fn drop_after_a_stage_moved_on() {
+    let counter = Arc::new(());
+
+    {
+        let (producer, transformer, consumer) =
+            InOutUnionFifo::<Arc<()>, Arc<()>>::new(2, 2).split();
+
+        for _ in 0..4 {
+            producer.transform(|| counter.clone()).unwrap();
+            transformer.transform(|input| input).unwrap();
+            consumer.transform(drop).unwrap();
+        }
+        // The transformer moves on to the next lap of the first block before the producer does.
+        assert!(transformer.get_entry().is_err());
+
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    assert_eq!(Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn drop_in_flight() {
+    let counter = Arc::new(());
+
+    {
+        let (producer, transformer, consumer) =
+            InOutUnionFifo::<Arc<()>, Arc<()>>::new(2, 2).split();
+
+        // Wrap around once so the blocks hold entries from two different laps.
+        for _ in 0..5 {
+            producer.transform(|| counter.clone()).unwrap();
+            transformer.transform(|input| input).unwrap();
+            consumer.transform(drop).unwrap();
+        }
+
+        producer.transform(|| counter.clone()).unwrap();
+        producer.transform(|| counter.clone()).unwrap();
+        transformer.transform(|input| input).unwrap();
+
+        assert_eq!(Arc::strong_count(&counter), 3);
+    }
+
+    assert_eq!(Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn shutdown_drains_in_order() {
+    let (producer, transformer, consumer) = InOutUnionFifo::<usize, usize>::new(2, 2).split();
+
+    for i in 0..3 {
+        producer.transform(|| i).unwrap();
+    }
+    transformer.transform(|input| input * 2).unwrap();
+
+    producer.shutdown();
+    assert!(consumer.is_closed());
+
+    assert_eq!(producer.transform(|| 3).unwrap_err(), Error::Closed);
+
+    // The consumer can only drain what the transformer already handed over.
+    consumer.transform(|output| assert_eq!(output, 0)).unwrap();
+    assert_eq!(consumer.get_entry().err(), Some(Error::NotAvailable));
+
+    for i in 1..3 {
+        transformer.transform(|input| input * 2).unwrap();
+        consumer
+            .transform(|output| assert_eq!(output, i * 2))
+            .unwrap();
+    }
+
+    assert_eq!(transformer.get_entry().err(), Some(Error::Closed));
+    assert_eq!(consumer.get_entry().err(), Some(Error::Closed));
+}
+
+#[test]
+fn shutdown_drains_threads() {
+    const ITEMS: usize = 1000;
+
+    let (producer, transformer, consumer) = InOutUnionFifo::<usize, usize>::new(4, 8).split();
+
+    let consumed = thread::scope(move |s| {
+        s.spawn(move || {
+            for i in 0..ITEMS {
+                while producer.transform(|| i).is_err() {
+                    thread::yield_now();
+                }
+            }
+            producer.shutdown();
+        });
+
+        s.spawn(move || {
+            loop {
+                match transformer.transform(|input| input + 1) {
+                    Err(Error::Closed) => break,
+                    Err(_) => thread::yield_now(),
+                    Ok(()) => {}
+                }
+            }
+        });
+
+        s.spawn(move || {
+            let mut consumed = Vec::with_capacity(ITEMS);
+            loop {
+                match consumer.transform(|output| consumed.push(output)) {
+                    Err(Error::Closed) => break consumed,
+                    Err(_) => thread::yield_now(),
+                    Ok(()) => {}
+                }
+            }
+        })
+        .join()
+        .unwrap()
+    });
+
+    assert_eq!(consumed, (1..=ITEMS).collect::<Vec<_>>());
+}
+
+#[test]
+fn concurrent_shutdown_loses_nothing() {
+    let (producer, transformer, consumer) = InOutUnionFifo::<usize, usize>::new(4, 8)
+        .concurrent_shutdown()
+        .split();
+
+    let (produced, consumed) = thread::scope(move |s| {
+        let producer = s.spawn(move || {
+            let mut produced = 0;
+            loop {
+                match producer.transform(|| produced) {
+                    Ok(()) => produced += 1,
+                    Err(Error::Closed) => break produced,
+                    Err(_) => thread::yield_now(),
+                }
+            }
+        });
+
+        s.spawn(move || {
+            while !matches!(transformer.transform(|input| input), Err(Error::Closed)) {
+                thread::yield_now();
+            }
+        });
+
+        let mut consumed = 0;
+        loop {
+            // Shuts down from another thread than the producer's, while it produces.
+            if consumed == 100 {
+                consumer.shutdown();
+            }
+            match consumer.transform(|output| assert_eq!(output, consumed)) {
+                Ok(()) => consumed += 1,
+                Err(Error::Closed) => break,
+                Err(_) => thread::yield_now(),
+            }
+        }
+        (producer.join().unwrap(), consumed)
+    });
+
+    assert_eq!(produced, consumed);
+}
+
+#[test]
+fn stage_stats_occupancy() {
+    let (producer, transformer, consumer) = InOutUnionFifo::<usize, usize>::new(2, 2).split();
+
+    let occupancy = || {
+        consumer
+            .stage_stats()
+            .iter()
+            .map(|stats| (stats.tag, stats.block, stats.queued, stats.in_flight))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        occupancy(),
+        [
+            (InOutUnionTag::Producer, None, 4, 0),
+            (InOutUnionTag::Transformer, Some(0), 0, 0),
+            (InOutUnionTag::Consumer, None, 0, 0),
+        ]
+    );
+
+    for i in 0..3 {
+        producer.transform(|| i).unwrap();
+    }
+    // Keeps the entry in flight until it is dropped.
+    let transforming = transformer.get_entry().unwrap();
+
+    assert_eq!(
+        occupancy(),
+        [
+            (InOutUnionTag::Producer, None, 1, 0),
+            (InOutUnionTag::Transformer, Some(0), 2, 1),
+            (InOutUnionTag::Consumer, None, 0, 0),
+        ]
+    );
+
+    drop(transforming);
+
+    assert_eq!(
+        occupancy(),
+        [
+            (InOutUnionTag::Producer, None, 1, 0),
+            (InOutUnionTag::Transformer, Some(0), 2, 0),
+            (InOutUnionTag::Consumer, None, 1, 0),
+        ]
+    );
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn stage_stats_counters() {
+    let (producer, transformer, consumer) = InOutUnionFifo::<usize, usize>::new(1, 2).split();
+
+    assert_eq!(consumer.get_entry().err(), Some(Error::NotAvailable));
+    assert_eq!(transformer.get_entry().err(), Some(Error::NotAvailable));
+
+    producer.transform(|| 0).unwrap();
+    producer.transform(|| 1).unwrap();
+    assert!(producer.transform(|| 2).is_err());
+
+    let stats = producer.stage_stats();
+    assert_eq!((stats[0].busy, stats[0].not_available), (0, 1));
+    assert_eq!((stats[1].busy, stats[1].not_available), (0, 1));
+    assert_eq!((stats[2].busy, stats[2].not_available), (0, 1));
+}
+
+/// The attribute form of `InOutUnion` above.
+#[fastfifo::pipeline]
+pub enum Stages<Input, Output> {
+    /// Hands out the inputs, only ever used from one thread.
+    Producer(Input),
+    #[atomic]
+    Transformer(Output),
+    Consumer,
+}
+
+#[test]
+fn pipeline_attribute() {
+    let (producer, transformer, consumer) = StagesFifo::<usize, String>::new(2, 2).split();
+
+    let transformer = transformer.clone();
+
+    for i in 0..20 {
+        producer.transform(|| i).unwrap();
+        transformer.transform(|input| input.to_string()).unwrap();
+        consumer
+            .transform(|output| assert_eq!(output, i.to_string()))
+            .unwrap();
+    }
+
+    assert!(StagesTag::Transformer.is_atomic());
+    assert!(!StagesTag::Producer.is_atomic());
+}
+
+static STRANDED_INPUTS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+static STRANDED_OUTPUTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static UNINIT_ENTRIES: AtomicUsize = AtomicUsize::new(0);
+
+fn strand_input(input: usize) {
+    STRANDED_INPUTS.lock().unwrap().push(input);
+}
+
+fn strand_output(output: String) {
+    STRANDED_OUTPUTS.lock().unwrap().push(output);
+}
+
+fn count_uninit(_: *mut HookedUnion) {
+    UNINIT_ENTRIES.fetch_add(1, Ordering::Relaxed);
+}
+
+generate_union! {
+    pub HookedUnion {
+        on_uninit = count_uninit;
+        Producer: usize, atomic = false, on_drop = strand_input;
+        Transformer: String, atomic = false, on_drop = strand_output;
+        Consumer: (), atomic = false;
+    }
+}
+
+#[test]
+fn drop_hooks() {
+    const NUM_BLOCKS: usize = 2;
+    const BLOCK_SIZE: usize = 4;
+
+    {
+        let (producer, transformer, consumer) =
+            HookedUnionFifo::new(NUM_BLOCKS, BLOCK_SIZE).split();
+
+        for i in 0..5 {
+            producer.transform(|| i).unwrap();
+        }
+        for _ in 0..3 {
+            transformer.transform(|input| input.to_string()).unwrap();
+        }
+        consumer
+            .transform(|output| assert_eq!(output, "0"))
+            .unwrap();
+    }
+
+    assert_eq!(*STRANDED_INPUTS.lock().unwrap(), [3, 4]);
+    assert_eq!(*STRANDED_OUTPUTS.lock().unwrap(), ["1", "2"]);
+    // One consumed entry and three entries never produced into.
+    assert_eq!(
+        UNINIT_ENTRIES.load(Ordering::Relaxed),
+        NUM_BLOCKS * BLOCK_SIZE - 4
+    );
+}
+
+generate_union! {
+    /// `Parse` and `Format` each start a buffer of their own.
+    pub Buffered<Input, Output> {
+        Producer: Input, atomic = false;
+        Parse: String, atomic = false, buffer;
+        Format: Output, atomic = true, buffer;
+        Consumer: (), atomic = false;
+    }
+}
+
+#[test]
+fn buffered_pipeline() {
+    let (producer, parse, format, consumer) =
+        BufferedFifo::<usize, usize>::with_buffers([(2, 2), (2, 1), (2, 4)]).split();
+
+    for i in 0..3 {
+        producer.transform(|| i).unwrap();
+    }
+
+    // The second buffer only holds two items, the third one waits inside `parse`.
+    for _ in 0..3 {
+        parse.transform(|input| input.to_string()).unwrap();
+    }
+    assert_eq!(parse.waiting(), 1);
+    assert_eq!(
+        parse.transform(|input| input.to_string()),
+        Err(Error::NotAvailable)
+    );
+
+    format
+        .transform(|parsed| parsed.parse::<usize>().unwrap() * 2)
+        .unwrap();
+    producer.shutdown();
+
+    // `parse` places the waiting item before it finds nothing left and closes its buffer.
+    assert_eq!(
+        parse.transform(|input| input.to_string()),
+        Err(Error::Closed)
+    );
+    assert_eq!(parse.waiting(), 0);
+
+    let mut consumed = Vec::new();
+    loop {
+        match format.transform(|parsed| parsed.parse::<usize>().unwrap() * 2) {
+            Err(Error::Closed) => break,
+            result => result.unwrap(),
+        }
+    }
+    loop {
+        match consumer.transform(|output| consumed.push(output)) {
+            Err(Error::Closed) => break,
+            result => result.unwrap(),
+        }
+    }
+    assert_eq!(consumed, [0, 2, 4]);
+}
+
+#[test]
+fn buffered_pipeline_threads() {
+    const ITEMS: usize = 1000;
+
+    let (producer, parse, format, consumer) =
+        BufferedFifo::<usize, usize>::with_buffers([(4, 8), (2, 1), (8, 2)]).split();
+
+    let mut consumed = thread::scope(move |s| {
+        s.spawn(move || {
+            for i in 0..ITEMS {
+                while producer.transform(|| i).is_err() {
+                    thread::yield_now();
+                }
+            }
+            producer.shutdown();
+        });
+
+        s.spawn(move || {
+            while parse.transform(|input| input.to_string()) != Err(Error::Closed) {
+                thread::yield_now();
+            }
+        });
+
+        for format in [format.clone(), format] {
+            s.spawn(move || {
+                while format.transform(|parsed| parsed.parse::<usize>().unwrap() + 1)
+                    != Err(Error::Closed)
+                {
+                    thread::yield_now();
+                }
+            });
+        }
+
+        s.spawn(move || {
+            let mut consumed = Vec::with_capacity(ITEMS);
+            loop {
+                match consumer.transform(|output| consumed.push(output)) {
+                    Err(Error::Closed) => break consumed,
+                    Err(_) => thread::yield_now(),
+                    Ok(()) => {}
+                }
+            }
+        })
+        .join()
+        .unwrap()
+    });
+
+    // Two threads share `format`, so only the set of items is kept.
+    consumed.sort_unstable();
+    assert_eq!(consumed, (1..=ITEMS).collect::<Vec<_>>());
+}
+
+generate_union! {
+    /// Every `Observer` handle sees every input before `Transformer` gets it.
+    pub Observed<Input, Output> {
+        Producer: Input, atomic = false;
+        Observer: Input, atomic = false, broadcast = true;
+        Transformer: Output, atomic = false;
+        Consumer: (), atomic = false;
+    }
+}
+
+#[test]
+fn broadcast_waits_for_every_handle() {
+    let (producer, observers, transformer, consumer) =
+        ObservedFifo::<usize, usize>::with_handles(2, 2, 3).split();
+    assert_eq!(
+        observers.iter().map(|o| o.handle()).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+
+    for lap in 0..5 {
+        for i in 0..4 {
+            producer.transform(|| lap * 4 + i).unwrap();
+        }
+        assert!(producer.transform(|| 0).is_err());
+
+        for i in 0..4 {
+            for observer in &observers[..2] {
+                observer
+                    .transform(|input| assert_eq!(*input, lap * 4 + i))
+                    .unwrap();
+            }
+            // The last handle still has to see the item.
+            assert_eq!(transformer.get_entry().err(), Some(Error::NotAvailable));
+
+            observers[2]
+                .transform(|input| assert_eq!(*input, lap * 4 + i))
+                .unwrap();
+            transformer.transform(|input| input * 2).unwrap();
+            consumer
+                .transform(|output| assert_eq!(output, (lap * 4 + i) * 2))
+                .unwrap();
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "at least one handle")]
+fn broadcast_needs_a_handle() {
+    ObservedFifo::<usize, usize>::with_handles(2, 2, 0);
+}
+
+#[test]
+#[should_panic(expected = "handle 2 out of 2 handles")]
+fn handle_out_of_range() {
+    let fifo = ObservedFifo::<usize, usize>::with_handles(2, 2, 2);
+    let _ = fifo.get_handle_entry(ObservedTag::Observer, 2);
+}
+
+#[test]
+fn broadcast_handles_drain_and_close() {
+    let (producer, observers, transformer, consumer) =
+        ObservedFifo::<Arc<()>, Arc<()>>::with_handles(2, 2, 2).split();
+    let counter = Arc::new(());
+
+    for _ in 0..3 {
+        producer.transform(|| counter.clone()).unwrap();
+    }
+    observers[0].transform(|_| {}).unwrap();
+    producer.shutdown();
+
+    // The second handle only closes once it saw everything, the first one waits for it.
+    for _ in 0..3 {
+        observers[1].transform(|_| {}).unwrap();
+    }
+    observers[0].transform(|_| {}).unwrap();
+    observers[0].transform(|_| {}).unwrap();
+    for observer in &observers {
+        assert_eq!(observer.get_entry().err(), Some(Error::Closed));
+    }
+
+    transformer.transform(|input| input).unwrap();
+    consumer.transform(drop).unwrap();
+    drop((producer, observers, transformer, consumer));
+
+    assert_eq!(Arc::strong_count(&counter), 1);
+}
+
+#[test]
+fn broadcast_threads() {
+    const ITEMS: usize = 1000;
+
+    let (producer, observers, transformer, consumer) =
+        ObservedFifo::<usize, usize>::with_handles(4, 8, 3).split();
+
+    let consumed = thread::scope(move |s| {
+        s.spawn(move || {
+            for i in 0..ITEMS {
+                while producer.transform(|| i).is_err() {
+                    thread::yield_now();
+                }
+            }
+            producer.shutdown();
+        });
+
+        for observer in observers {
+            s.spawn(move || {
+                let mut seen = 0;
+                loop {
+                    match observer.transform(|input| assert_eq!(*input, seen)) {
+                        Err(Error::Closed) => break,
+                        Err(_) => thread::yield_now(),
+                        Ok(()) => seen += 1,
+                    }
+                }
+                assert_eq!(seen, ITEMS);
+            });
+        }
+
+        s.spawn(move || {
+            while transformer.transform(|input| input + 1) != Err(Error::Closed) {
+                thread::yield_now();
+            }
+        });
+
+        s.spawn(move || {
+            let mut consumed = Vec::with_capacity(ITEMS);
+            loop {
+                match consumer.transform(|output| consumed.push(output)) {
+                    Err(Error::Closed) => break consumed,
+                    Err(_) => thread::yield_now(),
+                    Ok(()) => {}
+                }
+            }
+        })
+        .join()
+        .unwrap()
+    });
+
+    assert_eq!(consumed, (1..=ITEMS).collect::<Vec<_>>());
+}
+
+// Loom cells cannot lend out references.
+#[cfg(not(loom))]
+#[test]
+fn slots_dump_what_is_left() {
+    let (producer, transformer, mut consumer) = InOutUnionFifo::<usize, usize>::new(2, 2).split();
+
+    for i in 0..3 {
+        producer.transform(|| i).unwrap();
+    }
+    transformer.transform(|input| input * 10).unwrap();
+    transformer.transform(|input| input * 10).unwrap();
+    consumer.transform(|output| assert_eq!(output, 0)).unwrap();
+
+    // The other stages still share the fifo.
+    assert!(consumer.slots().is_none());
+    drop((producer, transformer));
+
+    let slots = consumer.slots().unwrap();
+    let dump = slots
+        .iter()
+        .map(|slot| {
+            let value = match slot.tag {
+                InOutUnionTag::Producer => unsafe { *slot.value.producer },
+                InOutUnionTag::Transformer => unsafe { *slot.value.transformer },
+                InOutUnionTag::Consumer => unreachable!(),
+            };
+            (slot.block, slot.index, slot.tag, value)
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        dump,
+        [
+            (0, 1, InOutUnionTag::Transformer, 10),
+            (1, 0, InOutUnionTag::Producer, 2),
+        ]
+    );
+}